@@ -15,15 +15,21 @@
     You should have received a copy of the GNU Affero General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
+#[cfg(feature = "std")]
+use super::energymeter::SmaEmHeader;
 use super::{
     cursor::Cursor,
     energymeter::SmaEmMessage,
     inverter::{
-        SmaInvGetDayData, SmaInvHeader, SmaInvIdentify, SmaInvLogin,
-        SmaInvLogout,
+        command_name, SmaInvGetAllSpotValues, SmaInvGetDayData,
+        SmaInvGetDeviceInfo, SmaInvGetEvents, SmaInvGetMonthData,
+        SmaInvGetTemperature, SmaInvGetTotalEnergy, SmaInvHeader,
+        SmaInvIdentify, SmaInvLogin, SmaInvLogout, SUPPORTED_OPCODES,
     },
-    packet::SmaPacketHeader,
-    Error, Result, SmaSerde,
+    packet::{
+        DeserializeOptions, SmaPacketFooter, SmaPacketHeader, TransportInfo,
+    },
+    Error, Result, SmaEndpoint, SmaSerde,
 };
 use byteorder::BigEndian;
 #[cfg(not(feature = "std"))]
@@ -31,26 +37,439 @@ use core::{
     clone::Clone,
     cmp::{Eq, PartialEq},
     fmt::Debug,
+    option::Option::{self, None, Some},
     prelude::rust_2021::derive,
     result::Result::{Err, Ok},
 };
 
+/// Returns the inverter opcodes that can be parsed by [`AnySmaMessage`].
+pub fn supported_opcodes() -> &'static [u32] {
+    &SUPPORTED_OPCODES
+}
+
+/// Returns the name of the inverter message with the given opcode, e.g.
+/// `"GetDayData"`, or `None` if the opcode is not supported.
+pub fn opcode_name(opcode: u32) -> Option<&'static str> {
+    command_name(opcode)
+}
+
+/// Returns the speedwire protocol IDs that can be parsed by
+/// [`AnySmaMessage`].
+pub fn supported_protocols() -> &'static [u16] {
+    &[
+        SmaPacketHeader::SMA_PROTOCOL_EM,
+        SmaPacketHeader::SMA_PROTOCOL_INV,
+    ]
+}
+
 /// Container that can hold any supported SMA speedwire message.
 #[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum AnySmaMessage {
     EmMessage(SmaEmMessage),
+    InvGetAllSpotValues(SmaInvGetAllSpotValues),
     InvGetDayData(SmaInvGetDayData),
+    InvGetDeviceInfo(SmaInvGetDeviceInfo),
+    InvGetEvents(SmaInvGetEvents),
+    InvGetMonthData(SmaInvGetMonthData),
+    InvGetTemperature(SmaInvGetTemperature),
+    InvGetTotalEnergy(SmaInvGetTotalEnergy),
     InvIdentify(SmaInvIdentify),
     InvLogin(SmaInvLogin),
     InvLogout(SmaInvLogout),
 }
 
+impl AnySmaMessage {
+    /// Returns the source endpoint of the contained message.
+    pub fn src(&self) -> &SmaEndpoint {
+        match self {
+            Self::EmMessage(x) => &x.src,
+            Self::InvGetAllSpotValues(x) => &x.src,
+            Self::InvGetDayData(x) => &x.src,
+            Self::InvGetDeviceInfo(x) => &x.src,
+            Self::InvGetEvents(x) => &x.src,
+            Self::InvGetMonthData(x) => &x.src,
+            Self::InvGetTemperature(x) => &x.src,
+            Self::InvGetTotalEnergy(x) => &x.src,
+            Self::InvIdentify(x) => &x.src,
+            Self::InvLogin(x) => &x.src,
+            Self::InvLogout(x) => &x.src,
+        }
+    }
+
+    /// Returns the destination endpoint of the contained message.
+    /// Energymeter messages are broadcasts without a destination.
+    pub fn dst(&self) -> Option<&SmaEndpoint> {
+        match self {
+            Self::EmMessage(_) => None,
+            Self::InvGetAllSpotValues(x) => Some(&x.dst),
+            Self::InvGetDayData(x) => Some(&x.dst),
+            Self::InvGetDeviceInfo(x) => Some(&x.dst),
+            Self::InvGetEvents(x) => Some(&x.dst),
+            Self::InvGetMonthData(x) => Some(&x.dst),
+            Self::InvGetTemperature(x) => Some(&x.dst),
+            Self::InvGetTotalEnergy(x) => Some(&x.dst),
+            Self::InvIdentify(x) => Some(&x.dst),
+            Self::InvLogin(x) => Some(&x.dst),
+            Self::InvLogout(x) => Some(&x.dst),
+        }
+    }
+
+    /// Returns true if the contained message is a response from a device.
+    /// Deserialization stores the response bit of the command word,
+    /// see [`crate::inverter::SmaCmdWord::is_response`], in the `response`
+    /// field of the message, which is evaluated here.
+    /// Energymeter broadcasts and logout messages are never responses.
+    pub fn is_response(&self) -> bool {
+        match self {
+            Self::EmMessage(_) => false,
+            Self::InvGetAllSpotValues(x) => x.response,
+            Self::InvGetDayData(x) => x.response,
+            Self::InvGetDeviceInfo(x) => x.response,
+            Self::InvGetEvents(x) => x.response,
+            Self::InvGetMonthData(x) => x.response,
+            Self::InvGetTemperature(x) => x.response,
+            Self::InvGetTotalEnergy(x) => x.response,
+            Self::InvIdentify(x) => x.response,
+            Self::InvLogin(x) => x.response,
+            Self::InvLogout(_) => false,
+        }
+    }
+
+    /// Checks all invariants of the contained message which are required
+    /// for serialization without serializing it.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Self::EmMessage(x) => x.validate(),
+            Self::InvGetAllSpotValues(x) => x.validate(),
+            Self::InvGetDayData(x) => x.validate(),
+            Self::InvGetDeviceInfo(_) => Ok(()),
+            Self::InvGetEvents(x) => x.validate(),
+            Self::InvGetMonthData(x) => x.validate(),
+            Self::InvGetTemperature(_) => Ok(()),
+            Self::InvGetTotalEnergy(_) => Ok(()),
+            Self::InvIdentify(_) => Ok(()),
+            Self::InvLogin(x) => x.validate(),
+            Self::InvLogout(_) => Ok(()),
+        }
+    }
+
+    /// Returns a concise human readable single line summary of the message.
+    #[cfg(feature = "std")]
+    pub fn summary(&self) -> String {
+        match self {
+            Self::EmMessage(x) => format!(
+                "EM {} ts={} {} OBIS",
+                x.src,
+                x.timestamp_ms,
+                x.payload.len()
+            ),
+            Self::InvGetAllSpotValues(x) => format!(
+                "INV GetAllSpotValues src={} dst={} err={} {} records",
+                x.src,
+                x.dst,
+                x.error_code,
+                x.records.len()
+            ),
+            Self::InvGetDayData(x) => format!(
+                "INV GetDayData src={} dst={} err={} {} records",
+                x.src,
+                x.dst,
+                x.error_code,
+                x.records.len()
+            ),
+            Self::InvGetDeviceInfo(x) => format!(
+                "INV GetDeviceInfo src={} dst={} err={} version={:?}",
+                x.src, x.dst, x.error_code, x.firmware_version
+            ),
+            Self::InvGetEvents(x) => format!(
+                "INV GetEvents src={} dst={} err={} {} records",
+                x.src,
+                x.dst,
+                x.error_code,
+                x.records.len()
+            ),
+            Self::InvGetMonthData(x) => format!(
+                "INV GetMonthData src={} dst={} err={} {} records",
+                x.src,
+                x.dst,
+                x.error_code,
+                x.records.len()
+            ),
+            Self::InvGetTemperature(x) => format!(
+                "INV GetTemperature src={} dst={} err={} {:?} °C",
+                x.src,
+                x.dst,
+                x.error_code,
+                x.temperature_c()
+            ),
+            Self::InvGetTotalEnergy(x) => format!(
+                "INV GetTotalEnergy src={} dst={} err={} {:?} Wh",
+                x.src, x.dst, x.error_code, x.energy_wh
+            ),
+            Self::InvIdentify(x) => format!(
+                "INV Identify src={} dst={} err={}",
+                x.src, x.dst, x.error_code
+            ),
+            Self::InvLogin(x) => format!(
+                "INV Login src={} dst={} err={}",
+                x.src, x.dst, x.error_code
+            ),
+            Self::InvLogout(x) => format!(
+                "INV Logout src={} dst={} err={}",
+                x.src, x.dst, x.error_code
+            ),
+        }
+    }
+
+    /// Reads the version, group, protocol and data length from the common
+    /// packet header of a frame without decoding the message. The version
+    /// and group are not validated, which allows inspecting frames of other
+    /// vendors or protocol versions.
+    pub fn transport_info(buf: &[u8]) -> Result<TransportInfo> {
+        SmaPacketHeader::transport_info(buf)
+    }
+
+    /// Deserializes a slice which contains exactly one message.
+    pub fn from_bytes(buf: &[u8]) -> Result<Self> {
+        Self::deserialize(&mut Cursor::new(buf))
+    }
+
+    /// Serializes the message into space separated upper case hex bytes,
+    /// e.g. `53 4D 41 00`, for logging and bug reports.
+    #[cfg(feature = "std")]
+    pub fn to_hex(&self) -> Result<String> {
+        let bytes = self.to_vec()?;
+        let hex: Vec<_> = bytes.iter().map(|x| format!("{x:02X}")).collect();
+
+        Ok(hex.join(" "))
+    }
+
+    /// Deserializes a hex string which contains exactly one message, e.g.
+    /// as produced by [`Self::to_hex`]. Whitespace between the bytes is
+    /// ignored and digits are case insensitive.
+    #[cfg(feature = "std")]
+    pub fn from_hex(s: &str) -> Result<Self> {
+        let mut bytes = Vec::with_capacity(s.len() / 2);
+        let mut high = None;
+        for (pos, c) in s.char_indices() {
+            if c.is_ascii_whitespace() {
+                if high.is_some() {
+                    return Err(Error::InvalidHex { pos });
+                }
+                continue;
+            }
+            let digit = c.to_digit(16).ok_or(Error::InvalidHex { pos })? as u8;
+            match high.take() {
+                Some(high) => bytes.push(high << 4 | digit),
+                None => high = Some(digit),
+            }
+        }
+        if high.is_some() {
+            return Err(Error::InvalidHex { pos: s.len() });
+        }
+
+        Self::from_bytes(&bytes)
+    }
+
+    /// Deserializes the message at the start of the slice, which may be
+    /// followed by other data, and returns it with its consumed length.
+    /// The message must be terminated by a regular 4 byte footer unless it
+    /// is the last one in the slice.
+    pub fn from_bytes_prefix(buf: &[u8]) -> Result<(Self, usize)> {
+        let cursor = Cursor::new(buf);
+        cursor.check_remaining(SmaPacketHeader::LENGTH)?;
+        let data_len =
+            (cursor.peek_u16::<BigEndian>(12) as usize).saturating_sub(2);
+        let len = buf
+            .len()
+            .min(SmaPacketHeader::LENGTH + data_len + SmaPacketFooter::LENGTH);
+
+        let mut cursor = Cursor::new(&buf[..len]);
+        let message = Self::deserialize(&mut cursor)?;
+
+        Ok((message, cursor.position()))
+    }
+
+    /// Checks if the buffer starts with a complete frame, e.g. before parsing
+    /// data accumulated from a stream. Returns `Ok(false)` if more bytes are
+    /// required and an error if the header is invalid. A frame is complete
+    /// once the payload and a short 2 byte footer were received, a normal
+    /// footer ends 2 bytes later. Trailing bytes after the frame are allowed.
+    pub fn is_complete_frame(buf: &[u8]) -> Result<bool> {
+        let fourcc = SmaPacketHeader::SMA_FOURCC.to_be_bytes();
+        let len = buf.len().min(fourcc.len());
+        if !fourcc.starts_with(&buf[..len]) {
+            let mut raw = [0; 4];
+            raw[..len].copy_from_slice(&buf[..len]);
+            return Err(Error::InvalidFourCC {
+                fourcc: u32::from_be_bytes(raw),
+            });
+        }
+        if buf.len() < SmaPacketHeader::LENGTH {
+            return Ok(false);
+        }
+
+        let header = SmaPacketHeader::deserialize(&mut Cursor::new(buf))?;
+
+        Ok(buf.len()
+            >= SmaPacketHeader::LENGTH
+                + header.data_len
+                + SmaPacketFooter::LENGTH_SHORT)
+    }
+
+    /// Returns labeled byte ranges of all fields within the given frame,
+    /// e.g. for displaying field boundaries in a hex viewer.
+    /// Variable length record lists are labeled as a single range.
+    /// Returns an empty list if the frame cannot be parsed.
+    #[cfg(feature = "std")]
+    pub fn annotate(buf: &[u8]) -> Vec<(std::ops::Range<usize>, &'static str)> {
+        Self::annotate_with(buf, &DeserializeOptions::default())
+    }
+
+    /// Returns labeled byte ranges like [`Self::annotate`] but parses the
+    /// frame with the given options, e.g. to label frames without footer.
+    #[cfg(feature = "std")]
+    pub fn annotate_with(
+        buf: &[u8],
+        options: &DeserializeOptions,
+    ) -> Vec<(std::ops::Range<usize>, &'static str)> {
+        let mut cursor = Cursor::new(buf);
+        let Ok(message) = Self::deserialize_with(&mut cursor, options) else {
+            return Vec::new();
+        };
+        let Ok(info) = SmaPacketHeader::transport_info(buf) else {
+            return Vec::new();
+        };
+        let end = cursor.position();
+        let header_end = SmaPacketHeader::LENGTH;
+        let payload_end = (header_end + info.data_len).min(end);
+
+        let mut ranges = vec![
+            (0..4, "fourcc"),
+            (4..6, "header length"),
+            (6..8, "start tag"),
+            (8..12, "group"),
+            (12..14, "data length"),
+            (14..16, "version"),
+            (16..header_end, "protocol"),
+        ];
+
+        let payload_start = if let Self::EmMessage(_) = message {
+            let em_header_end = header_end + SmaEmHeader::LENGTH;
+            ranges.extend_from_slice(&[
+                (header_end..header_end + 6, "source"),
+                (header_end + 6..em_header_end, "timestamp"),
+            ]);
+            em_header_end
+        } else {
+            let fields = [
+                (0, 1, "word count"),
+                (1, 2, "class"),
+                (2, 8, "destination"),
+                (8, 10, "destination control"),
+                (10, 16, "source"),
+                (16, 18, "source control"),
+                (18, 20, "error code"),
+                (20, 24, "counters"),
+                (24, 25, "channel"),
+                (25, SmaInvHeader::LENGTH, "opcode"),
+            ];
+            for (start, end, label) in fields {
+                ranges.push((header_end + start..header_end + end, label));
+            }
+            header_end + SmaInvHeader::LENGTH
+        };
+
+        let (fields, tail): (&[_], _) = match message {
+            Self::EmMessage(_) => (&[], "obis values"),
+            Self::InvGetAllSpotValues(_)
+            | Self::InvGetDeviceInfo(_)
+            | Self::InvGetTemperature(_)
+            | Self::InvGetTotalEnergy(_) => {
+                (&[(0, 4, "first index"), (4, 8, "last index")], "records")
+            }
+            Self::InvGetDayData(_)
+            | Self::InvGetEvents(_)
+            | Self::InvGetMonthData(_) => {
+                (&[(0, 4, "start time"), (4, 8, "end time")], "records")
+            }
+            Self::InvIdentify(_) => (&[], "identity"),
+            Self::InvLogin(_) => (
+                &[
+                    (0, 4, "user group"),
+                    (4, 8, "timeout"),
+                    (8, 12, "timestamp"),
+                    (12, 16, "status"),
+                ],
+                "password",
+            ),
+            Self::InvLogout(_) => (&[], "padding"),
+        };
+
+        let mut pos = payload_start;
+        for &(start, end, label) in fields {
+            pos = payload_start + end;
+            ranges.push((payload_start + start..pos, label));
+        }
+        if pos < payload_end {
+            ranges.push((pos..payload_end, tail));
+        }
+        if payload_end < end {
+            ranges.push((payload_end..end, "footer"));
+        }
+
+        ranges
+    }
+
+    /// Deserializes a single message like [`SmaSerde::deserialize`] and
+    /// additionally returns the footer that terminated the packet.
+    /// The total length of the frame is the cursor position after the call.
+    pub fn deserialize_with_footer(
+        buffer: &mut Cursor<&[u8]>,
+    ) -> Result<(Self, SmaPacketFooter)> {
+        buffer.check_remaining(SmaPacketHeader::LENGTH)?;
+        let footer_start = buffer.position()
+            + SmaPacketHeader::LENGTH
+            + buffer.peek_u16::<BigEndian>(12) as usize
+            - 2;
+
+        let message = Self::deserialize(buffer)?;
+        let footer = SmaPacketFooter {
+            padding_len: buffer.position() - footer_start,
+        };
+
+        Ok((message, footer))
+    }
+}
+
 impl SmaSerde for AnySmaMessage {
+    fn serialized_len(&self) -> usize {
+        match self {
+            Self::EmMessage(x) => x.serialized_len(),
+            Self::InvGetAllSpotValues(x) => x.serialized_len(),
+            Self::InvGetDayData(x) => x.serialized_len(),
+            Self::InvGetDeviceInfo(x) => x.serialized_len(),
+            Self::InvGetEvents(x) => x.serialized_len(),
+            Self::InvGetMonthData(x) => x.serialized_len(),
+            Self::InvGetTemperature(x) => x.serialized_len(),
+            Self::InvGetTotalEnergy(x) => x.serialized_len(),
+            Self::InvIdentify(x) => x.serialized_len(),
+            Self::InvLogin(x) => x.serialized_len(),
+            Self::InvLogout(x) => x.serialized_len(),
+        }
+    }
+
     fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
         match self {
             Self::EmMessage(x) => x.serialize(buffer),
+            Self::InvGetAllSpotValues(x) => x.serialize(buffer),
             Self::InvGetDayData(x) => x.serialize(buffer),
+            Self::InvGetDeviceInfo(x) => x.serialize(buffer),
+            Self::InvGetEvents(x) => x.serialize(buffer),
+            Self::InvGetMonthData(x) => x.serialize(buffer),
+            Self::InvGetTemperature(x) => x.serialize(buffer),
+            Self::InvGetTotalEnergy(x) => x.serialize(buffer),
             Self::InvIdentify(x) => x.serialize(buffer),
             Self::InvLogin(x) => x.serialize(buffer),
             Self::InvLogout(x) => x.serialize(buffer),
@@ -58,6 +477,13 @@ impl SmaSerde for AnySmaMessage {
     }
 
     fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
+
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
         buffer.check_remaining(SmaPacketHeader::LENGTH)?;
 
         let fourcc = buffer.peek_u32::<BigEndian>(0);
@@ -67,27 +493,53 @@ impl SmaSerde for AnySmaMessage {
 
         let protocol = buffer.peek_u16::<BigEndian>(16);
         let message = match protocol {
-            SmaPacketHeader::SMA_PROTOCOL_EM => {
-                Self::EmMessage(SmaEmMessage::deserialize(buffer)?)
-            }
+            SmaPacketHeader::SMA_PROTOCOL_EM => Self::EmMessage(
+                SmaEmMessage::deserialize_with(buffer, options)?,
+            ),
             SmaPacketHeader::SMA_PROTOCOL_INV => {
                 buffer.check_remaining(
                     SmaPacketHeader::LENGTH + SmaInvHeader::LENGTH,
                 )?;
                 let opcode = buffer.peek_u24::<BigEndian>(43);
                 match opcode {
+                    SmaInvGetAllSpotValues::OPCODE => {
+                        Self::InvGetAllSpotValues(
+                            SmaInvGetAllSpotValues::deserialize_with(
+                                buffer, options,
+                            )?,
+                        )
+                    }
                     SmaInvGetDayData::OPCODE => Self::InvGetDayData(
-                        SmaInvGetDayData::deserialize(buffer)?,
+                        SmaInvGetDayData::deserialize_with(buffer, options)?,
+                    ),
+                    SmaInvGetDeviceInfo::OPCODE => Self::InvGetDeviceInfo(
+                        SmaInvGetDeviceInfo::deserialize_with(buffer, options)?,
+                    ),
+                    SmaInvGetEvents::OPCODE => Self::InvGetEvents(
+                        SmaInvGetEvents::deserialize_with(buffer, options)?,
+                    ),
+                    SmaInvGetMonthData::OPCODE => Self::InvGetMonthData(
+                        SmaInvGetMonthData::deserialize_with(buffer, options)?,
+                    ),
+                    SmaInvGetTemperature::OPCODE => Self::InvGetTemperature(
+                        SmaInvGetTemperature::deserialize_with(
+                            buffer, options,
+                        )?,
+                    ),
+                    SmaInvGetTotalEnergy::OPCODE => Self::InvGetTotalEnergy(
+                        SmaInvGetTotalEnergy::deserialize_with(
+                            buffer, options,
+                        )?,
+                    ),
+                    SmaInvIdentify::OPCODE => Self::InvIdentify(
+                        SmaInvIdentify::deserialize_with(buffer, options)?,
+                    ),
+                    SmaInvLogin::OPCODE => Self::InvLogin(
+                        SmaInvLogin::deserialize_with(buffer, options)?,
+                    ),
+                    SmaInvLogout::OPCODE => Self::InvLogout(
+                        SmaInvLogout::deserialize_with(buffer, options)?,
                     ),
-                    SmaInvIdentify::OPCODE => {
-                        Self::InvIdentify(SmaInvIdentify::deserialize(buffer)?)
-                    }
-                    SmaInvLogin::OPCODE => {
-                        Self::InvLogin(SmaInvLogin::deserialize(buffer)?)
-                    }
-                    SmaInvLogout::OPCODE => {
-                        Self::InvLogout(SmaInvLogout::deserialize(buffer)?)
-                    }
                     opcode => return Err(Error::UnsupportedOpcode { opcode }),
                 }
             }
@@ -101,9 +553,7 @@ impl SmaSerde for AnySmaMessage {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{
-        energymeter::ObisValue, inverter::SmaInvCounter, packet::SmaEndpoint,
-    };
+    use crate::{energymeter::ObisValue, inverter::SmaInvCounter};
     #[cfg(not(feature = "std"))]
     use heapless::Vec;
 
@@ -148,6 +598,109 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_any_transport_info() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x10,
+            0x60, 0x69,
+            0xDE, 0xAD,
+            0x11, 0x22, 0x33, 0x44,
+            0xAA, 0xBB, 0xCC, 0xDD,
+            0x00, 0x01, 0x04, 0x00, 0x01, 0x02, 0x03, 0x04,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        match AnySmaMessage::transport_info(&serialized) {
+            Err(e) => panic!("Reading transport info failed: {e:?}"),
+            Ok(info) => assert_eq!(
+                TransportInfo {
+                    version: 0x10,
+                    group: 1,
+                    protocol: SmaPacketHeader::SMA_PROTOCOL_EM,
+                    data_len: 18,
+                },
+                info
+            ),
+        }
+
+        let mut foreign = serialized;
+        foreign[11] = 0x02;
+        foreign[15] = 0x20;
+        match AnySmaMessage::transport_info(&foreign) {
+            Err(e) => panic!("Reading transport info failed: {e:?}"),
+            Ok(info) => {
+                assert_eq!(2, info.group);
+                assert_eq!(0x20, info.version);
+            }
+        }
+
+        match AnySmaMessage::transport_info(&serialized[..10]) {
+            Err(Error::BufferTooSmall { .. }) => (),
+            x => panic!("Read transport info of truncated frame: {x:?}"),
+        }
+
+        let mut invalid = serialized;
+        invalid[13] = 0x00;
+        match AnySmaMessage::transport_info(&invalid) {
+            Err(Error::InvalidDataLen {
+                len: 0,
+                expected: 2,
+            }) => (),
+            x => panic!("Read transport info of invalid length: {x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_any_message_from_bytes() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x10,
+            0x60, 0x69,
+            0xDE, 0xAD,
+            0x11, 0x22, 0x33, 0x44,
+            0xAA, 0xBB, 0xCC, 0xDD,
+            0x00, 0x01, 0x04, 0x00, 0x01, 0x02, 0x03, 0x04,
+            0x00, 0x00, 0x00, 0x00,
+            0x53, 0x4D, 0x41, 0x00,
+        ];
+
+        let expected = AnySmaMessage::EmMessage(SmaEmMessage {
+            src: SmaEndpoint {
+                susy_id: 0xDEAD,
+                serial: 0x11223344,
+            },
+            timestamp_ms: 0xAABBCCDD,
+            payload: {
+                let mut message = Vec::default();
+                #[allow(clippy::let_unit_value)]
+                let _ = message.push(ObisValue {
+                    id: 0x010400,
+                    value: 0x01020304,
+                });
+                message
+            },
+        });
+
+        match AnySmaMessage::from_bytes(&serialized[..40]) {
+            Err(e) => panic!("AnySmaMessage deserialization failed: {e:?}"),
+            Ok(message) => assert_eq!(expected, message),
+        }
+        match AnySmaMessage::from_bytes_prefix(&serialized) {
+            Err(e) => panic!("AnySmaMessage deserialization failed: {e:?}"),
+            Ok((message, len)) => {
+                assert_eq!(expected, message);
+                assert_eq!(40, len);
+            }
+        }
+        match AnySmaMessage::from_bytes(&serialized) {
+            Err(Error::InvalidPadding { .. }) => (),
+            x => panic!("Deserialized message with trailing data as {x:?}"),
+        }
+    }
+
     #[test]
     fn test_any_inv_login_response_deserialization() {
         #[rustfmt::skip]
@@ -177,6 +730,7 @@ mod tests {
             },
             timestamp: 1700000000,
             password: None,
+            response: true,
             ..Default::default()
         });
 
@@ -190,6 +744,82 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_any_inv_login_direction() {
+        #[rustfmt::skip]
+        let request = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x3A, 0x00, 0x10,
+            0x60, 0x65,
+            0x0E, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x01,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x80,
+            0x0C, 0x04, 0xFD, 0xFF,
+            0x07, 0x00, 0x00, 0x00, 0x84, 0x03, 0x00, 0x00,
+            0x00, 0xF1, 0x53, 0x65, 0x00, 0x00, 0x00, 0x00,
+            0xB9, 0xBA, 0xBB, 0xBC, 0xBD, 0x88, 0x88, 0x88,
+            0x88, 0x88, 0x88, 0x88,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        #[rustfmt::skip]
+        let response = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x2E, 0x00, 0x10,
+            0x60, 0x65,
+            0x0B, 0xE0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x80,
+            0x0D, 0x04, 0xFD, 0xFF,
+            0x07, 0x00, 0x00, 0x00, 0x84, 0x03, 0x00, 0x00,
+            0x00, 0xF1, 0x53, 0x65, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        match AnySmaMessage::from_bytes(&request) {
+            Err(e) => panic!("AnySmaMessage deserialization failed: {e:?}"),
+            Ok(message) => assert!(!message.is_response()),
+        }
+        match AnySmaMessage::from_bytes(&response) {
+            Err(e) => panic!("AnySmaMessage deserialization failed: {e:?}"),
+            Ok(message) => assert!(message.is_response()),
+        }
+    }
+
+    #[test]
+    fn test_any_inv_empty_day_data_response_direction() {
+        #[rustfmt::skip]
+        let response = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x26, 0x00, 0x10,
+            0x60, 0x65,
+            0x09, 0xE0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x08, 0x80,
+            0x01, 0x02, 0x00, 0x70,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        match AnySmaMessage::from_bytes(&response) {
+            Err(e) => panic!("AnySmaMessage deserialization failed: {e:?}"),
+            Ok(message) => {
+                assert!(message.is_response());
+                match &message {
+                    AnySmaMessage::InvGetDayData(x) => {
+                        assert!(x.records.is_empty())
+                    }
+                    x => panic!("Unexpected message {x:?}"),
+                }
+            }
+        }
+        if let Err(e) = AnySmaMessage::verify_roundtrip(&response) {
+            panic!("Empty response roundtrip failed: {e:?}");
+        }
+    }
+
     #[test]
     fn test_any_inv_logout_serialization() {
         let cmd = AnySmaMessage::InvLogout(SmaInvLogout {
@@ -226,6 +856,7 @@ mod tests {
             0x00, 0x00, 0x00, 0x00,
         ];
         assert_eq!(SmaInvLogout::LENGTH, cursor.position());
+        assert_eq!(cmd.serialized_len(), cursor.position());
         assert_eq!(expected, buffer);
     }
 
@@ -259,6 +890,9 @@ mod tests {
                 packet_id: 3,
                 ..Default::default()
             },
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: false,
             start_time_idx: 1700000000,
             end_time_idx: 1750000000,
             records: Vec::new(),
@@ -271,4 +905,387 @@ mod tests {
             panic!("Serialized message into too small buffer {x:?}");
         }
     }
+
+    #[test]
+    fn test_any_message_footer_deserialization() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x10,
+            0x60, 0x69,
+            0xDE, 0xAD,
+            0x11, 0x22, 0x33, 0x44,
+            0xAA, 0xBB, 0xCC, 0xDD,
+            0x00, 0x01, 0x04, 0x00, 0x01, 0x02, 0x03, 0x04,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match AnySmaMessage::deserialize_with_footer(&mut cursor) {
+            Err(e) => panic!("AnySmaMessage deserialization failed: {e:?}"),
+            Ok((_, footer)) => {
+                assert_eq!(SmaPacketFooter::LENGTH, footer.padding_len);
+                assert_eq!(40, cursor.position());
+            }
+        }
+
+        let mut cursor = Cursor::new(&serialized[..38]);
+        match AnySmaMessage::deserialize_with_footer(&mut cursor) {
+            Err(e) => panic!("AnySmaMessage deserialization failed: {e:?}"),
+            Ok((_, footer)) => {
+                assert_eq!(SmaPacketFooter::LENGTH_SHORT, footer.padding_len);
+                assert!(footer.is_short());
+                assert_eq!(38, cursor.position());
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_any_message_validate_payload_len() {
+        let message = AnySmaMessage::EmMessage(SmaEmMessage {
+            src: SmaEndpoint::dummy(),
+            timestamp_ms: 0,
+            payload: vec![
+                ObisValue {
+                    id: 0x010400,
+                    value: 0,
+                };
+                SmaEmMessage::MAX_RECORD_COUNT + 1
+            ],
+        });
+
+        match message.validate() {
+            Err(Error::PayloadTooLarge { len: 81 }) => (),
+            x => panic!("Validated over-cap payload as {x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_any_message_validate_obis_id() {
+        let mut message = SmaEmMessage {
+            src: SmaEndpoint::dummy(),
+            timestamp_ms: 0,
+            payload: Vec::new(),
+        };
+        #[allow(clippy::let_unit_value)]
+        let _ = message.payload.push(ObisValue {
+            id: 0x010400,
+            value: 0,
+        });
+        let mut message = AnySmaMessage::EmMessage(message);
+        if let Err(e) = message.validate() {
+            panic!("Valid message failed validation: {e:?}");
+        }
+
+        if let AnySmaMessage::EmMessage(x) = &mut message {
+            #[allow(clippy::let_unit_value)]
+            let _ = x.payload.push(ObisValue {
+                id: 0x010200,
+                value: 0,
+            });
+        }
+        match message.validate() {
+            Err(Error::UnsupportedObisId { id: 0x010200 }) => (),
+            x => panic!("Validated invalid OBIS ID as {x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_any_message_endpoints() {
+        let em_src = SmaEndpoint {
+            susy_id: 0x015d,
+            serial: 1901439139,
+        };
+        let message = AnySmaMessage::EmMessage(SmaEmMessage {
+            src: em_src.clone(),
+            ..Default::default()
+        });
+        assert_eq!(&em_src, message.src());
+        assert_eq!(None, message.dst());
+
+        let inv_dst = SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
+        };
+        let message = AnySmaMessage::InvLogin(SmaInvLogin {
+            dst: inv_dst.clone(),
+            src: SmaEndpoint::dummy(),
+            ..Default::default()
+        });
+        assert_eq!(&SmaEndpoint::dummy(), message.src());
+        assert_eq!(Some(&inv_dst), message.dst());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_any_message_summary() {
+        let message = AnySmaMessage::EmMessage(SmaEmMessage {
+            src: SmaEndpoint {
+                susy_id: 0x015d,
+                serial: 1901439139,
+            },
+            timestamp_ms: 1000,
+            payload: vec![
+                ObisValue {
+                    id: 0x010400,
+                    value: 0x01020304,
+                },
+                ObisValue {
+                    id: 0x010800,
+                    value: 0x1020304050607080,
+                },
+            ],
+        });
+        assert_eq!("EM 349:1901439139 ts=1000 2 OBIS", message.summary());
+
+        let message = AnySmaMessage::InvLogin(SmaInvLogin {
+            dst: SmaEndpoint::dummy(),
+            src: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            timestamp: 1700000000,
+            ..Default::default()
+        });
+        assert_eq!(
+            "INV Login src=22136:2882382798 dst=57005:3735928559 err=0",
+            message.summary()
+        );
+    }
+
+    #[test]
+    fn test_any_em_message_version_deserialization() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x0F,
+            0x60, 0x69,
+            0xDE, 0xAD,
+            0x11, 0x22, 0x33, 0x44,
+            0xAA, 0xBB, 0xCC, 0xDD,
+            0x00, 0x01, 0x04, 0x00, 0x01, 0x02, 0x03, 0x04,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match AnySmaMessage::deserialize(&mut cursor) {
+            Err(Error::UnsupportedVersion { version: 0x0F }) => (),
+            x => panic!("Deserialized unsupported version as {x:?}"),
+        }
+
+        let options = DeserializeOptions {
+            versions: &[0x0F],
+            ..Default::default()
+        };
+        let mut cursor = Cursor::new(&serialized[..]);
+        match AnySmaMessage::deserialize_with(&mut cursor, &options) {
+            Err(e) => panic!("AnySmaMessage deserialization failed: {e:?}"),
+            Ok(message) => {
+                assert_eq!(0x11223344, message.src().serial);
+                assert_eq!(40, cursor.position());
+            }
+        }
+    }
+
+    #[test]
+    fn test_any_is_complete_frame() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x10,
+            0x60, 0x69,
+            0xDE, 0xAD,
+            0x11, 0x22, 0x33, 0x44,
+            0xAA, 0xBB, 0xCC, 0xDD,
+            0x00, 0x01, 0x04, 0x00, 0x01, 0x02, 0x03, 0x04,
+            0x00, 0x00, 0x00, 0x00,
+            0x53, 0x4D,
+        ];
+
+        for len in [0, 3, 17, 18, 37] {
+            match AnySmaMessage::is_complete_frame(&serialized[..len]) {
+                Ok(false) => (),
+                x => panic!("Partial frame of {len} bytes returned {x:?}"),
+            }
+        }
+        for len in [38, 40, 42] {
+            match AnySmaMessage::is_complete_frame(&serialized[..len]) {
+                Ok(true) => (),
+                x => panic!("Frame of {len} bytes returned {x:?}"),
+            }
+        }
+
+        match AnySmaMessage::is_complete_frame(&[0x53, 0x4D, 0x42]) {
+            Err(Error::InvalidFourCC { fourcc: 0x534D4200 }) => (),
+            x => panic!("Invalid FourCC returned {x:?}"),
+        }
+        let mut invalid = serialized;
+        invalid[6] = 0x00;
+        match AnySmaMessage::is_complete_frame(&invalid) {
+            Err(Error::InvalidStartTag { tag: 0x00A0 }) => (),
+            x => panic!("Invalid start tag returned {x:?}"),
+        }
+        let mut invalid = serialized;
+        invalid[13] = 0x01;
+        match AnySmaMessage::is_complete_frame(&invalid) {
+            Err(Error::InvalidDataLen {
+                len: 1,
+                expected: 2,
+            }) => (),
+            x => panic!("Invalid data length returned {x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_supported_opcodes_and_protocols() {
+        let opcodes = supported_opcodes();
+        for opcode in [
+            SmaInvIdentify::OPCODE,
+            SmaInvLogin::OPCODE,
+            SmaInvLogout::OPCODE,
+            SmaInvGetDayData::OPCODE,
+        ] {
+            assert!(opcodes.contains(&opcode), "Missing opcode {opcode:X}");
+        }
+
+        let protocols = supported_protocols();
+        assert!(protocols.contains(&SmaPacketHeader::SMA_PROTOCOL_EM));
+        assert!(protocols.contains(&SmaPacketHeader::SMA_PROTOCOL_INV));
+    }
+
+    #[test]
+    fn test_opcode_name() {
+        for (opcode, name) in [
+            (SmaInvGetAllSpotValues::OPCODE, "GetAllSpotValues"),
+            (SmaInvGetDayData::OPCODE, "GetDayData"),
+            (SmaInvGetDeviceInfo::OPCODE, "GetDeviceInfo"),
+            (SmaInvGetEvents::OPCODE, "GetEvents"),
+            (SmaInvGetMonthData::OPCODE, "GetMonthData"),
+            (SmaInvGetTemperature::OPCODE, "GetTemperature"),
+            (SmaInvGetTotalEnergy::OPCODE, "GetTotalEnergy"),
+            (SmaInvIdentify::OPCODE, "Identify"),
+            (SmaInvLogin::OPCODE, "Login"),
+            (SmaInvLogout::OPCODE, "Logout"),
+        ] {
+            assert_eq!(Some(name), opcode_name(opcode));
+        }
+        for opcode in supported_opcodes() {
+            assert!(opcode_name(*opcode).is_some(), "Unnamed {opcode:X}");
+        }
+        assert_eq!(None, opcode_name(0x123456));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_any_annotate_login() {
+        let password = match SmaInvLogin::pw_from_str("12345") {
+            Ok(x) => x,
+            Err(e) => panic!("Encoding password failed: {e:?}"),
+        };
+        let message = SmaInvLogin {
+            dst: SmaEndpoint::broadcast(),
+            src: SmaEndpoint::dummy(),
+            password: Some(password),
+            ..Default::default()
+        };
+        let buffer = match message.to_vec() {
+            Err(e) => panic!("SmaInvLogin serialization failed: {e:?}"),
+            Ok(x) => x,
+        };
+
+        let ranges = AnySmaMessage::annotate(&buffer);
+        for expected in [
+            (0..4, "fourcc"),
+            (16..18, "protocol"),
+            (20..26, "destination"),
+            (28..34, "source"),
+            (38..42, "counters"),
+            (43..46, "opcode"),
+            (62..74, "password"),
+            (74..78, "footer"),
+        ] {
+            assert!(ranges.contains(&expected), "Missing {expected:?}");
+        }
+
+        // Ranges are contiguous and cover the whole frame.
+        let mut pos = 0;
+        for (range, label) in &ranges {
+            assert_eq!(pos, range.start, "Gap before {label}");
+            pos = range.end;
+        }
+        assert_eq!(buffer.len(), pos);
+
+        assert!(AnySmaMessage::annotate(&buffer[..40]).is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_any_annotate_without_footer() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x10,
+            0x60, 0x69,
+            0xDE, 0xAD,
+            0x11, 0x22, 0x33, 0x44,
+            0xAA, 0xBB, 0xCC, 0xDD,
+            0x00, 0x01, 0x04, 0x00, 0x01, 0x02, 0x03, 0x04,
+        ];
+        let options = DeserializeOptions {
+            lenient_footer: true,
+            ..Default::default()
+        };
+
+        assert!(AnySmaMessage::annotate(&serialized).is_empty());
+        let ranges = AnySmaMessage::annotate_with(&serialized, &options);
+        assert_eq!(
+            &[
+                (18..24, "source"),
+                (24..28, "timestamp"),
+                (28..36, "obis values"),
+            ],
+            &ranges[7..]
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_any_hex_roundtrip() {
+        let message = AnySmaMessage::InvLogout(SmaInvLogout {
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            src: SmaEndpoint::dummy(),
+            counters: SmaInvCounter {
+                packet_id: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let hex = match message.to_hex() {
+            Ok(x) => x,
+            Err(e) => panic!("Hex serialization failed: {e:?}"),
+        };
+        assert!(hex.starts_with("53 4D 41 00 00 04 02 A0"));
+        match AnySmaMessage::from_hex(&hex) {
+            Ok(x) => assert_eq!(message, x),
+            Err(e) => panic!("Hex deserialization failed: {e:?}"),
+        }
+        match AnySmaMessage::from_hex(&hex.to_lowercase().replace(' ', "\n")) {
+            Ok(x) => assert_eq!(message, x),
+            Err(e) => panic!("Hex deserialization failed: {e:?}"),
+        }
+
+        match AnySmaMessage::from_hex("53 4D 4X") {
+            Err(Error::InvalidHex { pos: 7 }) => (),
+            x => panic!("Expected invalid hex, got {x:?}"),
+        }
+        match AnySmaMessage::from_hex("53 4D 4 1") {
+            Err(Error::InvalidHex { pos: 7 }) => (),
+            x => panic!("Expected invalid hex, got {x:?}"),
+        }
+    }
 }
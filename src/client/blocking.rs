@@ -0,0 +1,329 @@
+/******************************************************************************\
+    sma-proto - A SMA Speedwire protocol library
+    Copyright (C) 2024 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+
+//! Blocking SMA speedwire client on top of std sockets.
+
+use super::{
+    AnySmaMessage, ClientError, Cursor, FragmentedResponse, Fragments,
+    LoginInfo, SmaClient, SmaEndpoint, SmaSerde, SystemTimeSource, TimeSource,
+};
+use crate::{
+    inverter::{SmaInvGetDayData, SmaInvMeterValue},
+    packet::{MAX_FRAME_LEN, SMA_PORT},
+};
+
+use std::{
+    io::ErrorKind,
+    net::{Ipv4Addr, SocketAddrV4, UdpSocket},
+    time::{Duration, Instant},
+};
+
+/// Blocking SMA client for applications without an async runtime.
+///
+/// It combines the network independent [`SmaClient`] state with a unicast
+/// std [`UdpSocket`] to a single device and mirrors the basic request
+/// methods of [`SmaClient`]. Each request waits at most the configured
+/// timeout for its response, see [`Self::set_timeout`].
+#[derive(Debug)]
+pub struct SmaBlockingClient<C: TimeSource = SystemTimeSource> {
+    /// Network independent communication state.
+    client: SmaClient<C>,
+    /// Unicast socket.
+    socket: UdpSocket,
+    /// Destination socket address.
+    dst_sockaddr: SocketAddrV4,
+    /// Maximum time to wait for a response.
+    timeout: Duration,
+}
+
+impl SmaBlockingClient {
+    /// Default time to wait for a response.
+    pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Creates a new blocking client with the given SmaEndpoint as source
+    /// ID which communicates with the device at `remote_addr`.
+    pub fn open_unicast(
+        endpoint: SmaEndpoint,
+        remote_addr: Ipv4Addr,
+    ) -> Result<Self, ClientError> {
+        Self::with_client(SmaClient::new(endpoint), remote_addr)
+    }
+}
+
+impl<C: TimeSource> SmaBlockingClient<C> {
+    /// Creates a new blocking client from an existing [`SmaClient`] which
+    /// communicates with the device at `remote_addr`.
+    pub fn with_client(
+        client: SmaClient<C>,
+        remote_addr: Ipv4Addr,
+    ) -> Result<Self, ClientError> {
+        let socket =
+            UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 0))?;
+
+        Ok(Self {
+            client,
+            socket,
+            dst_sockaddr: SocketAddrV4::new(remote_addr, SMA_PORT),
+            timeout: SmaBlockingClient::TIMEOUT,
+        })
+    }
+
+    /// Sets the maximum time to wait for a response,
+    /// see [`SmaBlockingClient::TIMEOUT`]. Fragments of a
+    /// [`Self::get_day_data`] response use the fragment timeout of the
+    /// underlying client instead.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the underlying [`SmaClient`], e.g. to change its settings.
+    pub fn client(&mut self) -> &mut SmaClient<C> {
+        &mut self.client
+    }
+
+    /// Sends an identity request to the SMA device.
+    /// Returns the [`SmaEndpoint`] at the clients target IPv4 address.
+    pub fn identify(&mut self) -> Result<SmaEndpoint, ClientError> {
+        let req = self.client.identify_request(None);
+        self.write(req)?;
+        let resp = self.read(self.timeout, |msg| match msg {
+            AnySmaMessage::InvIdentify(resp)
+                if self.client.is_response(
+                    &resp.src,
+                    &resp.dst,
+                    &resp.counters,
+                    None,
+                ) =>
+            {
+                Some(resp)
+            }
+            _ => None,
+        })?;
+
+        if resp.error_code != 0 {
+            return Err(ClientError::DeviceError(resp.error_code));
+        }
+
+        Ok(resp.src)
+    }
+
+    /// Sends a login request to an SMA device, see [`SmaClient::login`].
+    pub fn login(
+        &mut self,
+        endpoint: &SmaEndpoint,
+        passwd: &str,
+    ) -> Result<LoginInfo, ClientError>
+    where
+        ClientError: From<C::Error>,
+    {
+        let req = self.client.login_request(endpoint, passwd)?;
+        self.write(req)?;
+        let resp = self.read(self.timeout, |msg| match msg {
+            AnySmaMessage::InvLogin(resp)
+                if self.client.is_response(
+                    &resp.src,
+                    &resp.dst,
+                    &resp.counters,
+                    Some(endpoint),
+                ) =>
+            {
+                Some(resp)
+            }
+            _ => None,
+        })?;
+
+        SmaClient::<C>::login_info(&resp)
+    }
+
+    /// Sends a logout request to an SMA device.
+    /// This command has no response.
+    pub fn logout(
+        &mut self,
+        endpoint: &SmaEndpoint,
+    ) -> Result<(), ClientError> {
+        let req = self.client.logout_request(endpoint);
+        self.write(req)
+    }
+
+    /// Requests stored energy meter data for a given time range from the
+    /// device and returns the received records, see
+    /// [`SmaClient::get_day_data`].
+    pub fn get_day_data(
+        &mut self,
+        endpoint: &SmaEndpoint,
+        start_time: u32,
+        end_time: u32,
+    ) -> Result<Vec<SmaInvMeterValue>, ClientError> {
+        let req = self
+            .client
+            .day_data_request(endpoint, start_time, end_time)?;
+        self.write(req)?;
+
+        let mut fragments = Fragments::<SmaInvGetDayData>::default();
+        while !fragments.is_complete() {
+            let read = self.read(self.client.fragment_timeout, |msg| {
+                SmaInvGetDayData::from_any(msg).filter(|resp| {
+                    self.client.is_response(
+                        resp.src(),
+                        resp.dst(),
+                        resp.counters(),
+                        Some(endpoint),
+                    )
+                })
+            });
+            let resp = match read {
+                Err(ClientError::Timeout) => {
+                    return Err(fragments.timeout_error())
+                }
+                resp => resp?,
+            };
+
+            fragments.push(resp)?;
+        }
+
+        fragments.finish(self.client.check_ordering)
+    }
+
+    fn write<T: SmaSerde>(&self, msg: T) -> Result<(), ClientError> {
+        let buffer = msg.to_vec()?;
+        Ok(self
+            .socket
+            .send_to(&buffer, self.dst_sockaddr)
+            .map(|_| ())?)
+    }
+
+    /// Receives messages from the device until one matches the predicate.
+    /// Returns [`ClientError::Timeout`] if no matching message arrived
+    /// within `timeout`.
+    fn read<T>(
+        &self,
+        timeout: Duration,
+        predicate: impl Fn(AnySmaMessage) -> Option<T>,
+    ) -> Result<T, ClientError> {
+        // One extra byte to detect datagrams truncated by the OS.
+        let mut buffer = [0u8; MAX_FRAME_LEN + 1];
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(ClientError::Timeout);
+            }
+            self.socket.set_read_timeout(Some(remaining))?;
+
+            let (rx_len, rx_addr) = match self.socket.recv_from(&mut buffer) {
+                Ok(x) => x,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::WouldBlock | ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Err(ClientError::Timeout)
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            if rx_addr.ip() != *self.dst_sockaddr.ip() {
+                continue;
+            }
+            if rx_len > MAX_FRAME_LEN {
+                return Err(ClientError::FrameTooLarge { max: MAX_FRAME_LEN });
+            }
+
+            let mut cursor = Cursor::new(&buffer[..rx_len]);
+            let message = AnySmaMessage::deserialize(&mut cursor)?;
+            if let Some(x) = predicate(message) {
+                return Ok(x);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn read_timeout_on_silent_device() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 23);
+        let _device =
+            match UdpSocket::bind(SocketAddrV4::new(device_addr, SMA_PORT)) {
+                Ok(x) => x,
+                Err(e) => panic!("Could not bind mock device socket: {e:?}"),
+            };
+
+        let mut client = match SmaBlockingClient::open_unicast(
+            SmaEndpoint::dummy(),
+            device_addr,
+        ) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open blocking client: {e:?}"),
+        };
+        client.set_timeout(Duration::from_millis(100));
+
+        let start = Instant::now();
+        match client.identify() {
+            Err(ClientError::Timeout) => (),
+            x => panic!("Expected timeout, got {x:?}"),
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    #[ignore]
+    fn read_solar_data_blocking() {
+        let inv_addr = Ipv4Addr::new(192, 168, 5, 1);
+        let mut client = match SmaBlockingClient::open_unicast(
+            SmaEndpoint::dummy(),
+            inv_addr,
+        ) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open blocking client: {e:?}"),
+        };
+
+        let device = match client.identify() {
+            Ok(x) => x,
+            Err(e) => panic!("Could not identify SMA device, {e:?}"),
+        };
+        eprintln!("{} is {:X}, {:X}", inv_addr, device.susy_id, device.serial);
+
+        if let Err(e) = client.logout(&device) {
+            panic!("Logout failed: {e:?}");
+        }
+        if let Err(e) = client.login(&device, "0000") {
+            panic!("Login failed: {e:?}");
+        }
+
+        let to = match SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            Ok(x) => x.as_secs() as u32,
+            Err(e) => panic!("Getting system time failed: {e:?}"),
+        };
+        let from = to - 36000;
+
+        match client.get_day_data(&device, from, to) {
+            Err(e) => panic!("Get Day Data failed: {e:?}"),
+            Ok(data) => eprintln!("Get Day data returned {data:?}"),
+        }
+
+        if let Err(e) = client.logout(&device) {
+            panic!("Logout failed: {e:?}");
+        }
+    }
+}
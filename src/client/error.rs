@@ -16,7 +16,21 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
 
-use crate::inverter::{InvalidPasswordError, SmaInvCounter};
+use crate::inverter::{
+    EventRecord, InvalidPasswordError, SmaInvCounter, SmaInvLogin,
+    SmaInvMeterValue,
+};
+
+/// Records of a partially received fragmented response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FragmentRecords {
+    /// Records of a day data response.
+    DayData(Vec<SmaInvMeterValue>),
+    /// Records of a month data response.
+    MonthData(Vec<SmaInvMeterValue>),
+    /// Records of an event log response.
+    Events(Vec<EventRecord>),
+}
 
 /// Errors returned from SMA speedwire client.
 #[derive(Clone, Debug)]
@@ -31,10 +45,80 @@ pub enum ClientError {
     DeviceError(u16),
     /// An additional start of fragment packet was received.
     ExtraSofPacket(SmaInvCounter),
-    /// Login was rejected by the device.
+    /// A fragment was received before the start of fragment packet.
+    MissingSofPacket(SmaInvCounter),
+    /// A fragment was received after the last fragment with ID 0.
+    ExtraFragment(SmaInvCounter),
+    /// Login was rejected by the device with an unknown error code.
     LoginFailed,
+    /// Login was rejected because of an invalid password.
+    WrongPassword,
     /// Invalid input password error.
     InvalidPasswordError(InvalidPasswordError),
+    /// The requested time range is reversed or exceeds the maximum range
+    /// of a single request.
+    InvalidTimeRange { start: u32, end: u32 },
+    /// No matching message was received within the given timeout.
+    Timeout,
+    /// The device echoed a reversed record index range, indicating that the
+    /// requested time range is outside of the stored data.
+    RangeOutOfBounds { start_idx: u32, end_idx: u32 },
+    /// No local network interface suitable for multicast was found.
+    NoMulticastInterface,
+    /// The identify response did not contain identity data.
+    MissingIdentity,
+    /// The device stopped responding before all fragments were received.
+    /// Contains the records of the `rx` received fragments. `total` is zero
+    /// if the start of fragment packet was not received.
+    IncompleteFragments {
+        records: FragmentRecords,
+        rx: u16,
+        total: u16,
+    },
+    /// The response did not contain a valid value for the given object ID.
+    MissingValue { id: u32 },
+    /// A fragment arrived out of sequence, i.e. the fragment with ID
+    /// `expected` was lost or reordered.
+    MissingFragment { expected: u16, got: u16 },
+    /// A received datagram exceeded the maximum frame size of `max` bytes
+    /// and was truncated.
+    FrameTooLarge { max: usize },
+    /// The reassembled day data responses contained a different number of
+    /// records than announced by their record index ranges.
+    RecordCountMismatch { expected: u32, got: u32 },
+    /// Sending a batch of messages stopped at `error` after `sent`
+    /// messages were sent.
+    IncompleteWrite {
+        sent: usize,
+        error: Box<ClientError>,
+    },
+}
+
+impl ClientError {
+    /// Maps a non-zero login response error code to an error.
+    pub(crate) fn from_login_error(error_code: u16) -> Self {
+        match error_code {
+            SmaInvLogin::ERROR_WRONG_PASSWORD => Self::WrongPassword,
+            _ => Self::LoginFailed,
+        }
+    }
+
+    /// Returns the underlying protocol error, if any.
+    pub fn as_protocol(&self) -> Option<&crate::Error> {
+        match self {
+            Self::ProtocolError(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Converts this error into the underlying protocol error.
+    /// Returns the unchanged error if it is not a protocol error.
+    pub fn into_protocol(self) -> Result<crate::Error, Self> {
+        match self {
+            Self::ProtocolError(e) => Ok(e),
+            e => Err(e),
+        }
+    }
 }
 
 impl From<std::io::Error> for ClientError {
@@ -43,6 +127,12 @@ impl From<std::io::Error> for ClientError {
     }
 }
 
+impl From<std::convert::Infallible> for ClientError {
+    fn from(e: std::convert::Infallible) -> Self {
+        match e {}
+    }
+}
+
 impl From<std::time::SystemTimeError> for ClientError {
     fn from(e: std::time::SystemTimeError) -> Self {
         Self::TimeError(e)
@@ -83,12 +173,98 @@ impl std::fmt::Display for ClientError {
                     counter.packet_id, counter.fragment_id
                 )
             }
+            Self::MissingSofPacket(counter) => {
+                write!(
+                    f,
+                    "Received fragment {}:{} before the start fragment",
+                    counter.packet_id, counter.fragment_id
+                )
+            }
+            Self::ExtraFragment(counter) => {
+                write!(
+                    f,
+                    "Received fragment {}:{} after the last fragment",
+                    counter.packet_id, counter.fragment_id
+                )
+            }
             Self::LoginFailed => {
+                write!(f, "The login was rejected")
+            }
+            Self::WrongPassword => {
                 write!(f, "The supplied password was rejected")
             }
             Self::InvalidPasswordError(e) => {
                 write!(f, "{e}")
             }
+            Self::InvalidTimeRange { start, end } => {
+                write!(f, "The time range from {start} to {end} is invalid")
+            }
+            Self::Timeout => {
+                write!(f, "Timed out waiting for a message")
+            }
+            Self::RangeOutOfBounds { start_idx, end_idx } => {
+                write!(
+                    f,
+                    "The device reported the record range {start_idx} \
+                    to {end_idx} as out of bounds"
+                )
+            }
+            Self::NoMulticastInterface => {
+                write!(f, "No non-loopback IPv4 interface was found")
+            }
+            Self::MissingIdentity => {
+                write!(f, "The identify response contained no identity data")
+            }
+            Self::IncompleteFragments { rx, total, .. } => {
+                write!(f, "Received only {rx} of {total} fragments")
+            }
+            Self::MissingValue { id } => {
+                write!(f, "The response contained no valid value for {id:X}")
+            }
+            Self::MissingFragment { expected, got } => {
+                write!(f, "Expected fragment {expected} but received {got}")
+            }
+            Self::FrameTooLarge { max } => {
+                write!(f, "Received a frame larger than {max} bytes")
+            }
+            Self::RecordCountMismatch { expected, got } => {
+                write!(f, "Expected {expected} records but received {got}")
+            }
+            Self::IncompleteWrite { sent, error } => {
+                write!(f, "Sending failed after {sent} messages: {error}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_error_accessors() {
+        let error = ClientError::from(crate::Error::InvalidPassword {
+            reason: InvalidPasswordError::InvalidCharacters,
+        });
+        match error.as_protocol() {
+            Some(crate::Error::InvalidPassword { .. }) => (),
+            x => panic!("Expected protocol error, got {x:?}"),
+        }
+        match error.into_protocol() {
+            Ok(crate::Error::InvalidPassword { .. }) => (),
+            x => panic!("Expected protocol error, got {x:?}"),
+        }
+    }
+
+    #[test]
+    fn io_error_accessors() {
+        let error = ClientError::IoError(std::io::ErrorKind::TimedOut);
+        if let Some(e) = error.as_protocol() {
+            panic!("Unexpected protocol error {e:?}");
+        }
+        match error.into_protocol() {
+            Err(ClientError::IoError(std::io::ErrorKind::TimedOut)) => (),
+            x => panic!("Expected IO error, got {x:?}"),
         }
     }
 }
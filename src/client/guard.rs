@@ -0,0 +1,85 @@
+/******************************************************************************\
+    sma-proto - A SMA Speedwire protocol library
+    Copyright (C) 2024 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+
+use super::{ClientError, LoginInfo, SmaClient, SmaSession};
+use crate::{SmaEndpoint, SystemTimeSource, TimeSource};
+
+/// Login guard returned by [`SmaClient::login_guarded`] which logs out from
+/// the device when dropped, so the device session is freed even if the
+/// program panics or returns early.
+///
+/// Since [`Drop`] can not be async, the logout on drop is sent without
+/// waiting for the socket. It is best-effort: the send rate limit of the
+/// session is bypassed and the logout is lost if the socket is not ready.
+/// The device then holds the login until the session timeout expires.
+/// Use [`Self::logout`] to log out reliably.
+#[derive(Debug)]
+pub struct LoggedInSession<'a, C: TimeSource = SystemTimeSource> {
+    client: &'a mut SmaClient<C>,
+    session: &'a SmaSession,
+    endpoint: SmaEndpoint,
+    info: LoginInfo,
+    logged_in: bool,
+}
+
+impl<'a, C: TimeSource> LoggedInSession<'a, C> {
+    pub(crate) fn new(
+        client: &'a mut SmaClient<C>,
+        session: &'a SmaSession,
+        endpoint: SmaEndpoint,
+        info: LoginInfo,
+    ) -> Self {
+        Self {
+            client,
+            session,
+            endpoint,
+            info,
+            logged_in: true,
+        }
+    }
+
+    /// Returns the session parameters granted by the device.
+    pub fn info(&self) -> &LoginInfo {
+        &self.info
+    }
+
+    /// Returns the endpoint of the logged in device.
+    pub fn endpoint(&self) -> &SmaEndpoint {
+        &self.endpoint
+    }
+
+    /// Returns the client and session for sending further requests.
+    pub fn client(&mut self) -> (&mut SmaClient<C>, &SmaSession) {
+        (self.client, self.session)
+    }
+
+    /// Logs out from the device and waits until the request is sent.
+    pub async fn logout(mut self) -> Result<(), ClientError> {
+        self.logged_in = false;
+        self.client.logout(self.session, &self.endpoint).await
+    }
+}
+
+impl<C: TimeSource> Drop for LoggedInSession<'_, C> {
+    fn drop(&mut self) {
+        if self.logged_in {
+            let req = self.client.logout_request(&self.endpoint);
+            let _ = self.session.try_write(req);
+        }
+    }
+}
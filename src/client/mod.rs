@@ -16,246 +16,2352 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
 
-//! High level tokio based SMA speedwire client.
+//! High level SMA speedwire clients.
+//!
+//! The `client` feature enables the tokio based [`SmaClient`] API and the
+//! `blocking` feature enables [`SmaBlockingClient`] on top of std sockets.
 
+#[cfg(feature = "client")]
 use super::{
     energymeter::{ObisValue, SmaEmMessage},
     inverter::{
-        SmaInvCounter, SmaInvGetDayData, SmaInvIdentify, SmaInvLogin,
-        SmaInvLogout, SmaInvMeterValue,
+        DeviceIdentity, SmaInvGetAllSpotValues, SmaInvGetDeviceInfo,
+        SmaInvGetTemperature, SmaInvGetTotalEnergy,
+    },
+};
+use super::{
+    inverter::{
+        EventRecord, SmaInvCounter, SmaInvGetDayData, SmaInvGetEvents,
+        SmaInvGetMonthData, SmaInvIdentify, SmaInvLogin, SmaInvLogout,
+        SmaInvMeterValue,
     },
     packet::SmaSerde,
-    AnySmaMessage, Cursor, Error, SmaEndpoint,
+    AnySmaMessage, Cursor, Error, SmaEndpoint, SystemTimeSource, TimeSource,
 };
-use std::time::SystemTime;
+use std::time::Duration;
+#[cfg(feature = "client")]
+use std::{sync::OnceLock, time::Instant};
+#[cfg(feature = "client")]
+use tokio::time;
 
+#[cfg(feature = "blocking")]
+mod blocking;
 mod error;
+#[cfg(feature = "client")]
+mod guard;
+#[cfg(feature = "client")]
 mod session;
+#[cfg(feature = "client")]
+mod transport;
 
-pub use error::ClientError;
+#[cfg(feature = "blocking")]
+pub use blocking::SmaBlockingClient;
+pub use error::{ClientError, FragmentRecords};
+#[cfg(feature = "client")]
+pub use guard::LoggedInSession;
+#[cfg(feature = "client")]
 pub use session::SmaSession;
+#[cfg(feature = "client")]
+pub use transport::{MockTransport, SmaTransport};
+
+/// Session parameters granted by a device on successful login.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LoginInfo {
+    /// Session timeout in seconds.
+    pub timeout: u32,
+    /// User group ID of the session.
+    pub user_group: u32,
+}
+
+/// Opaque token granted by a device in the first step of a two-step
+/// exchange, which authorizes the follow-up request. Tokens are stored on
+/// the session per device, see [`SmaTransport::grant_token`].
+#[cfg(feature = "client")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GrantToken(u32);
+
+/// SMA client instance for communication with devices.
+/// This object holds the network independent communication state.
+/// Requests are sent over any [`SmaTransport`], usually a [`SmaSession`].
+///
+/// The client intentionally does not implement [`Clone`] because two copies
+/// would emit identical packet IDs and could not tell their responses apart.
+/// Use [`SmaClient::fork`] to obtain a second client instead.
+///
+/// Login timestamps are taken from the [`TimeSource`] `C`, which defaults to
+/// the system clock. Use [`SmaClient::with_time_source`] to supply a
+/// different clock, e.g. a fixed time in tests.
+#[derive(Debug, Eq, PartialEq)]
+pub struct SmaClient<C: TimeSource = SystemTimeSource> {
+    /// Client SMA endpoint ID.
+    endpoint: SmaEndpoint,
+    /// Clock for message timestamps.
+    time: C,
+    /// Current packet number.
+    packet_id: u16,
+    /// Validate the ordering of received day data records.
+    check_ordering: bool,
+    /// Maximum time to wait for each response fragment.
+    fragment_timeout: Duration,
+}
+
+impl SmaClient {
+    /// Maximum time range in seconds of a single [`SmaInvGetDayData`]
+    /// request accepted by the client.
+    pub const DAY_DATA_MAX_RANGE: u32 = 24 * 60 * 60;
+
+    /// Default time to wait for each fragment of a multi-fragment response.
+    pub const FRAGMENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Distance between the packet IDs of a client and its fork.
+    pub const FORK_STRIDE: u16 = 0x1000;
+
+    /// Creates a new SmaClient with the given SmaEndpoint as source ID.
+    pub fn new(endpoint: SmaEndpoint) -> Self {
+        Self::with_time_source(endpoint, SystemTimeSource)
+    }
+}
+
+impl<C: TimeSource> SmaClient<C> {
+    /// Creates a new SmaClient with the given SmaEndpoint as source ID
+    /// which takes timestamps from the given [`TimeSource`].
+    pub fn with_time_source(endpoint: SmaEndpoint, time: C) -> Self {
+        Self {
+            endpoint,
+            time,
+            packet_id: 0,
+            check_ordering: false,
+            fragment_timeout: SmaClient::FRAGMENT_TIMEOUT,
+        }
+    }
+
+    /// Enables validation of strictly increasing timestamps in the records
+    /// returned by [`Self::get_day_data`] after reassembly of all fragments,
+    /// see [`SmaInvGetDayData::validate_record_ordering`].
+    pub fn set_check_ordering(&mut self, enable: bool) {
+        self.check_ordering = enable;
+    }
+
+    /// Sets the maximum time to wait for each fragment of a
+    /// [`Self::get_day_data`] response, see [`SmaClient::FRAGMENT_TIMEOUT`].
+    pub fn set_fragment_timeout(&mut self, timeout: Duration) {
+        self.fragment_timeout = timeout;
+    }
+
+    /// Creates a new client with the same endpoint and an independent
+    /// packet counter. The fork continues at the current packet ID while
+    /// this client skips ahead by [`SmaClient::FORK_STRIDE`], so both clients
+    /// only collide after that many requests.
+    pub fn fork(&mut self) -> Self
+    where
+        C: Clone,
+    {
+        let fork = Self {
+            endpoint: self.endpoint.clone(),
+            time: self.time.clone(),
+            packet_id: self.packet_id,
+            check_ordering: self.check_ordering,
+            fragment_timeout: self.fragment_timeout,
+        };
+        self.packet_id = (self.packet_id + SmaClient::FORK_STRIDE)
+            & !SmaInvCounter::FIRST_FRAGMENT_BIT;
+
+        fork
+    }
+}
+
+#[cfg(feature = "client")]
+impl<C: TimeSource> SmaClient<C> {
+    /// Sends an identity request to an SMA device.
+    /// Returns the [`SmaEndpoint`] at the clients target IPv4 address.
+    pub async fn identify(
+        &mut self,
+        session: &impl SmaTransport,
+    ) -> Result<SmaEndpoint, ClientError> {
+        Ok(self.identify_response(session, None).await?.src)
+    }
+
+    /// Sends an identity request addressed to the given `target` device
+    /// and returns its decoded [`DeviceIdentity`]. Responses from other
+    /// devices are ignored, which disambiguates discovery on segments with
+    /// multiple devices.
+    pub async fn identify_device(
+        &mut self,
+        session: &impl SmaTransport,
+        target: &SmaEndpoint,
+    ) -> Result<DeviceIdentity, ClientError> {
+        self.identify_response(session, Some(target))
+            .await?
+            .parse_identity()
+            .ok_or(ClientError::MissingIdentity)
+    }
+
+    /// Sends an identity request to an SMA device and returns the decoded
+    /// [`DeviceIdentity`], i.e. the endpoint, device class and embedded
+    /// serial number, of the device at the clients target IPv4 address.
+    /// This does not require a login.
+    pub async fn device_info(
+        &mut self,
+        session: &impl SmaTransport,
+    ) -> Result<DeviceIdentity, ClientError> {
+        self.identify_response(session, None)
+            .await?
+            .parse_identity()
+            .ok_or(ClientError::MissingIdentity)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "identify",
+            level = "debug",
+            skip(self, session),
+            fields(packet_id = tracing::field::Empty),
+        )
+    )]
+    async fn identify_response(
+        &mut self,
+        session: &impl SmaTransport,
+        target: Option<&SmaEndpoint>,
+    ) -> Result<SmaInvIdentify, ClientError> {
+        let req = self.identify_request(target);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("packet_id", req.counters.packet_id);
+
+        session.write(req).await?;
+        let resp = session
+            .read(|msg| match msg {
+                AnySmaMessage::InvIdentify(resp)
+                    if self.is_response(
+                        &resp.src,
+                        &resp.dst,
+                        &resp.counters,
+                        target,
+                    ) =>
+                {
+                    Some(resp)
+                }
+                _ => None,
+            })
+            .await?;
+
+        if resp.error_code != 0 {
+            return Err(ClientError::DeviceError(resp.error_code));
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(endpoint = ?resp.src, "identify response received");
+        Ok(resp)
+    }
+
+    /// Sends a login request to an SMA device.
+    /// Returns the granted [`LoginInfo`] on successful login or a
+    /// [`ClientError`] on failure. The granted timeout may differ from
+    /// the requested one.
+    pub async fn login(
+        &mut self,
+        session: &impl SmaTransport,
+        endpoint: &SmaEndpoint,
+        passwd: &str,
+    ) -> Result<LoginInfo, ClientError>
+    where
+        ClientError: From<C::Error>,
+    {
+        let resp = self.login_response(session, endpoint, passwd).await?;
+        Self::login_info(&resp)
+    }
+
+    /// Logs in to an SMA device like [`Self::login`] and returns the
+    /// `timestamp` field of the login response, i.e. the device's notion of
+    /// the current Unix time. The difference to the client clock can be
+    /// applied to the time range of [`Self::get_day_data`] requests.
+    ///
+    /// The device stays logged in, see [`Self::logout`].
+    pub async fn read_device_time(
+        &mut self,
+        session: &impl SmaTransport,
+        endpoint: &SmaEndpoint,
+        passwd: &str,
+    ) -> Result<u32, ClientError>
+    where
+        ClientError: From<C::Error>,
+    {
+        let resp = self.login_response(session, endpoint, passwd).await?;
+        Self::login_info(&resp)?;
+
+        Ok(resp.timestamp)
+    }
+
+    /// Sends a login request and returns the matching response.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "login",
+            level = "debug",
+            skip_all,
+            fields(?endpoint, packet_id = tracing::field::Empty),
+        )
+    )]
+    async fn login_response(
+        &mut self,
+        session: &impl SmaTransport,
+        endpoint: &SmaEndpoint,
+        passwd: &str,
+    ) -> Result<SmaInvLogin, ClientError>
+    where
+        ClientError: From<C::Error>,
+    {
+        let req = self.login_request(endpoint, passwd)?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("packet_id", req.counters.packet_id);
+
+        session.write(req).await?;
+        let resp = session
+            .read(|msg| match msg {
+                AnySmaMessage::InvLogin(resp)
+                    if self.is_response(
+                        &resp.src,
+                        &resp.dst,
+                        &resp.counters,
+                        Some(endpoint),
+                    ) =>
+                {
+                    Some(resp)
+                }
+                _ => None,
+            })
+            .await?;
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            error_code = resp.error_code,
+            "login response received"
+        );
+
+        Ok(resp)
+    }
+
+    /// Logs in to an SMA device like [`Self::login`] and returns a
+    /// [`LoggedInSession`] guard which logs out from the device when it is
+    /// dropped.
+    pub async fn login_guarded<'a>(
+        &'a mut self,
+        session: &'a SmaSession,
+        endpoint: &SmaEndpoint,
+        passwd: &str,
+    ) -> Result<LoggedInSession<'a, C>, ClientError>
+    where
+        ClientError: From<C::Error>,
+    {
+        let info = self.login(session, endpoint, passwd).await?;
+        Ok(LoggedInSession::new(self, session, endpoint.clone(), info))
+    }
+
+    /// Sends a logout request to an SMA device.
+    /// This command has no response.
+    pub async fn logout(
+        &mut self,
+        session: &impl SmaTransport,
+        endpoint: &SmaEndpoint,
+    ) -> Result<(), ClientError> {
+        let req = self.logout_request(endpoint);
+        session.write(req).await
+    }
+
+    /// Requests stored energy meter data for a given time range from the
+    /// device and returns the received records.
+    /// The time range is validated before sending the request, see
+    /// [`SmaClient::DAY_DATA_MAX_RANGE`].
+    ///
+    /// An empty vector is returned if the device has no records in the
+    /// requested range. If the device echoes a reversed record index range,
+    /// the request was not understood and
+    /// [`ClientError::RangeOutOfBounds`] is returned.
+    ///
+    /// If no fragment arrives within the fragment timeout, see
+    /// [`Self::set_fragment_timeout`], [`ClientError::Timeout`] is returned.
+    /// If the device stops responding after some fragments were received,
+    /// [`ClientError::IncompleteFragments`] carries the partial records.
+    /// Fragments must arrive with decrementing fragment IDs, otherwise
+    /// [`ClientError::MissingFragment`] is returned. Responses to other
+    /// clients or from other devices are ignored even if their packet ID
+    /// matches, so they can not cause a [`ClientError::ExtraSofPacket`].
+    /// If the number of received records differs from the record index
+    /// ranges announced by the fragments,
+    /// [`ClientError::RecordCountMismatch`] is returned.
+    ///
+    /// The records are 5 minute values of the total energy production
+    /// counter, see [`Self::get_month_data`] for daily values.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip(self, session),
+            fields(packet_id = tracing::field::Empty),
+        )
+    )]
+    pub async fn get_day_data(
+        &mut self,
+        session: &impl SmaTransport,
+        endpoint: &SmaEndpoint,
+        start_time: u32,
+        end_time: u32,
+    ) -> Result<Vec<SmaInvMeterValue>, ClientError> {
+        let req = self.day_data_request(endpoint, start_time, end_time)?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("packet_id", req.counters.packet_id);
+
+        session.write(req).await?;
+        self.read_fragments::<SmaInvGetDayData>(session, endpoint)
+            .await
+    }
+
+    /// Requests the daily values of the total energy production counter
+    /// for a given time range from the month archive of the device, see
+    /// [`SmaInvGetMonthData`]. The response is fragmented and reassembled
+    /// like [`Self::get_day_data`], but the time range is not limited.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip(self, session),
+            fields(packet_id = tracing::field::Empty),
+        )
+    )]
+    pub async fn get_month_data(
+        &mut self,
+        session: &impl SmaTransport,
+        endpoint: &SmaEndpoint,
+        start_time: u32,
+        end_time: u32,
+    ) -> Result<Vec<SmaInvMeterValue>, ClientError> {
+        let req = self.month_data_request(endpoint, start_time, end_time)?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("packet_id", req.counters.packet_id);
+
+        session.write(req).await?;
+        self.read_fragments::<SmaInvGetMonthData>(session, endpoint)
+            .await
+    }
+
+    /// Reads the day data of several SMA devices in turn. Each device is
+    /// logged in with the given password, queried with
+    /// [`Self::get_day_data`] and logged out again.
+    ///
+    /// Returns the result of each device in the order of `devices`. A failed
+    /// device does not abort polling of the remaining devices. Logout errors
+    /// after a successful readout are ignored since the device closes the
+    /// session after the login timeout anyway.
+    pub async fn poll_devices(
+        &mut self,
+        session: &impl SmaTransport,
+        devices: &[SmaEndpoint],
+        passwd: &str,
+        start_time: u32,
+        end_time: u32,
+    ) -> Vec<(SmaEndpoint, Result<Vec<SmaInvMeterValue>, ClientError>)>
+    where
+        ClientError: From<C::Error>,
+    {
+        let mut results = Vec::with_capacity(devices.len());
+        for device in devices {
+            let result = match self.login(session, device, passwd).await {
+                Ok(_) => {
+                    let result = self
+                        .get_day_data(session, device, start_time, end_time)
+                        .await;
+                    let _ = self.logout(session, device).await;
+                    result
+                }
+                Err(e) => Err(e),
+            };
+            results.push((device.clone(), result));
+        }
+
+        results
+    }
+
+    /// Requests the user event log entries of an SMA device for a given
+    /// time range. The response is fragmented and reassembled like
+    /// [`Self::get_day_data`]. If the device stops responding after some
+    /// fragments were received, [`ClientError::IncompleteFragments`] carries
+    /// the partial records.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip(self, session),
+            fields(packet_id = tracing::field::Empty),
+        )
+    )]
+    pub async fn get_events(
+        &mut self,
+        session: &impl SmaTransport,
+        endpoint: &SmaEndpoint,
+        start_time: u32,
+        end_time: u32,
+    ) -> Result<Vec<EventRecord>, ClientError> {
+        let req = self.events_request(endpoint, start_time, end_time)?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("packet_id", req.counters.packet_id);
+
+        session.write(req).await?;
+        self.read_fragments::<SmaInvGetEvents>(session, endpoint)
+            .await
+    }
+
+    /// Reads and reassembles all fragments of a response to the last
+    /// request sent to `endpoint`.
+    async fn read_fragments<T: FragmentedResponse>(
+        &self,
+        session: &impl SmaTransport,
+        endpoint: &SmaEndpoint,
+    ) -> Result<Vec<T::Record>, ClientError> {
+        let mut fragments = Fragments::<T>::default();
+        while !fragments.is_complete() {
+            // Cross-talk on shared segments is filtered by source and
+            // destination before the fragment checks below.
+            let read = session.read(|msg| {
+                T::from_any(msg).filter(|resp| {
+                    self.is_response(
+                        resp.src(),
+                        resp.dst(),
+                        resp.counters(),
+                        Some(endpoint),
+                    )
+                })
+            });
+            let resp = match time::timeout(self.fragment_timeout, read).await {
+                Ok(resp) => resp?,
+                Err(_) => return Err(fragments.timeout_error()),
+            };
+
+            fragments.push(resp)?;
+        }
+
+        fragments.finish(self.check_ordering)
+    }
+
+    /// Requests all spot values of an SMA device with a single request.
+    /// Returns the valid values as `(object ID, value)` pairs,
+    /// see [`SmaInvGetAllSpotValues`].
+    pub async fn get_all_spot_values(
+        &mut self,
+        session: &impl SmaTransport,
+        endpoint: &SmaEndpoint,
+    ) -> Result<Vec<(u32, i64)>, ClientError> {
+        let req = SmaInvGetAllSpotValues {
+            dst: endpoint.clone(),
+            src: self.endpoint.clone(),
+            counters: self.next_packet(),
+            first_idx: SmaInvGetAllSpotValues::FIRST_OBJECT,
+            last_idx: SmaInvGetAllSpotValues::LAST_OBJECT,
+            ..Default::default()
+        };
+
+        session.write(req).await?;
+        let resp = session
+            .read(|msg| match msg {
+                AnySmaMessage::InvGetAllSpotValues(resp)
+                    if self.is_response(
+                        &resp.src,
+                        &resp.dst,
+                        &resp.counters,
+                        Some(endpoint),
+                    ) =>
+                {
+                    Some(resp)
+                }
+                _ => None,
+            })
+            .await?;
+
+        if resp.error_code != 0 {
+            return Err(ClientError::DeviceError(resp.error_code));
+        }
+
+        Ok(resp.records)
+    }
+
+    /// Requests the current total energy production counter of an SMA
+    /// device in Wh with a single round trip, see [`SmaInvGetTotalEnergy`].
+    /// Returns [`ClientError::MissingValue`] if the device did not report
+    /// a valid counter value.
+    pub async fn get_total_energy(
+        &mut self,
+        session: &impl SmaTransport,
+        endpoint: &SmaEndpoint,
+    ) -> Result<u64, ClientError> {
+        let req = SmaInvGetTotalEnergy {
+            dst: endpoint.clone(),
+            src: self.endpoint.clone(),
+            counters: self.next_packet(),
+            first_idx: SmaInvGetTotalEnergy::FIRST_OBJECT,
+            last_idx: SmaInvGetTotalEnergy::LAST_OBJECT,
+            ..Default::default()
+        };
+
+        session.write(req).await?;
+        let resp = session
+            .read(|msg| match msg {
+                AnySmaMessage::InvGetTotalEnergy(resp)
+                    if self.is_response(
+                        &resp.src,
+                        &resp.dst,
+                        &resp.counters,
+                        Some(endpoint),
+                    ) =>
+                {
+                    Some(resp)
+                }
+                _ => None,
+            })
+            .await?;
+
+        if resp.error_code != 0 {
+            return Err(ClientError::DeviceError(resp.error_code));
+        }
+
+        resp.energy_wh.ok_or(ClientError::MissingValue {
+            id: SmaInvGetTotalEnergy::TOTAL_ENERGY_ID,
+        })
+    }
+
+    /// Requests the internal temperature of an SMA device in °C with a
+    /// single round trip, see [`SmaInvGetTemperature`].
+    /// Returns [`ClientError::MissingValue`] if the device did not report
+    /// a valid temperature.
+    pub async fn get_temperature(
+        &mut self,
+        session: &impl SmaTransport,
+        endpoint: &SmaEndpoint,
+    ) -> Result<f32, ClientError> {
+        let req = SmaInvGetTemperature {
+            dst: endpoint.clone(),
+            src: self.endpoint.clone(),
+            counters: self.next_packet(),
+            first_idx: SmaInvGetTemperature::FIRST_OBJECT,
+            last_idx: SmaInvGetTemperature::LAST_OBJECT,
+            ..Default::default()
+        };
+
+        session.write(req).await?;
+        let resp = session
+            .read(|msg| match msg {
+                AnySmaMessage::InvGetTemperature(resp)
+                    if self.is_response(
+                        &resp.src,
+                        &resp.dst,
+                        &resp.counters,
+                        Some(endpoint),
+                    ) =>
+                {
+                    Some(resp)
+                }
+                _ => None,
+            })
+            .await?;
+
+        if resp.error_code != 0 {
+            return Err(ClientError::DeviceError(resp.error_code));
+        }
+
+        resp.temperature_c().ok_or(ClientError::MissingValue {
+            id: SmaInvGetTemperature::TEMPERATURE_ID,
+        })
+    }
+
+    /// Requests the packed firmware version of an SMA device with
+    /// [`SmaInvGetDeviceInfo`]. If the device answers with a grant token
+    /// instead of the version, the token is stored on the session and
+    /// echoed in a follow-up request. Later requests to the same device
+    /// echo the stored token right away.
+    /// Returns [`ClientError::MissingValue`] if the device did not report
+    /// a firmware version.
+    pub async fn get_firmware_version(
+        &mut self,
+        session: &impl SmaTransport,
+        endpoint: &SmaEndpoint,
+    ) -> Result<u32, ClientError> {
+        let mut resp = self.device_info_exchange(session, endpoint).await?;
+        if resp.firmware_version.is_none() && resp.token.is_some() {
+            resp = self.device_info_exchange(session, endpoint).await?;
+        }
+
+        resp.firmware_version.ok_or(ClientError::MissingValue {
+            id: SmaInvGetDeviceInfo::FIRMWARE_VERSION_ID,
+        })
+    }
+
+    /// Sends a [`SmaInvGetDeviceInfo`] request with the grant token stored
+    /// on the session, if any, and stores a newly granted token.
+    async fn device_info_exchange(
+        &mut self,
+        session: &impl SmaTransport,
+        endpoint: &SmaEndpoint,
+    ) -> Result<SmaInvGetDeviceInfo, ClientError> {
+        let req = SmaInvGetDeviceInfo {
+            dst: endpoint.clone(),
+            src: self.endpoint.clone(),
+            counters: self.next_packet(),
+            first_idx: SmaInvGetDeviceInfo::FIRST_OBJECT,
+            last_idx: SmaInvGetDeviceInfo::LAST_OBJECT,
+            token: session.grant_token(endpoint).map(|grant| grant.0),
+            ..Default::default()
+        };
+
+        session.write(req).await?;
+        let resp = session
+            .read(|msg| match msg {
+                AnySmaMessage::InvGetDeviceInfo(resp)
+                    if self.is_response(
+                        &resp.src,
+                        &resp.dst,
+                        &resp.counters,
+                        Some(endpoint),
+                    ) =>
+                {
+                    Some(resp)
+                }
+                _ => None,
+            })
+            .await?;
+
+        if resp.error_code != 0 {
+            return Err(ClientError::DeviceError(resp.error_code));
+        }
+        if let Some(token) = resp.token {
+            session.set_grant_token(endpoint, GrantToken(token));
+        }
+
+        Ok(resp)
+    }
+
+    /// Receives a single [`SmaEmMessage`] message and returns the
+    /// millisecond timestamp and payload of the message.
+    pub async fn read_em_message(
+        &mut self,
+        session: &impl SmaTransport,
+        src: &SmaEndpoint,
+    ) -> Result<(u32, Vec<ObisValue>), ClientError> {
+        let msg = session
+            .read(|msg| match msg {
+                AnySmaMessage::EmMessage(resp) if resp.src == *src => {
+                    Some(resp)
+                }
+                _ => None,
+            })
+            .await?;
+
+        Ok((msg.timestamp_ms, msg.payload))
+    }
+
+    /// Waits up to `timeout` for the next [`SmaEmMessage`] from `src` and
+    /// returns the millisecond timestamp and payload of the message.
+    /// Returns [`ClientError::Timeout`] if no message arrived in time.
+    pub async fn poll_em_snapshot(
+        &mut self,
+        session: &impl SmaTransport,
+        src: &SmaEndpoint,
+        timeout: Duration,
+    ) -> Result<(u32, Vec<ObisValue>), ClientError> {
+        match time::timeout(timeout, self.read_em_message(session, src)).await {
+            Ok(result) => result,
+            Err(_) => Err(ClientError::Timeout),
+        }
+    }
+
+    /// Waits up to `timeout` for the next [`SmaEmMessage`] from `src` which
+    /// contains the OBIS value `obis_id`. Messages without the value are
+    /// skipped. Returns the millisecond timestamp and payload of the message
+    /// or [`ClientError::Timeout`] if no matching message arrived in time.
+    pub async fn read_em_with_obis(
+        &mut self,
+        session: &impl SmaTransport,
+        src: &SmaEndpoint,
+        obis_id: u32,
+        timeout: Duration,
+    ) -> Result<(u32, Vec<ObisValue>), ClientError> {
+        let read = session.read(|msg| match msg {
+            AnySmaMessage::EmMessage(resp)
+                if resp.src == *src && resp.contains_obis(obis_id) =>
+            {
+                Some(resp)
+            }
+            _ => None,
+        });
+
+        match time::timeout(timeout, read).await {
+            Ok(msg) => {
+                let msg = msg?;
+                Ok((msg.timestamp_ms, msg.payload))
+            }
+            Err(_) => Err(ClientError::Timeout),
+        }
+    }
+
+    /// Broadcasts the given payload with the given millisecond timestamp
+    /// in a single [`SmaEmMessage`] message.
+    pub async fn write_em_message(
+        &mut self,
+        session: &impl SmaTransport,
+        timestamp_ms: u32,
+        payload: Vec<ObisValue>,
+    ) -> Result<(), ClientError> {
+        let msg = SmaEmMessage {
+            src: self.endpoint.clone(),
+            timestamp_ms,
+            payload,
+        };
+
+        session.write(msg).await
+    }
+
+    /// Broadcasts the given payload in a single [`SmaEmMessage`] message
+    /// with the current monotonic timestamp.
+    ///
+    /// The timestamp counts milliseconds since the first call of this
+    /// method in the process and wraps around at 2^32 like the timestamp
+    /// field of the message. It is not related to the wall clock.
+    pub async fn write_em_message_now(
+        &self,
+        session: &impl SmaTransport,
+        payload: Vec<ObisValue>,
+    ) -> Result<(), ClientError> {
+        let msg = SmaEmMessage {
+            src: self.endpoint.clone(),
+            timestamp_ms: Self::monotonic_ms(),
+            payload,
+        };
+
+        session.write(msg).await
+    }
+
+    /// Returns the milliseconds since the first call modulo 2^32.
+    fn monotonic_ms() -> u32 {
+        static EPOCH: OnceLock<Instant> = OnceLock::new();
+        EPOCH.get_or_init(Instant::now).elapsed().as_millis() as u32
+    }
+}
+
+impl<C: TimeSource> SmaClient<C> {
+    /// Builds an identity request to `target` or to all devices.
+    fn identify_request(
+        &mut self,
+        target: Option<&SmaEndpoint>,
+    ) -> SmaInvIdentify {
+        SmaInvIdentify {
+            dst: target.cloned().unwrap_or_else(SmaEndpoint::broadcast),
+            src: self.endpoint.clone(),
+            counters: self.next_packet(),
+            ..Default::default()
+        }
+    }
+
+    /// Builds a login request with the given password.
+    fn login_request(
+        &mut self,
+        endpoint: &SmaEndpoint,
+        passwd: &str,
+    ) -> Result<SmaInvLogin, ClientError>
+    where
+        ClientError: From<C::Error>,
+    {
+        Ok(SmaInvLogin {
+            dst: endpoint.clone(),
+            src: self.endpoint.clone(),
+            counters: self.next_packet(),
+            password: Some(SmaInvLogin::pw_from_str(passwd)?),
+            ..Default::default()
+        }
+        .with_time_source(&self.time)?)
+    }
+
+    /// Converts a login response into the granted [`LoginInfo`].
+    fn login_info(resp: &SmaInvLogin) -> Result<LoginInfo, ClientError> {
+        if resp.error_code != 0 {
+            Err(ClientError::from_login_error(resp.error_code))
+        } else {
+            Ok(LoginInfo {
+                timeout: resp.timeout,
+                user_group: resp.user_group,
+            })
+        }
+    }
+
+    fn logout_request(&mut self, endpoint: &SmaEndpoint) -> SmaInvLogout {
+        SmaInvLogout {
+            dst: endpoint.clone(),
+            src: self.endpoint.clone(),
+            counters: self.next_packet(),
+            ..Default::default()
+        }
+    }
+
+    /// Builds a day data request after validating the time range.
+    fn day_data_request(
+        &mut self,
+        endpoint: &SmaEndpoint,
+        start_time: u32,
+        end_time: u32,
+    ) -> Result<SmaInvGetDayData, ClientError> {
+        if end_time < start_time
+            || end_time - start_time > SmaClient::DAY_DATA_MAX_RANGE
+        {
+            return Err(ClientError::InvalidTimeRange {
+                start: start_time,
+                end: end_time,
+            });
+        }
+
+        Ok(SmaInvGetDayData {
+            dst: endpoint.clone(),
+            src: self.endpoint.clone(),
+            counters: self.next_packet(),
+            start_time_idx: start_time,
+            end_time_idx: end_time,
+            ..Default::default()
+        })
+    }
+
+    /// Builds a month data request after validating the time range.
+    #[cfg(feature = "client")]
+    fn month_data_request(
+        &mut self,
+        endpoint: &SmaEndpoint,
+        start_time: u32,
+        end_time: u32,
+    ) -> Result<SmaInvGetMonthData, ClientError> {
+        if end_time < start_time {
+            return Err(ClientError::InvalidTimeRange {
+                start: start_time,
+                end: end_time,
+            });
+        }
+
+        Ok(SmaInvGetMonthData {
+            dst: endpoint.clone(),
+            src: self.endpoint.clone(),
+            counters: self.next_packet(),
+            start_time_idx: start_time,
+            end_time_idx: end_time,
+            ..Default::default()
+        })
+    }
+
+    /// Builds an event log request after validating the time range.
+    #[cfg(feature = "client")]
+    fn events_request(
+        &mut self,
+        endpoint: &SmaEndpoint,
+        start_time: u32,
+        end_time: u32,
+    ) -> Result<SmaInvGetEvents, ClientError> {
+        if end_time < start_time {
+            return Err(ClientError::InvalidTimeRange {
+                start: start_time,
+                end: end_time,
+            });
+        }
+
+        Ok(SmaInvGetEvents {
+            dst: endpoint.clone(),
+            src: self.endpoint.clone(),
+            counters: self.next_packet(),
+            start_time_idx: start_time,
+            end_time_idx: end_time,
+            ..Default::default()
+        })
+    }
+
+    /// Returns true if an inverter message with the given addresses and
+    /// counters answers the last request sent to `device`, or to any device
+    /// for broadcast requests. The opcode is checked by matching the
+    /// message type in the read predicate.
+    ///
+    /// Packet IDs wrap after 32768 requests. Checking the endpoints prevents
+    /// stale responses from other devices or clients within this window
+    /// from being accepted.
+    fn is_response(
+        &self,
+        src: &SmaEndpoint,
+        dst: &SmaEndpoint,
+        counters: &SmaInvCounter,
+        device: Option<&SmaEndpoint>,
+    ) -> bool {
+        counters.packet_id == self.packet_id
+            && *dst == self.endpoint
+            && device.map_or(true, |device| src == device)
+    }
+
+    /// Returns the next packet counter.
+    fn next_packet(&mut self) -> SmaInvCounter {
+        self.packet_id += 1;
+        if (self.packet_id & SmaInvCounter::FIRST_FRAGMENT_BIT) != 0 {
+            self.packet_id = 0;
+        }
+
+        SmaInvCounter {
+            packet_id: self.packet_id,
+            fragment_id: 0,
+            first_fragment: true,
+        }
+    }
+}
+
+/// An inverter response which may be split into multiple fragments.
+trait FragmentedResponse: Sized {
+    type Record;
+
+    /// Extracts the response from a received message.
+    fn from_any(msg: AnySmaMessage) -> Option<Self>;
+    fn src(&self) -> &SmaEndpoint;
+    fn dst(&self) -> &SmaEndpoint;
+    fn counters(&self) -> &SmaInvCounter;
+    fn error_code(&self) -> u16;
+    /// Returns the record index range of the fragment.
+    fn record_range(&self) -> (u32, u32);
+    /// Returns the maximum number of records in a single fragment.
+    fn max_record_count() -> usize;
+    /// Returns true if the record index ranges of the fragments announce
+    /// the number of records they contain.
+    fn announces_record_count() -> bool {
+        true
+    }
+    fn take_records(&mut self) -> Vec<Self::Record>;
+    /// Checks the ordering of the reassembled records.
+    fn validate_ordering(_records: &[Self::Record]) -> Result<(), Error> {
+        Ok(())
+    }
+    /// Returns the error for a device which stopped responding after `rx`
+    /// of `total` fragments.
+    fn incomplete(
+        records: Vec<Self::Record>,
+        rx: u16,
+        total: u16,
+    ) -> ClientError;
+}
+
+impl FragmentedResponse for SmaInvGetDayData {
+    type Record = SmaInvMeterValue;
+
+    fn from_any(msg: AnySmaMessage) -> Option<Self> {
+        match msg {
+            AnySmaMessage::InvGetDayData(resp) => Some(resp),
+            _ => None,
+        }
+    }
+
+    fn src(&self) -> &SmaEndpoint {
+        &self.src
+    }
+
+    fn dst(&self) -> &SmaEndpoint {
+        &self.dst
+    }
+
+    fn counters(&self) -> &SmaInvCounter {
+        &self.counters
+    }
+
+    fn error_code(&self) -> u16 {
+        self.error_code
+    }
+
+    fn record_range(&self) -> (u32, u32) {
+        (self.start_time_idx, self.end_time_idx)
+    }
+
+    fn max_record_count() -> usize {
+        Self::MAX_RECORD_COUNT
+    }
+
+    fn take_records(&mut self) -> Vec<Self::Record> {
+        std::mem::take(&mut self.records)
+    }
+
+    fn validate_ordering(records: &[Self::Record]) -> Result<(), Error> {
+        Self::validate_record_ordering(records)
+    }
+
+    fn incomplete(
+        records: Vec<Self::Record>,
+        rx: u16,
+        total: u16,
+    ) -> ClientError {
+        ClientError::IncompleteFragments {
+            records: FragmentRecords::DayData(records),
+            rx,
+            total,
+        }
+    }
+}
+
+impl FragmentedResponse for SmaInvGetMonthData {
+    type Record = SmaInvMeterValue;
+
+    fn from_any(msg: AnySmaMessage) -> Option<Self> {
+        match msg {
+            AnySmaMessage::InvGetMonthData(resp) => Some(resp),
+            _ => None,
+        }
+    }
+
+    fn src(&self) -> &SmaEndpoint {
+        &self.src
+    }
+
+    fn dst(&self) -> &SmaEndpoint {
+        &self.dst
+    }
+
+    fn counters(&self) -> &SmaInvCounter {
+        &self.counters
+    }
+
+    fn error_code(&self) -> u16 {
+        self.error_code
+    }
+
+    fn record_range(&self) -> (u32, u32) {
+        (self.start_time_idx, self.end_time_idx)
+    }
+
+    fn max_record_count() -> usize {
+        Self::MAX_RECORD_COUNT
+    }
+
+    fn take_records(&mut self) -> Vec<Self::Record> {
+        std::mem::take(&mut self.records)
+    }
+
+    fn validate_ordering(records: &[Self::Record]) -> Result<(), Error> {
+        SmaInvGetDayData::validate_record_ordering(records)
+    }
+
+    fn incomplete(
+        records: Vec<Self::Record>,
+        rx: u16,
+        total: u16,
+    ) -> ClientError {
+        ClientError::IncompleteFragments {
+            records: FragmentRecords::MonthData(records),
+            rx,
+            total,
+        }
+    }
+}
+
+impl FragmentedResponse for SmaInvGetEvents {
+    type Record = EventRecord;
+
+    fn from_any(msg: AnySmaMessage) -> Option<Self> {
+        match msg {
+            AnySmaMessage::InvGetEvents(resp) => Some(resp),
+            _ => None,
+        }
+    }
+
+    fn src(&self) -> &SmaEndpoint {
+        &self.src
+    }
+
+    fn dst(&self) -> &SmaEndpoint {
+        &self.dst
+    }
+
+    fn counters(&self) -> &SmaInvCounter {
+        &self.counters
+    }
+
+    fn error_code(&self) -> u16 {
+        self.error_code
+    }
+
+    fn record_range(&self) -> (u32, u32) {
+        (self.start_time_idx, self.end_time_idx)
+    }
+
+    fn max_record_count() -> usize {
+        Self::MAX_RECORD_COUNT
+    }
+
+    /// It is not verified that the record index ranges of event log
+    /// fragments match their record counts, so they are not checked.
+    fn announces_record_count() -> bool {
+        false
+    }
+
+    fn take_records(&mut self) -> Vec<Self::Record> {
+        std::mem::take(&mut self.records)
+    }
+
+    fn incomplete(
+        records: Vec<Self::Record>,
+        rx: u16,
+        total: u16,
+    ) -> ClientError {
+        ClientError::IncompleteFragments {
+            records: FragmentRecords::Events(records),
+            rx,
+            total,
+        }
+    }
+}
+
+/// Reassembly state of a fragmented response.
+#[derive(Debug)]
+struct Fragments<T: FragmentedResponse> {
+    records: Vec<T::Record>,
+    /// Sum of the record counts announced by the received fragments.
+    expected_records: u32,
+    total: u16,
+    rx: u16,
+    rx_first: bool,
+    next_fragment: Option<u16>,
+}
+
+impl<T: FragmentedResponse> Default for Fragments<T> {
+    fn default() -> Self {
+        Self {
+            records: Vec::new(),
+            expected_records: 0,
+            total: 0,
+            rx: 0,
+            rx_first: false,
+            next_fragment: None,
+        }
+    }
+}
+
+impl<T: FragmentedResponse> Fragments<T> {
+    /// Maximum number of announced fragments for which records are
+    /// reserved upfront. The fragment count is taken from the untrusted
+    /// fragment ID of the first fragment, so records of larger responses
+    /// are allocated as the fragments arrive.
+    const MAX_RESERVED_FRAGMENTS: u16 = 16;
+
+    /// Returns true if all fragments of the response were received.
+    fn is_complete(&self) -> bool {
+        self.rx_first && self.rx == self.total
+    }
+
+    /// Validates the sequence of a received fragment and appends its
+    /// records.
+    fn push(&mut self, mut resp: T) -> Result<(), ClientError> {
+        let counters = resp.counters().clone();
+        if counters.first_fragment {
+            if !self.rx_first {
+                // A bogus fragment ID of 0xFFFF must not overflow.
+                self.total = counters.fragment_id.saturating_add(1);
+                self.rx_first = true;
+            } else {
+                return Err(ClientError::ExtraSofPacket(counters));
+            }
+        } else if !self.rx_first {
+            return Err(ClientError::MissingSofPacket(counters));
+        } else if self.next_fragment.is_none() {
+            // Fragment IDs count down, nothing follows fragment 0.
+            return Err(ClientError::ExtraFragment(counters));
+        }
+        self.rx = self
+            .rx
+            .checked_add(1)
+            .ok_or(ClientError::ExtraFragment(counters.clone()))?;
+        if let Some(expected) = self.next_fragment {
+            if counters.fragment_id != expected {
+                return Err(ClientError::MissingFragment {
+                    expected,
+                    got: counters.fragment_id,
+                });
+            }
+        }
+        self.next_fragment = counters.fragment_id.checked_sub(1);
+        let mut records = resp.take_records();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            records = records.len(),
+            "fragment received {}/{}",
+            self.rx,
+            self.total,
+        );
+
+        if resp.error_code() != 0 {
+            return Err(ClientError::DeviceError(resp.error_code()));
+        }
+        let (start_idx, end_idx) = resp.record_range();
+        if end_idx < start_idx {
+            return Err(ClientError::RangeOutOfBounds { start_idx, end_idx });
+        }
+        if counters.first_fragment {
+            // Only the last fragment may contain less records than the
+            // first one. The announced counts are bounded to guard against
+            // bogus record ranges and fragment IDs.
+            let per_fragment =
+                ((end_idx - start_idx) as usize).min(T::max_record_count());
+            let fragments = self.total.min(Self::MAX_RESERVED_FRAGMENTS);
+            let expected = per_fragment * usize::from(fragments);
+            self.records
+                .reserve_exact(expected.saturating_sub(self.records.len()));
+        }
+
+        self.expected_records =
+            self.expected_records.wrapping_add(end_idx - start_idx);
+        self.records.append(&mut records);
+        Ok(())
+    }
+
+    /// Returns the error for a timeout while waiting for the next fragment.
+    fn timeout_error(self) -> ClientError {
+        if self.rx == 0 {
+            ClientError::Timeout
+        } else {
+            T::incomplete(self.records, self.rx, self.total)
+        }
+    }
+
+    /// Returns the reassembled records after checking that their number
+    /// matches the announced record count, if the response announces one.
+    fn finish(
+        self,
+        check_ordering: bool,
+    ) -> Result<Vec<T::Record>, ClientError> {
+        if T::announces_record_count()
+            && self.records.len() != self.expected_records as usize
+        {
+            return Err(ClientError::RecordCountMismatch {
+                expected: self.expected_records,
+                got: self.records.len() as u32,
+            });
+        }
+        if check_ordering {
+            T::validate_ordering(&self.records)?;
+        }
+
+        Ok(self.records)
+    }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+    use super::*;
+    use crate::{
+        energymeter::wrapping_ms_delta,
+        inverter::DeviceClass,
+        packet::{MAX_FRAME_LEN, SMA_PORT},
+    };
+    use std::{
+        net::{Ipv4Addr, SocketAddrV4},
+        sync::mpsc,
+        time::SystemTime,
+    };
+    use tokio::{net::UdpSocket, task::JoinHandle};
+
+    /// Spawns a mock SMA device on the given loopback address which
+    /// answers every received message with the messages returned
+    /// by `respond`.
+    async fn spawn_mock_device<F>(addr: Ipv4Addr, respond: F) -> JoinHandle<()>
+    where
+        F: Fn(AnySmaMessage) -> Vec<AnySmaMessage> + Send + 'static,
+    {
+        let socket =
+            match UdpSocket::bind(SocketAddrV4::new(addr, SMA_PORT)).await {
+                Ok(x) => x,
+                Err(e) => panic!("Could not bind mock device socket: {e:?}"),
+            };
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; MAX_FRAME_LEN];
+            loop {
+                let Ok((len, src)) = socket.recv_from(&mut buffer).await else {
+                    return;
+                };
+                let mut cursor = Cursor::new(&buffer[..len]);
+                let Ok(request) = AnySmaMessage::deserialize(&mut cursor)
+                else {
+                    continue;
+                };
+
+                for response in respond(request) {
+                    let tx_buffer = match response.to_vec() {
+                        Ok(x) => x,
+                        Err(e) => panic!("Mock serialization failed: {e:?}"),
+                    };
+                    if let Err(e) = socket.send_to(&tx_buffer, src).await {
+                        panic!("Sending mock response failed: {e:?}");
+                    }
+                }
+            }
+        })
+    }
+
+    /// Builds a [`SmaInvGetDayData`] response to the given request.
+    fn day_data_response(
+        request: &SmaInvGetDayData,
+        start_time_idx: u32,
+        end_time_idx: u32,
+        records: Vec<SmaInvMeterValue>,
+    ) -> AnySmaMessage {
+        AnySmaMessage::InvGetDayData(SmaInvGetDayData {
+            dst: request.src.clone(),
+            src: request.dst.clone(),
+            counters: request.counters.clone(),
+            start_time_idx,
+            end_time_idx,
+            records,
+            response: true,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn forked_clients_use_independent_packet_ids() {
+        let mut client = SmaClient::new(SmaEndpoint::dummy());
+        let mut fork1 = client.fork();
+        let mut fork2 = client.fork();
+
+        let mut ids = Vec::new();
+        for _ in 0..16 {
+            ids.push(client.next_packet().packet_id);
+            ids.push(fork1.next_packet().packet_id);
+            ids.push(fork2.next_packet().packet_id);
+        }
+
+        let count = ids.len();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(count, ids.len());
+    }
+
+    #[test]
+    fn fragments_bound_reservation_of_spoofed_fragment_id() {
+        let mut fragments = Fragments::<SmaInvGetDayData>::default();
+        let first = SmaInvGetDayData {
+            counters: SmaInvCounter {
+                packet_id: 1,
+                fragment_id: u16::MAX,
+                first_fragment: true,
+            },
+            start_time_idx: 0,
+            end_time_idx: u32::MAX,
+            response: true,
+            ..Default::default()
+        };
+
+        if let Err(e) = fragments.push(first) {
+            panic!("Pushing first fragment failed: {e:?}");
+        }
+        assert_eq!(u16::MAX, fragments.total);
+        assert!(
+            fragments.records.capacity()
+                <= usize::from(
+                    Fragments::<SmaInvGetDayData>::MAX_RESERVED_FRAGMENTS
+                ) * SmaInvGetDayData::MAX_RECORD_COUNT
+        );
+    }
+
+    #[test]
+    fn fragments_reject_out_of_sequence_fragments() {
+        let fragment = |fragment_id, first_fragment| SmaInvGetDayData {
+            counters: SmaInvCounter {
+                packet_id: 1,
+                fragment_id,
+                first_fragment,
+            },
+            response: true,
+            ..Default::default()
+        };
+
+        let mut fragments = Fragments::<SmaInvGetDayData>::default();
+        match fragments.push(fragment(0, false)) {
+            Err(ClientError::MissingSofPacket(counters)) => {
+                assert_eq!(0, counters.fragment_id)
+            }
+            x => panic!("Accepted fragment before start fragment: {x:?}"),
+        }
+
+        let mut fragments = Fragments::<SmaInvGetDayData>::default();
+        for (fragment_id, first_fragment) in [(1, true), (0, false)] {
+            if let Err(e) =
+                fragments.push(fragment(fragment_id, first_fragment))
+            {
+                panic!("Pushing fragment {fragment_id} failed: {e:?}");
+            }
+        }
+        match fragments.push(fragment(0, false)) {
+            Err(ClientError::ExtraFragment(counters)) => {
+                assert_eq!(0, counters.fragment_id)
+            }
+            x => panic!("Accepted fragment after fragment 0: {x:?}"),
+        }
+        assert_eq!(2, fragments.rx);
+    }
+
+    #[tokio::test]
+    async fn login_returns_granted_timeout() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 8);
+        let device = spawn_mock_device(device_addr, |msg| match msg {
+            AnySmaMessage::InvLogin(req) => {
+                vec![AnySmaMessage::InvLogin(SmaInvLogin {
+                    dst: req.src,
+                    src: req.dst,
+                    counters: req.counters,
+                    user_group: req.user_group,
+                    timeout: 300,
+                    timestamp: req.timestamp,
+                    response: true,
+                    ..Default::default()
+                })]
+            }
+            _ => Vec::new(),
+        })
+        .await;
+
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+        let device_ep = SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
+        };
+
+        let result = time::timeout(
+            Duration::from_secs(1),
+            sma_client.login(&session, &device_ep, "0000"),
+        )
+        .await;
+
+        device.abort();
+        match result {
+            Ok(Ok(info)) => assert_eq!(
+                LoginInfo {
+                    timeout: 300,
+                    user_group: 7,
+                },
+                info
+            ),
+            Ok(Err(e)) => panic!("Login failed: {e:?}"),
+            Err(_) => panic!("Login test timed out"),
+        }
+    }
+
+    #[tokio::test]
+    async fn login_uses_time_source() {
+        struct FixedClock;
+
+        impl TimeSource for FixedClock {
+            type Error = std::convert::Infallible;
+
+            fn now_unix_secs(&self) -> Result<u32, Self::Error> {
+                Ok(1700000000)
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let device_addr = Ipv4Addr::new(127, 0, 0, 16);
+        let device = spawn_mock_device(device_addr, move |msg| match msg {
+            AnySmaMessage::InvLogin(req) => {
+                let _ = tx.send(AnySmaMessage::InvLogin(req.clone()).to_vec());
+                vec![AnySmaMessage::InvLogin(SmaInvLogin {
+                    dst: req.src,
+                    src: req.dst,
+                    counters: req.counters,
+                    timestamp: req.timestamp,
+                    response: true,
+                    ..Default::default()
+                })]
+            }
+            _ => Vec::new(),
+        })
+        .await;
+
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client =
+            SmaClient::with_time_source(SmaEndpoint::dummy(), FixedClock);
+        let device_ep = SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
+        };
+
+        let result = time::timeout(
+            Duration::from_secs(1),
+            sma_client.login(&session, &device_ep, "0000"),
+        )
+        .await;
+
+        device.abort();
+        match result {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => panic!("Login failed: {e:?}"),
+            Err(_) => panic!("Login test timed out"),
+        }
+        match rx.try_recv() {
+            Ok(Ok(frame)) => {
+                assert_eq!([0x00, 0xF1, 0x53, 0x65], frame[54..58])
+            }
+            x => panic!("Mock device did not receive login: {x:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn login_maps_error_codes() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 19);
+        let device = spawn_mock_device(device_addr, |msg| match msg {
+            AnySmaMessage::InvLogin(req) => {
+                let error_code = match req.password.map(|pw| pw[0]) {
+                    Some(b'1') => SmaInvLogin::ERROR_WRONG_PASSWORD,
+                    _ => 0xFFFF,
+                };
+                vec![AnySmaMessage::InvLogin(SmaInvLogin {
+                    dst: req.src,
+                    src: req.dst,
+                    error_code,
+                    counters: req.counters,
+                    timestamp: req.timestamp,
+                    password: req.password,
+                    response: true,
+                    ..Default::default()
+                })]
+            }
+            _ => Vec::new(),
+        })
+        .await;
+
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+        let device_ep = SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
+        };
+
+        let result = time::timeout(Duration::from_secs(1), async {
+            for (passwd, expected) in [
+                ("1111", ClientError::WrongPassword),
+                ("4444", ClientError::LoginFailed),
+            ] {
+                match sma_client.login(&session, &device_ep, passwd).await {
+                    Err(e) => assert_eq!(
+                        std::mem::discriminant(&expected),
+                        std::mem::discriminant(&e)
+                    ),
+                    Ok(x) => panic!("Login with {passwd} succeeded: {x:?}"),
+                }
+            }
+        })
+        .await;
+
+        device.abort();
+        if result.is_err() {
+            panic!("Login test timed out");
+        }
+    }
+
+    #[tokio::test]
+    async fn login_guard_logs_out_on_drop() {
+        let (tx, rx) = mpsc::channel();
+        let device_addr = Ipv4Addr::new(127, 0, 0, 20);
+        let device = spawn_mock_device(device_addr, move |msg| match msg {
+            AnySmaMessage::InvLogin(req) => {
+                vec![AnySmaMessage::InvLogin(SmaInvLogin {
+                    dst: req.src,
+                    src: req.dst,
+                    counters: req.counters,
+                    timestamp: req.timestamp,
+                    response: true,
+                    ..Default::default()
+                })]
+            }
+            AnySmaMessage::InvLogout(req) => {
+                let _ = tx.send(req);
+                Vec::new()
+            }
+            _ => Vec::new(),
+        })
+        .await;
+
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+        let device_ep = SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
+        };
+
+        let result = time::timeout(Duration::from_secs(1), async {
+            let guard = match sma_client
+                .login_guarded(&session, &device_ep, "0000")
+                .await
+            {
+                Ok(x) => x,
+                Err(e) => panic!("Login failed: {e:?}"),
+            };
+            assert_eq!(&device_ep, guard.endpoint());
+            drop(guard);
+
+            loop {
+                if let Ok(logout) = rx.try_recv() {
+                    return logout;
+                }
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+
+        device.abort();
+        match result {
+            Ok(logout) => {
+                assert_eq!(device_ep, logout.dst);
+                assert_eq!(SmaEndpoint::dummy(), logout.src);
+                assert_eq!(2, logout.counters.packet_id);
+            }
+            Err(_) => panic!("No logout received after dropping guard"),
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn login_emits_tracing_span() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 14);
+        let device = spawn_mock_device(device_addr, |msg| match msg {
+            AnySmaMessage::InvLogin(req) => {
+                vec![AnySmaMessage::InvLogin(SmaInvLogin {
+                    dst: req.src,
+                    src: req.dst,
+                    counters: req.counters,
+                    timestamp: req.timestamp,
+                    response: true,
+                    ..Default::default()
+                })]
+            }
+            _ => Vec::new(),
+        })
+        .await;
+
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+        let device_ep = SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
+        };
+
+        let result = time::timeout(
+            Duration::from_secs(1),
+            sma_client.login(&session, &device_ep, "0000"),
+        )
+        .await;
+
+        device.abort();
+        match result {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => panic!("Login failed: {e:?}"),
+            Err(_) => panic!("Login test timed out"),
+        }
+        assert!(logs_contain("login{"));
+        assert!(logs_contain("packet_id=1"));
+        assert!(logs_contain("login response received"));
+    }
+
+    #[tokio::test]
+    async fn ignore_stale_response_from_other_device() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 9);
+        let device = spawn_mock_device(device_addr, |msg| match msg {
+            AnySmaMessage::InvGetAllSpotValues(req) => {
+                let response = |src, value| {
+                    AnySmaMessage::InvGetAllSpotValues(SmaInvGetAllSpotValues {
+                        dst: req.src.clone(),
+                        src,
+                        counters: req.counters.clone(),
+                        records: vec![(0x00263F00, value)],
+                        response: true,
+                        ..Default::default()
+                    })
+                };
+                let stale_src = SmaEndpoint {
+                    susy_id: 0x1234,
+                    serial: 0x12345678,
+                };
+                vec![response(stale_src, 1), response(req.dst.clone(), 2)]
+            }
+            _ => Vec::new(),
+        })
+        .await;
+
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+        let device_ep = SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
+        };
+
+        let result = time::timeout(
+            Duration::from_secs(1),
+            sma_client.get_all_spot_values(&session, &device_ep),
+        )
+        .await;
+
+        device.abort();
+        match result {
+            Ok(Ok(records)) => assert_eq!(vec![(0x00263F00, 2)], records),
+            Ok(Err(e)) => panic!("Get all spot values failed: {e:?}"),
+            Err(_) => panic!("Get all spot values test timed out"),
+        }
+    }
+
+    #[tokio::test]
+    async fn device_info_from_identify_response() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 11);
+        let device = spawn_mock_device(device_addr, |msg| match msg {
+            AnySmaMessage::InvIdentify(req) => {
+                vec![AnySmaMessage::InvIdentify(SmaInvIdentify {
+                    dst: req.src,
+                    src: SmaEndpoint {
+                        susy_id: 0x5678,
+                        serial: 0xABCDABCE,
+                    },
+                    counters: req.counters,
+                    #[rustfmt::skip]
+                    identity: Some([
+                        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x00, 0x03, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x56, 0x78,
+                        0xAB, 0xCD, 0xAB, 0xDE, 0x00, 0x00, 0x0A, 0x00,
+                        0x0C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00,
+                    ]),
+                    response: true,
+                    ..Default::default()
+                })]
+            }
+            _ => Vec::new(),
+        })
+        .await;
+
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+
+        let result = time::timeout(
+            Duration::from_secs(1),
+            sma_client.device_info(&session),
+        )
+        .await;
+
+        device.abort();
+        match result {
+            Ok(Ok(info)) => assert_eq!(
+                DeviceIdentity {
+                    endpoint: SmaEndpoint {
+                        susy_id: 0x5678,
+                        serial: 0xABCDABCE,
+                    },
+                    class: DeviceClass::SolarInverter,
+                    serial: 0xABCDABDE,
+                },
+                info
+            ),
+            Ok(Err(e)) => panic!("Device info failed: {e:?}"),
+            Err(_) => panic!("Device info test timed out"),
+        }
+    }
+
+    #[tokio::test]
+    async fn identify_device_ignores_other_devices() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 15);
+        let device = spawn_mock_device(device_addr, |msg| match msg {
+            AnySmaMessage::InvIdentify(req) => {
+                let response = |serial, class| {
+                    #[rustfmt::skip]
+                    let identity = [
+                        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x00, 0x03, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x56, 0x78,
+                        0xAB, 0xCD, 0xAB, 0xDE, 0x00, 0x00, 0x0A, 0x00,
+                        0x0C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, class, 0x01, 0x00, 0x00,
+                    ];
+                    AnySmaMessage::InvIdentify(SmaInvIdentify {
+                        dst: req.src.clone(),
+                        src: SmaEndpoint {
+                            susy_id: 0x5678,
+                            serial,
+                        },
+                        counters: req.counters.clone(),
+                        identity: Some(identity),
+                        response: true,
+                        ..Default::default()
+                    })
+                };
+                // Another device on the segment answers first.
+                vec![response(0x12345678, 0x07), response(0xABCDABCE, 0x01)]
+            }
+            _ => Vec::new(),
+        })
+        .await;
+
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+        let device_ep = SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
+        };
 
-/// SMA client instance for communication with devices.
-/// This object holds the network independent communication state.
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct SmaClient {
-    /// Client SMA endpoint ID.
-    endpoint: SmaEndpoint,
-    /// Current packet number.
-    packet_id: u16,
-}
+        let result = time::timeout(
+            Duration::from_secs(1),
+            sma_client.identify_device(&session, &device_ep),
+        )
+        .await;
 
-impl SmaClient {
-    /// Creates a new SmaClient with the given SmaEndpoint as source ID.
-    pub fn new(endpoint: SmaEndpoint) -> Self {
-        Self {
-            endpoint,
-            packet_id: 0,
+        device.abort();
+        match result {
+            Ok(Ok(info)) => {
+                assert_eq!(device_ep, info.endpoint);
+                assert_eq!(DeviceClass::SolarInverter, info.class);
+            }
+            Ok(Err(e)) => panic!("Identify device failed: {e:?}"),
+            Err(_) => panic!("Identify device test timed out"),
         }
     }
 
-    /// Sends an identity request to an SMA device.
-    /// Returns the [`SmaEndpoint`] at the clients target IPv4 address.
-    pub async fn identify(
-        &mut self,
-        session: &SmaSession,
-    ) -> Result<SmaEndpoint, ClientError> {
-        let req = SmaInvIdentify {
-            dst: SmaEndpoint::broadcast(),
-            src: self.endpoint.clone(),
-            counters: self.next_packet(),
-            ..Default::default()
+    #[tokio::test]
+    async fn get_day_data_without_records() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 6);
+        let device = spawn_mock_device(device_addr, |msg| match msg {
+            AnySmaMessage::InvGetDayData(req) if req.start_time_idx == 0 => {
+                vec![day_data_response(&req, 4, 4, Vec::new())]
+            }
+            AnySmaMessage::InvGetDayData(req) => {
+                vec![day_data_response(&req, 8, 4, Vec::new())]
+            }
+            _ => Vec::new(),
+        })
+        .await;
+
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+        let device_ep = SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
         };
 
-        session.write(req).await?;
-        let resp = session
-            .read(|msg| match msg {
-                AnySmaMessage::InvIdentify(resp)
-                    if resp.counters.packet_id == self.packet_id =>
-                {
-                    Some(resp)
-                }
-                _ => None,
-            })
-            .await?;
+        let result = time::timeout(Duration::from_secs(1), async {
+            match sma_client.get_day_data(&session, &device_ep, 0, 300).await {
+                Ok(records) => assert!(records.is_empty()),
+                Err(e) => panic!("Get Day Data failed: {e:?}"),
+            }
 
-        if resp.error_code != 0 {
-            return Err(ClientError::DeviceError(resp.error_code));
-        }
+            match sma_client.get_day_data(&session, &device_ep, 1, 300).await {
+                Err(ClientError::RangeOutOfBounds {
+                    start_idx: 8,
+                    end_idx: 4,
+                }) => (),
+                x => panic!("Expected out of bounds error, got {x:?}"),
+            }
+        })
+        .await;
 
-        Ok(resp.src)
+        device.abort();
+        if result.is_err() {
+            panic!("Get Day Data test timed out");
+        }
     }
 
-    /// Sends a login request to an SMA device.
-    /// Returns `Ok(())` on successful login or a [`ClientError`] on failure.
-    pub async fn login(
-        &mut self,
-        session: &SmaSession,
-        endpoint: &SmaEndpoint,
-        passwd: &str,
-    ) -> Result<(), ClientError> {
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_secs();
+    #[tokio::test]
+    async fn get_month_data_requests_month_archive() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 30);
+        let device = spawn_mock_device(device_addr, |msg| match msg {
+            AnySmaMessage::InvGetMonthData(req) => {
+                let records = (0..2)
+                    .map(|i| SmaInvMeterValue {
+                        timestamp: req.start_time_idx + 86400 * i,
+                        energy_wh: 1000 + 10 * i as u64,
+                    })
+                    .collect();
+                vec![AnySmaMessage::InvGetMonthData(SmaInvGetMonthData {
+                    dst: req.src.clone(),
+                    src: req.dst.clone(),
+                    counters: req.counters.clone(),
+                    response: true,
+                    start_time_idx: 0,
+                    end_time_idx: 2,
+                    records,
+                    ..Default::default()
+                })]
+            }
+            _ => Vec::new(),
+        })
+        .await;
 
-        let req = SmaInvLogin {
-            dst: endpoint.clone(),
-            src: self.endpoint.clone(),
-            counters: self.next_packet(),
-            timestamp: now as u32,
-            password: Some(SmaInvLogin::pw_from_str(passwd)?),
-            ..Default::default()
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+        let device_ep = SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
         };
 
-        session.write(req).await?;
-        let resp = session
-            .read(|msg| match msg {
-                AnySmaMessage::InvLogin(resp)
-                    if resp.counters.packet_id == self.packet_id =>
-                {
-                    Some(resp)
-                }
-                _ => None,
-            })
-            .await?;
+        let result = time::timeout(
+            Duration::from_secs(1),
+            sma_client.get_month_data(&session, &device_ep, 0, 7 * 86400),
+        )
+        .await;
 
-        if resp.error_code != 0 {
-            Err(ClientError::LoginFailed)
-        } else {
-            Ok(())
+        device.abort();
+        match result {
+            Ok(Ok(records)) => {
+                let values: Vec<_> = records
+                    .iter()
+                    .map(|x| (x.timestamp, x.energy_wh))
+                    .collect();
+                assert_eq!(vec![(0, 1000), (86400, 1010)], values);
+            }
+            Ok(Err(e)) => panic!("Get Month Data failed: {e:?}"),
+            Err(_) => panic!("Get Month Data test timed out"),
         }
     }
 
-    /// Sends a logout request to an SMA device.
-    /// This command has no response.
-    pub async fn logout(
-        &mut self,
-        session: &SmaSession,
-        endpoint: &SmaEndpoint,
-    ) -> Result<(), ClientError> {
-        let req = SmaInvLogout {
-            dst: endpoint.clone(),
-            src: self.endpoint.clone(),
-            counters: self.next_packet(),
-            ..Default::default()
+    #[tokio::test]
+    async fn get_day_data_returns_incomplete_fragments() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 13);
+        let device = spawn_mock_device(device_addr, |msg| match msg {
+            AnySmaMessage::InvGetDayData(req) => (0..2)
+                .map(|i| {
+                    let record = SmaInvMeterValue {
+                        timestamp: req.start_time_idx + 300 * i as u32,
+                        energy_wh: 1000 + i,
+                    };
+                    let mut resp = day_data_response(
+                        &req,
+                        i as u32,
+                        i as u32,
+                        vec![record],
+                    );
+                    if let AnySmaMessage::InvGetDayData(resp) = &mut resp {
+                        resp.counters.fragment_id = 2 - i as u16;
+                        resp.counters.first_fragment = i == 0;
+                    }
+                    resp
+                })
+                .collect(),
+            _ => Vec::new(),
+        })
+        .await;
+
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+        sma_client.set_fragment_timeout(Duration::from_millis(100));
+        let device_ep = SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
         };
 
-        session.write(req).await
+        let result = time::timeout(Duration::from_secs(1), async {
+            match sma_client.get_day_data(&session, &device_ep, 0, 900).await {
+                Err(ClientError::IncompleteFragments {
+                    records: FragmentRecords::DayData(records),
+                    rx: 2,
+                    total: 3,
+                }) => {
+                    let energy: Vec<_> =
+                        records.iter().map(|x| x.energy_wh).collect();
+                    assert_eq!(vec![1000, 1001], energy);
+                }
+                x => panic!("Expected incomplete fragments, got {x:?}"),
+            }
+        })
+        .await;
+
+        device.abort();
+        if result.is_err() {
+            panic!("Get Day Data test timed out");
+        }
     }
 
-    /// Requests stored energy meter data for a given time range from the
-    /// device and returns the received records.
-    pub async fn get_day_data(
-        &mut self,
-        session: &SmaSession,
-        endpoint: &SmaEndpoint,
-        start_time: u32,
-        end_time: u32,
-    ) -> Result<Vec<SmaInvMeterValue>, ClientError> {
-        let req = SmaInvGetDayData {
-            dst: endpoint.clone(),
-            src: self.endpoint.clone(),
-            counters: self.next_packet(),
-            start_time_idx: start_time,
-            end_time_idx: end_time,
-            ..Default::default()
+    #[tokio::test]
+    async fn get_day_data_ignores_foreign_sof() {
+        let device_ep = SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
+        };
+        let other_ep = SmaEndpoint {
+            susy_id: 0x1234,
+            serial: 0x12345678,
+        };
+        let sof = |src: &SmaEndpoint, dst: &SmaEndpoint, energy_wh| {
+            let resp = SmaInvGetDayData {
+                dst: dst.clone(),
+                src: src.clone(),
+                counters: SmaInvCounter {
+                    packet_id: 1,
+                    fragment_id: 0,
+                    first_fragment: true,
+                },
+                end_time_idx: 1,
+                records: vec![SmaInvMeterValue {
+                    timestamp: 1700000000,
+                    energy_wh,
+                }],
+                response: true,
+                ..Default::default()
+            };
+            match resp.to_vec() {
+                Ok(x) => x,
+                Err(e) => panic!("Mock serialization failed: {e:?}"),
+            }
         };
+        let transport = MockTransport::new([
+            // Response of another device to the same client.
+            sof(&other_ep, &SmaEndpoint::dummy(), 1),
+            // Response of the same device to another client.
+            sof(&device_ep, &other_ep, 2),
+            sof(&device_ep, &SmaEndpoint::dummy(), 3),
+        ]);
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
 
-        session.write(req).await?;
+        match sma_client
+            .get_day_data(&transport, &device_ep, 1700000000, 1700000300)
+            .await
+        {
+            Ok(records) => {
+                let energy: Vec<_> =
+                    records.iter().map(|x| x.energy_wh).collect();
+                assert_eq!(vec![3], energy);
+            }
+            Err(e) => panic!("Get day data failed: {e:?}"),
+        }
+    }
 
-        let mut records = Vec::with_capacity(128);
-        let mut total_fragments = 0;
-        let mut rx_fragments = 0;
-        let mut rx_first = false;
-
-        while rx_fragments != total_fragments || !rx_first {
-            let mut resp = session
-                .read(|msg| match msg {
-                    AnySmaMessage::InvGetDayData(resp)
-                        if resp.counters.packet_id == self.packet_id =>
-                    {
-                        Some(resp)
+    #[tokio::test]
+    async fn get_day_data_detects_missing_fragment() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 21);
+        let device = spawn_mock_device(device_addr, |msg| match msg {
+            AnySmaMessage::InvGetDayData(req) => [0, 2]
+                .into_iter()
+                .map(|i| {
+                    let mut resp =
+                        day_data_response(&req, i as u32, i as u32, Vec::new());
+                    if let AnySmaMessage::InvGetDayData(resp) = &mut resp {
+                        resp.counters.fragment_id = 2 - i;
+                        resp.counters.first_fragment = i == 0;
                     }
-                    _ => None,
+                    resp
                 })
-                .await?;
-
-            rx_fragments += 1;
-            if resp.counters.first_fragment {
-                if !rx_first {
-                    total_fragments = resp.counters.fragment_id + 1;
-                    rx_first = true;
-                } else {
-                    return Err(ClientError::ExtraSofPacket(resp.counters));
-                }
-            }
+                .collect(),
+            _ => Vec::new(),
+        })
+        .await;
+
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+        let device_ep = SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
+        };
 
-            if resp.error_code != 0 {
-                return Err(ClientError::DeviceError(resp.error_code));
+        let result = time::timeout(Duration::from_secs(1), async {
+            match sma_client.get_day_data(&session, &device_ep, 0, 900).await {
+                Err(ClientError::MissingFragment {
+                    expected: 1,
+                    got: 0,
+                }) => (),
+                x => panic!("Expected missing fragment, got {x:?}"),
             }
+        })
+        .await;
 
-            records.append(&mut resp.records);
+        device.abort();
+        if result.is_err() {
+            panic!("Get Day Data test timed out");
         }
-
-        Ok(records)
     }
 
-    /// Receives a single [`SmaEmMessage`] message and returns the
-    /// millisecond timestamp and payload of the message.
-    pub async fn read_em_message(
-        &mut self,
-        session: &SmaSession,
-        src: &SmaEndpoint,
-    ) -> Result<(u32, Vec<ObisValue>), ClientError> {
-        let msg = session
-            .read(|msg| match msg {
-                AnySmaMessage::EmMessage(resp) if resp.src == *src => {
-                    Some(resp)
-                }
-                _ => None,
-            })
-            .await?;
+    #[tokio::test]
+    async fn poll_em_snapshot_from_mock_source() {
+        let meter_addr = Ipv4Addr::new(127, 0, 0, 4);
+        let meter = match UdpSocket::bind(SocketAddrV4::new(
+            meter_addr, SMA_PORT,
+        ))
+        .await
+        {
+            Ok(x) => x,
+            Err(e) => panic!("Could not bind mock meter socket: {e:?}"),
+        };
+        let session = match SmaSession::open_unicast(meter_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+        let meter_ep = SmaEndpoint {
+            susy_id: 0x015d,
+            serial: 1901439139,
+        };
 
-        Ok((msg.timestamp_ms, msg.payload))
+        let message = SmaEmMessage {
+            src: meter_ep.clone(),
+            timestamp_ms: 0xAABBCCDD,
+            payload: vec![ObisValue {
+                id: 0x010400,
+                value: 0x01020304,
+            }],
+        };
+        let mut buffer = [0u8; SmaEmMessage::LENGTH_MAX];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaEmMessage serialization failed: {e:?}");
+        }
+        let len = cursor.position();
+        let client_addr =
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, session.local_port());
+        if let Err(e) = meter.send_to(&buffer[..len], client_addr).await {
+            panic!("Sending mock energymeter message failed: {e:?}");
+        }
+
+        let timeout = Duration::from_secs(1);
+        match sma_client
+            .poll_em_snapshot(&session, &meter_ep, timeout)
+            .await
+        {
+            Ok((timestamp_ms, payload)) => {
+                assert_eq!(message.timestamp_ms, timestamp_ms);
+                assert_eq!(message.payload, payload);
+            }
+            Err(e) => panic!("Polling energymeter snapshot failed: {e:?}"),
+        }
+
+        let timeout = Duration::from_millis(50);
+        match sma_client
+            .poll_em_snapshot(&session, &meter_ep, timeout)
+            .await
+        {
+            Err(ClientError::Timeout) => (),
+            x => panic!("Expected timeout, got {x:?}"),
+        }
     }
 
-    /// Broadcasts the given payload with the given millisecond timestamp
-    /// in a single [`SmaEmMessage`] message.
-    pub async fn write_em_message(
-        &mut self,
-        session: &SmaSession,
-        timestamp_ms: u32,
-        payload: Vec<ObisValue>,
-    ) -> Result<(), ClientError> {
-        let msg = SmaEmMessage {
-            src: self.endpoint.clone(),
-            timestamp_ms,
-            payload,
+    #[tokio::test]
+    async fn read_em_message_with_obis() {
+        let meter_addr = Ipv4Addr::new(127, 0, 0, 7);
+        let meter = match UdpSocket::bind(SocketAddrV4::new(
+            meter_addr, SMA_PORT,
+        ))
+        .await
+        {
+            Ok(x) => x,
+            Err(e) => panic!("Could not bind mock meter socket: {e:?}"),
+        };
+        let session = match SmaSession::open_unicast(meter_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+        let meter_ep = SmaEndpoint {
+            susy_id: 0x015d,
+            serial: 1901439139,
         };
+        let client_addr =
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, session.local_port());
 
-        session.write(msg).await
+        for (timestamp_ms, id) in [(1000, 0x010400), (2000, 0x020400)] {
+            let message = SmaEmMessage {
+                src: meter_ep.clone(),
+                timestamp_ms,
+                payload: vec![ObisValue {
+                    id,
+                    value: 0x01020304,
+                }],
+            };
+            let buffer = match message.to_vec() {
+                Ok(x) => x,
+                Err(e) => panic!("SmaEmMessage serialization failed: {e:?}"),
+            };
+            if let Err(e) = meter.send_to(&buffer, client_addr).await {
+                panic!("Sending mock energymeter message failed: {e:?}");
+            }
+        }
+
+        let timeout = Duration::from_secs(1);
+        match sma_client
+            .read_em_with_obis(&session, &meter_ep, 0x020400, timeout)
+            .await
+        {
+            Ok((timestamp_ms, payload)) => {
+                assert_eq!(2000, timestamp_ms);
+                assert_eq!(0x020400, payload[0].id);
+            }
+            Err(e) => panic!("Reading energymeter message failed: {e:?}"),
+        }
     }
 
-    /// Returns the next packet counter.
-    fn next_packet(&mut self) -> SmaInvCounter {
-        self.packet_id += 1;
-        if (self.packet_id & SmaInvCounter::FIRST_FRAGMENT_BIT) != 0 {
-            self.packet_id = 0;
+    #[tokio::test]
+    async fn reject_invalid_day_data_range() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 3);
+        let device =
+            match UdpSocket::bind(SocketAddrV4::new(device_addr, SMA_PORT))
+                .await
+            {
+                Ok(x) => x,
+                Err(e) => panic!("Could not bind mock device socket: {e:?}"),
+            };
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+        let device_ep = SmaEndpoint::broadcast();
+
+        let ranges = [
+            (1700003600, 1700000000),
+            (1700000000, 1700000000 + SmaClient::DAY_DATA_MAX_RANGE + 1),
+        ];
+        for (from, to) in ranges {
+            match sma_client
+                .get_day_data(&session, &device_ep, from, to)
+                .await
+            {
+                Err(ClientError::InvalidTimeRange { start, end }) => {
+                    assert_eq!((from, to), (start, end));
+                }
+                x => panic!("Accepted invalid time range: {x:?}"),
+            }
         }
 
-        SmaInvCounter {
-            packet_id: self.packet_id,
-            fragment_id: 0,
-            first_fragment: true,
+        let mut buffer = [0u8; SmaInvGetDayData::LENGTH_MIN];
+        if let Ok(x) = device.try_recv_from(&mut buffer) {
+            panic!("Request with invalid time range was sent: {x:?}");
         }
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::net::Ipv4Addr;
-    use tokio::time;
 
     #[tokio::test]
     #[ignore]
@@ -356,4 +2462,39 @@ mod tests {
             panic!("Read energymeter message test timed out");
         }
     }
+
+    #[tokio::test]
+    async fn write_em_message_now_timestamps_increase() {
+        let transport = MockTransport::new([]);
+        let sma_client = SmaClient::new(SmaEndpoint::dummy());
+        let payload = vec![ObisValue {
+            id: 0x010400,
+            value: 1000,
+        }];
+
+        for _ in 0..2 {
+            if let Err(e) = sma_client
+                .write_em_message_now(&transport, payload.clone())
+                .await
+            {
+                panic!("Writing energymeter message failed: {e:?}");
+            }
+            time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let timestamps: Vec<_> = transport
+            .written()
+            .iter()
+            .map(|frame| match AnySmaMessage::from_bytes(frame) {
+                Ok(AnySmaMessage::EmMessage(msg)) => {
+                    assert_eq!(payload, msg.payload);
+                    msg.timestamp_ms
+                }
+                x => panic!("Unexpected written message: {x:?}"),
+            })
+            .collect();
+        assert_eq!(2, timestamps.len());
+        let delta = wrapping_ms_delta(timestamps[1], timestamps[0]);
+        assert!((5..u32::MAX / 2).contains(&delta), "Delta was {delta}");
+    }
 }
@@ -16,12 +16,102 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
 
-use super::{AnySmaMessage, ClientError, Cursor, Error, SmaSerde};
+use super::{
+    transport::GrantTokens, AnySmaMessage, ClientError, Cursor, Error,
+    SmaEndpoint, SmaSerde,
+};
+use crate::packet::{MAX_FRAME_LEN, SMA_PORT};
 
-// Required for set_multicast_if_v4 and set_reuse_address
-use socket2::{Domain, Socket, Type};
-use std::net::{Ipv4Addr, SocketAddrV4};
-use tokio::net::UdpSocket;
+use if_addrs::IfAddr;
+// Required for set_multicast_if_v4, set_reuse_address and buffer sizes
+use socket2::{Domain, SockRef, Socket, Type};
+use std::{
+    collections::VecDeque,
+    net::{IpAddr, Ipv4Addr, SocketAddrV4},
+    sync::Mutex,
+};
+use tokio::{
+    net::UdpSocket,
+    time::{self, Duration, Instant},
+};
+
+/// Key which identifies a received frame for duplicate detection.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum FrameKey {
+    /// Energymeter source and millisecond timestamp.
+    Em(SmaEndpoint, u32),
+    /// Inverter source and packet counter key.
+    Inv(SmaEndpoint, u32),
+}
+
+impl FrameKey {
+    fn new(message: &AnySmaMessage) -> Self {
+        match message {
+            AnySmaMessage::EmMessage(x) => {
+                Self::Em(x.src.clone(), x.timestamp_ms)
+            }
+            AnySmaMessage::InvGetAllSpotValues(x) => {
+                Self::Inv(x.src.clone(), x.counters.key())
+            }
+            AnySmaMessage::InvGetDayData(x) => {
+                Self::Inv(x.src.clone(), x.counters.key())
+            }
+            AnySmaMessage::InvGetDeviceInfo(x) => {
+                Self::Inv(x.src.clone(), x.counters.key())
+            }
+            AnySmaMessage::InvGetEvents(x) => {
+                Self::Inv(x.src.clone(), x.counters.key())
+            }
+            AnySmaMessage::InvGetMonthData(x) => {
+                Self::Inv(x.src.clone(), x.counters.key())
+            }
+            AnySmaMessage::InvGetTemperature(x) => {
+                Self::Inv(x.src.clone(), x.counters.key())
+            }
+            AnySmaMessage::InvGetTotalEnergy(x) => {
+                Self::Inv(x.src.clone(), x.counters.key())
+            }
+            AnySmaMessage::InvIdentify(x) => {
+                Self::Inv(x.src.clone(), x.counters.key())
+            }
+            AnySmaMessage::InvLogin(x) => {
+                Self::Inv(x.src.clone(), x.counters.key())
+            }
+            AnySmaMessage::InvLogout(x) => {
+                Self::Inv(x.src.clone(), x.counters.key())
+            }
+        }
+    }
+}
+
+/// Ring buffer of recently received frame keys.
+#[derive(Debug, Default)]
+struct DedupWindow {
+    size: usize,
+    keys: VecDeque<FrameKey>,
+}
+
+impl DedupWindow {
+    /// Records the key of the given message and returns true if it was
+    /// already seen within the window.
+    fn is_duplicate(&mut self, message: &AnySmaMessage) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+
+        let key = FrameKey::new(message);
+        if self.keys.contains(&key) {
+            return true;
+        }
+
+        if self.keys.len() >= self.size {
+            self.keys.pop_front();
+        }
+        self.keys.push_back(key);
+
+        false
+    }
+}
 
 /// SMA client session instance that holds the network dependent state
 /// for communication with a single unicast device, or a group of multicast
@@ -29,83 +119,416 @@ use tokio::net::UdpSocket;
 #[derive(Debug)]
 pub struct SmaSession {
     multicast: bool,
+    /// Local interface address of multicast sessions.
+    local_addr: Ipv4Addr,
     dst_sockaddr: SocketAddrV4,
     socket: UdpSocket,
+    dedup: Mutex<DedupWindow>,
+    /// Minimum interval between two sent messages.
+    send_interval: Duration,
+    /// Earliest time the next message may be sent.
+    next_send: Mutex<Option<Instant>>,
+    /// Multicast TTL set by the user, restored on reopen.
+    multicast_ttl: Option<u32>,
+    /// Receive buffer size set by the user, restored on reopen.
+    recv_buffer_size: Option<usize>,
+    /// Send buffer size set by the user, restored on reopen.
+    send_buffer_size: Option<usize>,
+    /// Grant tokens of two-step exchanges, kept on reopen.
+    pub(crate) grants: GrantTokens,
 }
 
 impl SmaSession {
-    /// Largest seen SMA speedwire packet size before fragmentation.
-    const BUFFER_SIZE: usize = 1030;
-
-    const SMA_PORT: u16 = 9522;
-    const SMA_MCAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 12, 255, 254);
+    /// UDP port of the SMA speedwire protocol, used for unicast and
+    /// multicast traffic.
+    ///
+    /// ```
+    /// use sma_proto::client::SmaSession;
+    /// use std::net::SocketAddrV4;
+    ///
+    /// let group = SocketAddrV4::new(SmaSession::MCAST_ADDR, SmaSession::PORT);
+    /// assert_eq!("239.12.255.254:9522", group.to_string());
+    /// ```
+    pub const PORT: u16 = SMA_PORT;
+    /// Multicast group of SMA speedwire devices, see [`Self::PORT`].
+    pub const MCAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 12, 255, 254);
 
     /// Opens a unicast network socket for communication with a single SMA
     /// device identified by a IP address.
     pub fn open_unicast(remote_addr: Ipv4Addr) -> Result<Self, ClientError> {
-        let socket = Socket::new(Domain::IPV4, Type::DGRAM, None)?;
-        socket.bind(&SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 0).into())?;
-        socket.set_nonblocking(true)?;
-
         Ok(Self {
             multicast: false,
-            socket: UdpSocket::from_std(socket.into())?,
-            dst_sockaddr: SocketAddrV4::new(remote_addr, Self::SMA_PORT),
+            local_addr: Ipv4Addr::UNSPECIFIED,
+            socket: Self::unicast_socket()?,
+            dst_sockaddr: SocketAddrV4::new(remote_addr, Self::PORT),
+            dedup: Mutex::default(),
+            send_interval: Duration::ZERO,
+            next_send: Mutex::default(),
+            multicast_ttl: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            grants: GrantTokens::default(),
         })
     }
 
     /// Opens a multicast network socket on the given local IPv4 address for
     /// communication with a group of SMA devices.
     pub fn open_multicast(local_addr: Ipv4Addr) -> Result<Self, ClientError> {
+        Self::open_multicast_port(local_addr, Self::PORT)
+    }
+
+    fn open_multicast_port(
+        local_addr: Ipv4Addr,
+        port: u16,
+    ) -> Result<Self, ClientError> {
+        Ok(Self {
+            multicast: true,
+            local_addr,
+            socket: Self::multicast_socket(local_addr, port)?,
+            dst_sockaddr: SocketAddrV4::new(Self::MCAST_ADDR, Self::PORT),
+            dedup: Mutex::default(),
+            send_interval: Duration::ZERO,
+            next_send: Mutex::default(),
+            multicast_ttl: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            grants: GrantTokens::default(),
+        })
+    }
+
+    /// Replaces the underlying socket with a new one using the parameters
+    /// the session was opened with, e.g. after the network interface
+    /// went down. Duplicate detection, rate limit, multicast TTL, socket
+    /// buffer size settings and grant tokens are kept.
+    pub fn reopen(&mut self) -> Result<(), ClientError> {
+        self.socket = if self.multicast {
+            let port = self.socket.local_addr()?.port();
+            Self::multicast_socket(self.local_addr, port)?
+        } else {
+            Self::unicast_socket()?
+        };
+
+        if let Some(ttl) = self.multicast_ttl {
+            self.socket.set_multicast_ttl_v4(ttl)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            SockRef::from(&self.socket).set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            SockRef::from(&self.socket).set_send_buffer_size(size)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the time-to-live of sent multicast packets, i.e. the number of
+    /// routers they may cross. The default of 1 keeps them in the local
+    /// subnet, which is sufficient for typical installations since SMA
+    /// devices are usually on the same link. Larger values are required
+    /// to reach devices in other subnets through a multicast router.
+    pub fn set_multicast_ttl(&mut self, ttl: u32) -> Result<(), ClientError> {
+        self.socket.set_multicast_ttl_v4(ttl)?;
+        self.multicast_ttl = Some(ttl);
+
+        Ok(())
+    }
+
+    /// Returns the time-to-live of sent multicast packets.
+    pub fn multicast_ttl(&self) -> Result<u32, ClientError> {
+        Ok(self.socket.multicast_ttl_v4()?)
+    }
+
+    /// Sets the size of the operating system receive buffer of the socket.
+    /// Frames which arrive while the buffer is full are dropped, which can
+    /// happen on segments with many energymeters broadcasting every second.
+    /// Energymeter frames are smaller than 1 KiB, so a buffer of 256 KiB
+    /// holds a few seconds of traffic of a large installation. The
+    /// operating system may round the size or limit it, e.g. to
+    /// `net.core.rmem_max` on Linux.
+    pub fn set_recv_buffer_size(
+        &mut self,
+        size: usize,
+    ) -> Result<(), ClientError> {
+        SockRef::from(&self.socket).set_recv_buffer_size(size)?;
+        self.recv_buffer_size = Some(size);
+
+        Ok(())
+    }
+
+    /// Returns the size of the operating system receive buffer.
+    pub fn recv_buffer_size(&self) -> Result<usize, ClientError> {
+        Ok(SockRef::from(&self.socket).recv_buffer_size()?)
+    }
+
+    /// Sets the size of the operating system send buffer of the socket,
+    /// e.g. for bursts of messages with [`Self::write_many`]. The default
+    /// is sufficient for request/response traffic.
+    pub fn set_send_buffer_size(
+        &mut self,
+        size: usize,
+    ) -> Result<(), ClientError> {
+        SockRef::from(&self.socket).set_send_buffer_size(size)?;
+        self.send_buffer_size = Some(size);
+
+        Ok(())
+    }
+
+    /// Returns the size of the operating system send buffer.
+    pub fn send_buffer_size(&self) -> Result<usize, ClientError> {
+        Ok(SockRef::from(&self.socket).send_buffer_size()?)
+    }
+
+    fn unicast_socket() -> Result<UdpSocket, ClientError> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, None)?;
+        socket.bind(&SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 0).into())?;
+        socket.set_nonblocking(true)?;
+
+        Ok(UdpSocket::from_std(socket.into())?)
+    }
+
+    fn multicast_socket(
+        local_addr: Ipv4Addr,
+        port: u16,
+    ) -> Result<UdpSocket, ClientError> {
         let socket = Socket::new(Domain::IPV4, Type::DGRAM, None)?;
         socket.set_reuse_address(true)?;
-        socket.bind(
-            &SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), Self::SMA_PORT)
-                .into(),
-        )?;
+        socket
+            .bind(&SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), port).into())?;
         socket.set_nonblocking(true)?;
 
         socket.set_multicast_loop_v4(false)?;
         socket.set_multicast_if_v4(&local_addr)?;
-        socket.join_multicast_v4(&Self::SMA_MCAST_ADDR, &local_addr)?;
+        socket.join_multicast_v4(&Self::MCAST_ADDR, &local_addr)?;
 
-        Ok(Self {
-            multicast: true,
-            socket: UdpSocket::from_std(socket.into())?,
-            dst_sockaddr: SocketAddrV4::new(
-                Self::SMA_MCAST_ADDR,
-                Self::SMA_PORT,
-            ),
-        })
+        Ok(UdpSocket::from_std(socket.into())?)
+    }
+
+    /// Opens a multicast network socket on an automatically selected local
+    /// interface, see [`Self::multicast_interface`].
+    pub fn open_multicast_auto() -> Result<Self, ClientError> {
+        Self::open_multicast(Self::multicast_interface()?)
+    }
+
+    /// Selects the local IPv4 address used by [`Self::open_multicast_auto`].
+    /// Interfaces are considered in the order reported by the operating
+    /// system. Loopback addresses are never selected and link-local
+    /// addresses only if no other IPv4 address exists.
+    /// Returns [`ClientError::NoMulticastInterface`] if no address is found.
+    pub fn multicast_interface() -> Result<Ipv4Addr, ClientError> {
+        let addrs = if_addrs::get_if_addrs()?
+            .into_iter()
+            .filter_map(|interface| match interface.addr {
+                IfAddr::V4(addr) => Some(addr.ip),
+                IfAddr::V6(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        Self::select_multicast_interface(&addrs)
+            .ok_or(ClientError::NoMulticastInterface)
+    }
+
+    fn select_multicast_interface(addrs: &[Ipv4Addr]) -> Option<Ipv4Addr> {
+        let mut candidates = addrs.iter().filter(|addr| !addr.is_loopback());
+        candidates
+            .clone()
+            .find(|addr| !addr.is_link_local())
+            .or_else(|| candidates.next())
+            .copied()
+    }
+
+    /// Enables dropping of duplicate frames, e.g. multicast frames received
+    /// on multiple interfaces. Frames are identified by their source and
+    /// timestamp (energymeter) or packet and fragment ID (inverter).
+    /// The last `window` frames are remembered, zero disables the check.
+    pub fn set_dedup(&mut self, window: usize) {
+        let dedup = self.dedup.get_mut().unwrap_or_else(|e| e.into_inner());
+        dedup.size = window;
+        dedup.keys.clear();
+    }
+
+    /// Sets the minimum interval between two sent messages. Writes are
+    /// delayed until the interval since the previous write has elapsed.
+    /// This avoids dropped responses on devices which can not handle
+    /// back-to-back requests. Zero disables the limit.
+    pub fn set_send_rate_limit(&mut self, interval: Duration) {
+        self.send_interval = interval;
+    }
+
+    /// Returns true if this session communicates with a group of multicast
+    /// devices, false for a single unicast device.
+    pub fn is_multicast(&self) -> bool {
+        self.multicast
+    }
+
+    /// Returns the socket address messages are sent to, i.e. the device
+    /// address for unicast or the SMA multicast group for multicast
+    /// sessions.
+    pub fn target_addr(&self) -> SocketAddrV4 {
+        self.dst_sockaddr
+    }
+
+    /// Returns the local port of the underlying socket.
+    #[cfg(test)]
+    pub(crate) fn local_port(&self) -> u16 {
+        self.socket.local_addr().map(|x| x.port()).unwrap_or(0)
     }
 
     pub(crate) async fn write<T: SmaSerde>(
         &self,
         msg: T,
     ) -> Result<(), ClientError> {
-        let mut buffer = [0u8; Self::BUFFER_SIZE];
-        let mut cursor = Cursor::new(&mut buffer[..]);
+        self.send_to(msg, self.dst_sockaddr).await
+    }
 
-        msg.serialize(&mut cursor)?;
-        let len = cursor.position();
+    /// Sends a message to the speedwire port of an arbitrary device over
+    /// the socket of this session instead of the target address, e.g. to
+    /// poll many devices over a single unicast socket. The send rate limit
+    /// applies to all writes of the session. Responses from other addresses
+    /// than the target address are received with [`Self::read_from_any`].
+    pub async fn write_to<T: SmaSerde>(
+        &self,
+        msg: T,
+        addr: Ipv4Addr,
+    ) -> Result<(), ClientError> {
+        self.send_to(msg, SocketAddrV4::new(addr, self.dst_sockaddr.port()))
+            .await
+    }
+
+    async fn send_to<T: SmaSerde>(
+        &self,
+        msg: T,
+        dst_sockaddr: SocketAddrV4,
+    ) -> Result<(), ClientError> {
+        let buffer = msg.to_vec()?;
+
+        if !self.send_interval.is_zero() {
+            let now = Instant::now();
+            let send_at = {
+                let mut next_send =
+                    self.next_send.lock().unwrap_or_else(|e| e.into_inner());
+                let send_at = next_send.map_or(now, |next| next.max(now));
+                *next_send = Some(send_at + self.send_interval);
+                send_at
+            };
+            time::sleep_until(send_at).await;
+        }
 
         Ok(self
             .socket
-            .send_to(&buffer[..len], self.dst_sockaddr)
+            .send_to(&buffer, dst_sockaddr)
             .await
             .map(|_| ())?)
     }
 
-    pub(crate) async fn read<T: SmaSerde>(
+    /// Sends all given messages in order, e.g. a burst of energymeter
+    /// messages. Stops at the first error and returns
+    /// [`ClientError::IncompleteWrite`] with the number of messages which
+    /// were sent before.
+    pub async fn write_many<T: SmaSerde>(
+        &self,
+        msgs: impl IntoIterator<Item = T>,
+    ) -> Result<(), ClientError> {
+        for (sent, msg) in msgs.into_iter().enumerate() {
+            if let Err(e) = self.write(msg).await {
+                return Err(ClientError::IncompleteWrite {
+                    sent,
+                    error: Box::new(e),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends a message without waiting for the socket, for use in
+    /// synchronous contexts. The send rate limit is not applied and the
+    /// message is dropped if the socket is not ready.
+    pub(crate) fn try_write<T: SmaSerde>(
+        &self,
+        msg: T,
+    ) -> Result<(), ClientError> {
+        let buffer = msg.to_vec()?;
+        Ok(self
+            .socket
+            .try_send_to(&buffer, self.dst_sockaddr.into())
+            .map(|_| ())?)
+    }
+
+    pub(crate) async fn read<T>(
+        &self,
+        predicate: impl Fn(AnySmaMessage) -> Option<T>,
+    ) -> Result<T, ClientError> {
+        self.read_frame(false, |msg, _, _| predicate(msg)).await
+    }
+
+    /// Receives messages from any address until one matches the predicate
+    /// and returns the result of the predicate together with the address
+    /// of the sender, e.g. for responses to [`Self::write_to`]. Unicast
+    /// sessions otherwise only accept messages from the target address.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe, see [`Self::read_any_of`].
+    pub async fn read_from_any<T>(
+        &self,
+        predicate: impl Fn(AnySmaMessage) -> Option<T>,
+    ) -> Result<(T, Ipv4Addr), ClientError> {
+        self.read_frame(true, |msg, _, addr| predicate(msg).map(|x| (x, addr)))
+            .await
+    }
+
+    /// Receives messages until one matches the predicate like
+    /// [`Self::read_any_of`] and additionally returns a copy of the
+    /// received datagram, e.g. for archiving frames for later replay.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe, see [`Self::read_any_of`].
+    pub async fn read_raw<T>(
         &self,
         predicate: impl Fn(AnySmaMessage) -> Option<T>,
+    ) -> Result<(T, Vec<u8>), ClientError> {
+        self.read_frame(false, |msg, frame, _| {
+            predicate(msg).map(|x| (x, frame.to_vec()))
+        })
+        .await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "read",
+            level = "debug",
+            skip_all,
+            fields(dst = %self.dst_sockaddr),
+        )
+    )]
+    async fn read_frame<T>(
+        &self,
+        any_source: bool,
+        predicate: impl Fn(AnySmaMessage, &[u8], Ipv4Addr) -> Option<T>,
     ) -> Result<T, ClientError> {
-        let mut buffer = [0u8; Self::BUFFER_SIZE];
+        // One extra byte to detect datagrams truncated by the OS.
+        let mut buffer = [0u8; MAX_FRAME_LEN + 1];
 
         loop {
+            // This must remain the only await point for cancel safety.
+            // Received datagrams are processed without yielding, so
+            // dropping this future never loses a datagram.
             let (rx_len, rx_addr) = self.socket.recv_from(&mut buffer).await?;
 
-            if self.multicast || rx_addr.ip() == *self.dst_sockaddr.ip() {
+            let rx_ip = match rx_addr.ip() {
+                IpAddr::V4(ip) => ip,
+                IpAddr::V6(_) => continue,
+            };
+            if self.multicast || any_source || rx_ip == *self.dst_sockaddr.ip()
+            {
+                if rx_len > MAX_FRAME_LEN {
+                    return Err(ClientError::FrameTooLarge {
+                        max: MAX_FRAME_LEN,
+                    });
+                }
+
                 // Since speedwire is a multicast protocol, receiving an
                 // incorrect message type is not necessarily an
                 // error as it could be just another broadcast message.
@@ -121,10 +544,521 @@ impl SmaSession {
                     Err(e) => return Err(e.into()),
                 };
 
-                if let Some(x) = predicate(message) {
+                let duplicate = match self.dedup.lock() {
+                    Ok(mut dedup) => dedup.is_duplicate(&message),
+                    Err(e) => e.into_inner().is_duplicate(&message),
+                };
+                if duplicate {
+                    continue;
+                }
+
+                if let Some(x) = predicate(message, &buffer[..rx_len], rx_ip) {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        len = rx_len,
+                        from = %rx_addr,
+                        "message received"
+                    );
                     return Ok(x);
                 }
             }
         }
     }
+
+    /// Receives messages until one matches any of the given predicates and
+    /// returns the result of the first matching predicate. This allows
+    /// waiting for different message types on the same session, e.g.
+    /// inverter responses and energymeter broadcasts, without discarding
+    /// frames which are matched by another predicate.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe. The only await point is receiving a
+    /// datagram, which is cancel safe itself, and a received datagram is
+    /// processed without yielding. Dropping the future, e.g. in a
+    /// `tokio::select!` branch, never loses a message or leaves the
+    /// duplicate filter in an inconsistent state.
+    pub async fn read_any_of<T>(
+        &self,
+        predicates: &[&dyn Fn(AnySmaMessage) -> Option<T>],
+    ) -> Result<T, ClientError> {
+        self.read(|msg| {
+            predicates
+                .iter()
+                .find_map(|predicate| predicate(msg.clone()))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        energymeter::{ObisValue, SmaEmMessage},
+        inverter::{SmaInvCounter, SmaInvIdentify, SmaInvLogout},
+    };
+
+    /// Binds a mock device socket to `device_addr` and opens a unicast
+    /// session to it.
+    async fn mock_device(device_addr: Ipv4Addr) -> (UdpSocket, SmaSession) {
+        let device =
+            match UdpSocket::bind(SocketAddrV4::new(device_addr, SMA_PORT))
+                .await
+            {
+                Ok(x) => x,
+                Err(e) => panic!("Could not bind mock device socket: {e:?}"),
+            };
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+
+        (device, session)
+    }
+
+    /// Builds an energymeter message with a single OBIS value.
+    fn em_message(timestamp_ms: u32) -> SmaEmMessage {
+        SmaEmMessage {
+            src: SmaEndpoint::dummy(),
+            timestamp_ms,
+            payload: vec![ObisValue {
+                id: 0x010400,
+                value: 0x01020304,
+            }],
+        }
+    }
+
+    #[test]
+    fn select_multicast_interface_order() {
+        let loopback = Ipv4Addr::LOCALHOST;
+        let link_local = Ipv4Addr::new(169, 254, 1, 2);
+        let lan = Ipv4Addr::new(192, 168, 1, 2);
+
+        assert_eq!(
+            Some(lan),
+            SmaSession::select_multicast_interface(&[
+                loopback, link_local, lan
+            ])
+        );
+        assert_eq!(
+            Some(link_local),
+            SmaSession::select_multicast_interface(&[loopback, link_local])
+        );
+        assert_eq!(None, SmaSession::select_multicast_interface(&[loopback]));
+    }
+
+    #[test]
+    fn detect_multicast_interface() {
+        match SmaSession::multicast_interface() {
+            Ok(addr) => assert!(!addr.is_loopback()),
+            Err(ClientError::NoMulticastInterface) => (),
+            Err(e) => panic!("Multicast interface detection failed: {e:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_duplicate_frames() {
+        let meter_addr = Ipv4Addr::new(127, 0, 0, 5);
+        let (meter, mut session) = mock_device(meter_addr).await;
+        session.set_dedup(4);
+        let client_addr =
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, session.local_port());
+
+        for timestamp_ms in [1000, 1000, 2000] {
+            let message = em_message(timestamp_ms);
+            let mut buffer = [0u8; SmaEmMessage::LENGTH_MAX];
+            let mut cursor = Cursor::new(&mut buffer[..]);
+            if let Err(e) = message.serialize(&mut cursor) {
+                panic!("SmaEmMessage serialization failed: {e:?}");
+            }
+            let len = cursor.position();
+            if let Err(e) = meter.send_to(&buffer[..len], client_addr).await {
+                panic!("Sending mock energymeter message failed: {e:?}");
+            }
+        }
+
+        for expected in [1000, 2000] {
+            match session
+                .read(|msg| match msg {
+                    AnySmaMessage::EmMessage(x) => Some(x),
+                    _ => None,
+                })
+                .await
+            {
+                Ok(msg) => assert_eq!(expected, msg.timestamp_ms),
+                Err(e) => panic!("Reading energymeter message failed: {e:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn cancelled_read_keeps_session_usable() {
+        let meter_addr = Ipv4Addr::new(127, 0, 0, 27);
+        let (meter, session) = mock_device(meter_addr).await;
+        let client_addr =
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, session.local_port());
+        let send = |timestamp_ms| {
+            let message = em_message(timestamp_ms);
+            match message.to_vec() {
+                Ok(x) => x,
+                Err(e) => panic!("Mock serialization failed: {e:?}"),
+            }
+        };
+        let em_message = |msg| match msg {
+            AnySmaMessage::EmMessage(x) => Some(x.timestamp_ms),
+            _ => None,
+        };
+
+        if let Err(e) = meter.send_to(&send(1000), client_addr).await {
+            panic!("Sending mock energymeter message failed: {e:?}");
+        }
+        // Exactly one of the racing reads receives the frame while the
+        // other one is dropped while waiting.
+        let timestamp = tokio::select! {
+            x = session.read(em_message) => x,
+            x = session.read(em_message) => x,
+        };
+        match timestamp {
+            Ok(timestamp) => assert_eq!(1000, timestamp),
+            Err(e) => panic!("Reading energymeter message failed: {e:?}"),
+        }
+
+        // Cancel a read which is waiting for data.
+        tokio::select! {
+            x = session.read(em_message) => {
+                panic!("Read without pending frame returned {x:?}")
+            }
+            _ = time::sleep(Duration::from_millis(50)) => (),
+        }
+
+        if let Err(e) = meter.send_to(&send(2000), client_addr).await {
+            panic!("Sending mock energymeter message failed: {e:?}");
+        }
+        match time::timeout(Duration::from_secs(1), session.read(em_message))
+            .await
+        {
+            Ok(Ok(timestamp)) => assert_eq!(2000, timestamp),
+            x => panic!("Reading after cancelled read failed: {x:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn write_to_multiple_devices() {
+        let addrs =
+            [Ipv4Addr::new(127, 0, 0, 28), Ipv4Addr::new(127, 0, 0, 29)];
+        let mut devices = Vec::new();
+        for addr in addrs {
+            match UdpSocket::bind(SocketAddrV4::new(addr, SMA_PORT)).await {
+                Ok(x) => devices.push(x),
+                Err(e) => panic!("Could not bind mock device socket: {e:?}"),
+            }
+        }
+        let session = match SmaSession::open_unicast(addrs[0]) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+
+        for (addr, device) in addrs.into_iter().zip(&devices) {
+            let request = SmaInvIdentify::default();
+            if let Err(e) = session.write_to(request, addr).await {
+                panic!("Sending identify request to {addr} failed: {e:?}");
+            }
+
+            // Each device answers with its own address as serial number.
+            let mut buffer = [0u8; MAX_FRAME_LEN];
+            let (len, client_addr) = match device.recv_from(&mut buffer).await {
+                Ok(x) => x,
+                Err(e) => panic!("Receiving request at {addr} failed: {e:?}"),
+            };
+            if let Err(e) =
+                SmaInvIdentify::deserialize(&mut Cursor::new(&buffer[..len]))
+            {
+                panic!("Device {addr} received invalid request: {e:?}");
+            }
+            let response = SmaInvIdentify {
+                src: SmaEndpoint {
+                    susy_id: 0x1234,
+                    serial: u32::from(addr),
+                },
+                response: true,
+                ..Default::default()
+            };
+            let buffer = match response.to_vec() {
+                Ok(x) => x,
+                Err(e) => panic!("Mock serialization failed: {e:?}"),
+            };
+            if let Err(e) = device.send_to(&buffer, client_addr).await {
+                panic!("Sending mock response failed: {e:?}");
+            }
+
+            match session
+                .read_from_any(|msg| match msg {
+                    AnySmaMessage::InvIdentify(x) => Some(x.src.serial),
+                    _ => None,
+                })
+                .await
+            {
+                Ok((serial, src)) => {
+                    assert_eq!(addr, src);
+                    assert_eq!(u32::from(addr), serial);
+                }
+                Err(e) => panic!("Reading response from {addr} failed: {e:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn read_any_of_returns_first_match() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 17);
+        let (device, session) = mock_device(device_addr).await;
+        let client_addr =
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, session.local_port());
+
+        let messages = [
+            AnySmaMessage::EmMessage(em_message(1000)),
+            AnySmaMessage::InvIdentify(SmaInvIdentify::default()),
+        ];
+        for message in messages {
+            let buffer = match message.to_vec() {
+                Ok(x) => x,
+                Err(e) => panic!("Mock serialization failed: {e:?}"),
+            };
+            if let Err(e) = device.send_to(&buffer, client_addr).await {
+                panic!("Sending mock message failed: {e:?}");
+            }
+        }
+
+        let identify = |msg| match msg {
+            AnySmaMessage::InvIdentify(_) => Some("identify"),
+            _ => None,
+        };
+        let energymeter = |msg| match msg {
+            AnySmaMessage::EmMessage(_) => Some("energymeter"),
+            _ => None,
+        };
+        match session.read_any_of(&[&identify, &energymeter]).await {
+            Ok(kind) => assert_eq!("energymeter", kind),
+            Err(e) => panic!("Reading any message failed: {e:?}"),
+        }
+        match session.read_any_of(&[&identify, &energymeter]).await {
+            Ok(kind) => assert_eq!("identify", kind),
+            Err(e) => panic!("Reading any message failed: {e:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn read_raw_returns_frame() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 18);
+        let (device, session) = mock_device(device_addr).await;
+        let client_addr =
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, session.local_port());
+
+        let message = em_message(1000);
+        let buffer = match message.to_vec() {
+            Ok(x) => x,
+            Err(e) => panic!("Mock serialization failed: {e:?}"),
+        };
+        if let Err(e) = device.send_to(&buffer, client_addr).await {
+            panic!("Sending mock message failed: {e:?}");
+        }
+
+        let (received, frame) = match session
+            .read_raw(|msg| match msg {
+                AnySmaMessage::EmMessage(x) => Some(x),
+                _ => None,
+            })
+            .await
+        {
+            Ok(x) => x,
+            Err(e) => panic!("Reading raw message failed: {e:?}"),
+        };
+        assert_eq!(message, received);
+        assert_eq!(buffer, frame);
+        match AnySmaMessage::from_bytes(&frame) {
+            Ok(AnySmaMessage::EmMessage(x)) => assert_eq!(received, x),
+            x => panic!("Raw frame did not round-trip: {x:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn session_transport_mode() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 24);
+        let session = match SmaSession::open_unicast(device_addr) {
+            Ok(x) => x,
+            Err(e) => panic!("Could not open SMA client session: {e:?}"),
+        };
+        assert!(!session.is_multicast());
+        assert_eq!(
+            SocketAddrV4::new(device_addr, SMA_PORT),
+            session.target_addr()
+        );
+
+        // Use an ephemeral port to avoid conflicts with mock devices.
+        let session =
+            match SmaSession::open_multicast_port(Ipv4Addr::LOCALHOST, 0) {
+                Ok(x) => x,
+                Err(e) => panic!("Could not open SMA client session: {e:?}"),
+            };
+        assert!(session.is_multicast());
+        assert_eq!(
+            SocketAddrV4::new(Ipv4Addr::new(239, 12, 255, 254), SMA_PORT),
+            session.target_addr()
+        );
+    }
+
+    #[tokio::test]
+    async fn set_socket_buffer_sizes() {
+        let mut session =
+            match SmaSession::open_unicast(Ipv4Addr::new(127, 0, 0, 26)) {
+                Ok(x) => x,
+                Err(e) => panic!("Could not open SMA client session: {e:?}"),
+            };
+
+        // Use small sizes which are below the system limits. Linux doubles
+        // the requested size for bookkeeping overhead.
+        if let Err(e) = session.set_recv_buffer_size(16384) {
+            panic!("Setting receive buffer size failed: {e:?}");
+        }
+        if let Err(e) = session.set_send_buffer_size(16384) {
+            panic!("Setting send buffer size failed: {e:?}");
+        }
+        if let Err(e) = session.reopen() {
+            panic!("Reopening SMA client session failed: {e:?}");
+        }
+        match session.recv_buffer_size() {
+            Ok(size) => assert!(size >= 16384, "Receive buffer is {size}"),
+            Err(e) => panic!("Reading receive buffer size failed: {e:?}"),
+        }
+        match session.send_buffer_size() {
+            Ok(size) => assert!(size >= 16384, "Send buffer is {size}"),
+            Err(e) => panic!("Reading send buffer size failed: {e:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn set_multicast_ttl() {
+        // Use an ephemeral port to avoid conflicts with mock devices.
+        let mut session =
+            match SmaSession::open_multicast_port(Ipv4Addr::LOCALHOST, 0) {
+                Ok(x) => x,
+                Err(e) => panic!("Could not open SMA client session: {e:?}"),
+            };
+
+        match session.multicast_ttl() {
+            Ok(ttl) => assert_eq!(1, ttl),
+            Err(e) => panic!("Reading multicast TTL failed: {e:?}"),
+        }
+        if let Err(e) = session.set_multicast_ttl(8) {
+            panic!("Setting multicast TTL failed: {e:?}");
+        }
+        match session.multicast_ttl() {
+            Ok(ttl) => assert_eq!(8, ttl),
+            Err(e) => panic!("Reading multicast TTL failed: {e:?}"),
+        }
+
+        if let Err(e) = session.reopen() {
+            panic!("Reopening SMA client session failed: {e:?}");
+        }
+        match session.multicast_ttl() {
+            Ok(ttl) => assert_eq!(8, ttl),
+            Err(e) => panic!("Reading multicast TTL failed: {e:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn reopen_session_and_send() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 10);
+        let (device, mut session) = mock_device(device_addr).await;
+        if let Err(e) = session.reopen() {
+            panic!("Reopening SMA client session failed: {e:?}");
+        }
+
+        let message = em_message(1000);
+        if let Err(e) = session.write(message.clone()).await {
+            panic!("Writing to reopened session failed: {e:?}");
+        }
+
+        let mut buffer = [0u8; SmaEmMessage::LENGTH_MAX];
+        let (len, src) = match device.recv_from(&mut buffer).await {
+            Ok(x) => x,
+            Err(e) => panic!("Receiving at mock device failed: {e:?}"),
+        };
+        assert_eq!(session.local_port(), src.port());
+        let mut cursor = Cursor::new(&buffer[..len]);
+        match SmaEmMessage::deserialize(&mut cursor) {
+            Ok(received) => assert_eq!(message, received),
+            Err(e) => panic!("SmaEmMessage deserialization failed: {e:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn write_many_sends_all_messages() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 25);
+        let (device, session) = mock_device(device_addr).await;
+
+        let logouts = (1..=3).map(|packet_id| SmaInvLogout {
+            dst: SmaEndpoint::broadcast(),
+            src: SmaEndpoint::dummy(),
+            counters: SmaInvCounter {
+                packet_id,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        if let Err(e) = session.write_many(logouts).await {
+            panic!("Writing messages failed: {e:?}");
+        }
+
+        let mut buffer = [0u8; SmaInvLogout::LENGTH];
+        for packet_id in 1..=3 {
+            let len = match device.recv(&mut buffer).await {
+                Ok(x) => x,
+                Err(e) => panic!("Receiving at mock device failed: {e:?}"),
+            };
+            let mut cursor = Cursor::new(&buffer[..len]);
+            match SmaInvLogout::deserialize(&mut cursor) {
+                Ok(received) => {
+                    assert_eq!(packet_id, received.counters.packet_id)
+                }
+                Err(e) => panic!("SmaInvLogout deserialization failed: {e:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limit_consecutive_writes() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 12);
+        let (_device, mut session) = mock_device(device_addr).await;
+        let interval = Duration::from_millis(50);
+        session.set_send_rate_limit(interval);
+
+        let message = em_message(1000);
+
+        let start = Instant::now();
+        for _ in 0..2 {
+            if let Err(e) = session.write(message.clone()).await {
+                panic!("Writing message failed: {e:?}");
+            }
+        }
+        assert!(start.elapsed() >= interval);
+    }
+
+    #[tokio::test]
+    async fn read_oversized_frame() {
+        let device_addr = Ipv4Addr::new(127, 0, 0, 22);
+        let (device, session) = mock_device(device_addr).await;
+        let client_addr =
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, session.local_port());
+
+        let buffer = [0u8; MAX_FRAME_LEN + 100];
+        if let Err(e) = device.send_to(&buffer, client_addr).await {
+            panic!("Sending mock message failed: {e:?}");
+        }
+
+        match session.read(Some).await {
+            Err(ClientError::FrameTooLarge { max: MAX_FRAME_LEN }) => (),
+            x => panic!("Expected oversized frame error, got {x:?}"),
+        }
+    }
 }
@@ -0,0 +1,702 @@
+/******************************************************************************\
+    sma-proto - A SMA Speedwire protocol library
+    Copyright (C) 2024 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+
+use super::{ClientError, GrantToken, SmaSession};
+use crate::{AnySmaMessage, Cursor, SmaEndpoint, SmaSerde};
+use std::{collections::VecDeque, future::Future, sync::Mutex};
+
+/// Message transport used by [`SmaClient`](super::SmaClient) requests.
+/// This is implemented by the network based [`SmaSession`] and by
+/// [`MockTransport`] for testing without a network.
+pub trait SmaTransport {
+    /// Sends a message to the remote device or multicast group.
+    fn write<T: SmaSerde>(
+        &self,
+        msg: T,
+    ) -> impl Future<Output = Result<(), ClientError>>;
+
+    /// Receives messages until one matches the predicate and returns the
+    /// result of the predicate.
+    ///
+    /// Implementations must be cancel safe, i.e. dropping the future, e.g.
+    /// in a `tokio::select!` branch or on timeout, must not lose a message
+    /// which a subsequent read could return.
+    fn read<T>(
+        &self,
+        predicate: impl Fn(AnySmaMessage) -> Option<T>,
+    ) -> impl Future<Output = Result<T, ClientError>>;
+
+    /// Returns the grant token which `endpoint` granted on this transport
+    /// in the first step of a two-step exchange, see [`GrantToken`].
+    fn grant_token(&self, endpoint: &SmaEndpoint) -> Option<GrantToken>;
+
+    /// Stores a grant token of `endpoint`, replacing a previous one.
+    fn set_grant_token(&self, endpoint: &SmaEndpoint, token: GrantToken);
+}
+
+/// Grant tokens of the devices reachable over a transport.
+#[derive(Debug, Default)]
+pub(crate) struct GrantTokens(Mutex<Vec<(SmaEndpoint, GrantToken)>>);
+
+impl GrantTokens {
+    fn get(&self, endpoint: &SmaEndpoint) -> Option<GrantToken> {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|(ep, _)| ep == endpoint)
+            .map(|(_, token)| *token)
+    }
+
+    fn set(&self, endpoint: &SmaEndpoint, token: GrantToken) {
+        let mut tokens = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match tokens.iter_mut().find(|(ep, _)| ep == endpoint) {
+            Some(entry) => entry.1 = token,
+            None => tokens.push((endpoint.clone(), token)),
+        }
+    }
+}
+
+impl SmaTransport for SmaSession {
+    async fn write<T: SmaSerde>(&self, msg: T) -> Result<(), ClientError> {
+        SmaSession::write(self, msg).await
+    }
+
+    async fn read<T>(
+        &self,
+        predicate: impl Fn(AnySmaMessage) -> Option<T>,
+    ) -> Result<T, ClientError> {
+        SmaSession::read(self, predicate).await
+    }
+
+    fn grant_token(&self, endpoint: &SmaEndpoint) -> Option<GrantToken> {
+        self.grants.get(endpoint)
+    }
+
+    fn set_grant_token(&self, endpoint: &SmaEndpoint, token: GrantToken) {
+        self.grants.set(endpoint, token)
+    }
+}
+
+/// Transport which replays a scripted sequence of received frames and
+/// records all written frames, e.g. for deterministic client tests.
+///
+/// Each read consumes frames in order until one matches the predicate.
+/// Non-matching frames are discarded like on a real session. If the script
+/// is exhausted, [`ClientError::Timeout`] is returned.
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    frames: Mutex<VecDeque<Vec<u8>>>,
+    written: Mutex<Vec<Vec<u8>>>,
+    grants: GrantTokens,
+}
+
+impl MockTransport {
+    /// Creates a new mock transport which replays the given frames.
+    pub fn new(frames: impl IntoIterator<Item = Vec<u8>>) -> Self {
+        Self {
+            frames: Mutex::new(frames.into_iter().collect()),
+            written: Mutex::default(),
+            grants: GrantTokens::default(),
+        }
+    }
+
+    /// Appends a frame to the end of the script.
+    pub fn push_frame(&self, frame: Vec<u8>) {
+        self.frames
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(frame);
+    }
+
+    /// Returns the number of frames which were not read yet.
+    pub fn remaining(&self) -> usize {
+        self.frames.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Returns all frames written so far.
+    pub fn written(&self) -> Vec<Vec<u8>> {
+        self.written
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl SmaTransport for MockTransport {
+    async fn write<T: SmaSerde>(&self, msg: T) -> Result<(), ClientError> {
+        let buffer = msg.to_vec()?;
+        self.written
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(buffer);
+
+        Ok(())
+    }
+
+    async fn read<T>(
+        &self,
+        predicate: impl Fn(AnySmaMessage) -> Option<T>,
+    ) -> Result<T, ClientError> {
+        loop {
+            let Some(frame) = self
+                .frames
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .pop_front()
+            else {
+                return Err(ClientError::Timeout);
+            };
+
+            let message = AnySmaMessage::deserialize(&mut Cursor::new(&frame))?;
+            if let Some(x) = predicate(message) {
+                return Ok(x);
+            }
+        }
+    }
+
+    fn grant_token(&self, endpoint: &SmaEndpoint) -> Option<GrantToken> {
+        self.grants.get(endpoint)
+    }
+
+    fn set_grant_token(&self, endpoint: &SmaEndpoint, token: GrantToken) {
+        self.grants.set(endpoint, token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::SmaClient,
+        inverter::{
+            EventRecord, SmaInvCounter, SmaInvGetDayData, SmaInvGetDeviceInfo,
+            SmaInvGetEvents, SmaInvLogin, SmaInvLogout, SmaInvMeterValue,
+        },
+        SmaEndpoint, TimeSource,
+    };
+    use std::time::{SystemTime, SystemTimeError, UNIX_EPOCH};
+
+    fn device_ep() -> SmaEndpoint {
+        SmaEndpoint {
+            susy_id: 0x5678,
+            serial: 0xABCDABCE,
+        }
+    }
+
+    fn counters(packet_id: u16) -> SmaInvCounter {
+        SmaInvCounter {
+            packet_id,
+            ..Default::default()
+        }
+    }
+
+    fn frame(message: impl SmaSerde) -> Vec<u8> {
+        match message.to_vec() {
+            Ok(x) => x,
+            Err(e) => panic!("Mock serialization failed: {e:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn login_flow_with_mock_transport() {
+        let transport = MockTransport::new([
+            // Unrelated response with a stale packet ID is skipped.
+            frame(SmaInvLogin {
+                dst: SmaEndpoint::dummy(),
+                src: device_ep(),
+                counters: counters(7),
+                response: true,
+                ..Default::default()
+            }),
+            frame(SmaInvLogin {
+                dst: SmaEndpoint::dummy(),
+                src: device_ep(),
+                counters: counters(1),
+                timeout: 900,
+                user_group: 7,
+                response: true,
+                ..Default::default()
+            }),
+            frame(SmaInvGetDayData {
+                dst: SmaEndpoint::dummy(),
+                src: device_ep(),
+                counters: SmaInvCounter {
+                    packet_id: 2,
+                    fragment_id: 0,
+                    first_fragment: true,
+                },
+                start_time_idx: 0,
+                end_time_idx: 1,
+                records: vec![SmaInvMeterValue {
+                    timestamp: 1700000000,
+                    energy_wh: 1234,
+                }],
+                response: true,
+                ..Default::default()
+            }),
+        ]);
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+
+        match sma_client.login(&transport, &device_ep(), "0000").await {
+            Ok(info) => {
+                assert_eq!(900, info.timeout);
+                assert_eq!(7, info.user_group);
+            }
+            Err(e) => panic!("Login failed: {e:?}"),
+        }
+        match sma_client
+            .get_day_data(&transport, &device_ep(), 1700000000, 1700000300)
+            .await
+        {
+            Ok(records) => {
+                assert_eq!(1, records.len());
+                assert_eq!(1234, records[0].energy_wh);
+            }
+            Err(e) => panic!("Get day data failed: {e:?}"),
+        }
+        if let Err(e) = sma_client.logout(&transport, &device_ep()).await {
+            panic!("Logout failed: {e:?}");
+        }
+        assert_eq!(0, transport.remaining());
+
+        let written = transport.written();
+        assert_eq!(3, written.len());
+        match AnySmaMessage::from_bytes(&written[0]) {
+            Ok(AnySmaMessage::InvLogin(req)) => {
+                assert_eq!(device_ep(), req.dst);
+                assert_eq!(1, req.counters.packet_id);
+                assert!(req.password.is_some());
+            }
+            x => panic!("Expected login request, got {x:?}"),
+        }
+        match AnySmaMessage::from_bytes(&written[1]) {
+            Ok(AnySmaMessage::InvGetDayData(req)) => {
+                assert_eq!(1700000000, req.start_time_idx);
+                assert_eq!(1700000300, req.end_time_idx);
+            }
+            x => panic!("Expected get day data request, got {x:?}"),
+        }
+        match AnySmaMessage::from_bytes(&written[2]) {
+            Ok(AnySmaMessage::InvLogout(SmaInvLogout { counters, .. })) => {
+                assert_eq!(3, counters.packet_id);
+            }
+            x => panic!("Expected logout request, got {x:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn login_fails_on_clock_before_epoch() {
+        struct BrokenClock;
+
+        impl TimeSource for BrokenClock {
+            type Error = SystemTimeError;
+
+            fn now_unix_secs(&self) -> Result<u32, Self::Error> {
+                UNIX_EPOCH
+                    .duration_since(SystemTime::now())
+                    .map(|x| x.as_secs() as u32)
+            }
+        }
+
+        let transport = MockTransport::default();
+        let mut sma_client =
+            SmaClient::with_time_source(SmaEndpoint::dummy(), BrokenClock);
+
+        match sma_client.login(&transport, &device_ep(), "0000").await {
+            Err(ClientError::TimeError(_)) => (),
+            x => panic!("Expected time error, got {x:?}"),
+        }
+        assert!(transport.written().is_empty());
+    }
+
+    #[tokio::test]
+    async fn login_times_out_on_exhausted_script() {
+        let transport = MockTransport::default();
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+
+        match sma_client.login(&transport, &device_ep(), "0000").await {
+            Err(ClientError::Timeout) => (),
+            x => panic!("Expected timeout, got {x:?}"),
+        }
+        assert_eq!(1, transport.written().len());
+    }
+
+    #[tokio::test]
+    async fn read_device_time_from_login_response() {
+        let transport = MockTransport::new([frame(SmaInvLogin {
+            dst: SmaEndpoint::dummy(),
+            src: device_ep(),
+            counters: counters(1),
+            timestamp: 1700000123,
+            response: true,
+            ..Default::default()
+        })]);
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+
+        match sma_client
+            .read_device_time(&transport, &device_ep(), "0000")
+            .await
+        {
+            Ok(timestamp) => assert_eq!(1700000123, timestamp),
+            Err(e) => panic!("Reading device time failed: {e:?}"),
+        }
+
+        let written = transport.written();
+        assert_eq!(1, written.len());
+        match AnySmaMessage::from_bytes(&written[0]) {
+            Ok(AnySmaMessage::InvLogin(req)) => {
+                assert_eq!(device_ep(), req.dst);
+                assert!(req.password.is_some());
+            }
+            x => panic!("Expected login request, got {x:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn read_device_time_fails_on_wrong_password() {
+        let transport = MockTransport::new([frame(SmaInvLogin {
+            dst: SmaEndpoint::dummy(),
+            src: device_ep(),
+            counters: counters(1),
+            error_code: SmaInvLogin::ERROR_WRONG_PASSWORD,
+            timestamp: 1700000123,
+            response: true,
+            ..Default::default()
+        })]);
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+
+        match sma_client
+            .read_device_time(&transport, &device_ep(), "0000")
+            .await
+        {
+            Err(ClientError::WrongPassword) => (),
+            x => panic!("Expected wrong password error, got {x:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_firmware_version_single_round_trip() {
+        let transport = MockTransport::new([frame(SmaInvGetDeviceInfo {
+            dst: SmaEndpoint::dummy(),
+            src: device_ep(),
+            counters: counters(1),
+            response: true,
+            firmware_version: Some(0x02001252),
+            ..Default::default()
+        })]);
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+
+        match sma_client
+            .get_firmware_version(&transport, &device_ep())
+            .await
+        {
+            Ok(version) => assert_eq!(0x02001252, version),
+            Err(e) => panic!("Get firmware version failed: {e:?}"),
+        }
+
+        match &transport.written()[..] {
+            [frame] => match AnySmaMessage::from_bytes(frame) {
+                Ok(AnySmaMessage::InvGetDeviceInfo(req)) => {
+                    assert!(!req.response);
+                    assert_eq!(
+                        SmaInvGetDeviceInfo::FIRST_OBJECT,
+                        req.first_idx
+                    );
+                    assert_eq!(SmaInvGetDeviceInfo::LAST_OBJECT, req.last_idx);
+                }
+                x => panic!("Expected device info request, got {x:?}"),
+            },
+            x => panic!("Expected a single request, got {x:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_firmware_version_two_step_grant() {
+        let transport = MockTransport::new([
+            frame(SmaInvGetDeviceInfo {
+                dst: SmaEndpoint::dummy(),
+                src: device_ep(),
+                counters: counters(1),
+                response: true,
+                token: Some(0xCAFE0001),
+                ..Default::default()
+            }),
+            frame(SmaInvGetDeviceInfo {
+                dst: SmaEndpoint::dummy(),
+                src: device_ep(),
+                counters: counters(2),
+                response: true,
+                firmware_version: Some(0x02001252),
+                ..Default::default()
+            }),
+        ]);
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+
+        match sma_client
+            .get_firmware_version(&transport, &device_ep())
+            .await
+        {
+            Ok(version) => assert_eq!(0x02001252, version),
+            Err(e) => panic!("Get firmware version failed: {e:?}"),
+        }
+
+        let tokens: Vec<_> = transport
+            .written()
+            .iter()
+            .map(|frame| match AnySmaMessage::from_bytes(frame) {
+                Ok(AnySmaMessage::InvGetDeviceInfo(req)) => req.token,
+                x => panic!("Expected device info request, got {x:?}"),
+            })
+            .collect();
+        assert_eq!(vec![None, Some(0xCAFE0001)], tokens);
+        assert_eq!(
+            Some(GrantToken(0xCAFE0001)),
+            transport.grant_token(&device_ep())
+        );
+        assert_eq!(None, transport.grant_token(&SmaEndpoint::dummy()));
+    }
+
+    #[tokio::test]
+    async fn get_day_data_detects_record_count_mismatch() {
+        let transport = MockTransport::new([frame(SmaInvGetDayData {
+            dst: SmaEndpoint::dummy(),
+            src: device_ep(),
+            counters: SmaInvCounter {
+                packet_id: 1,
+                fragment_id: 0,
+                first_fragment: true,
+            },
+            start_time_idx: 4,
+            end_time_idx: 7,
+            records: vec![SmaInvMeterValue {
+                timestamp: 1700000000,
+                energy_wh: 1234,
+            }],
+            response: true,
+            ..Default::default()
+        })]);
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+
+        match sma_client
+            .get_day_data(&transport, &device_ep(), 1700000000, 1700000900)
+            .await
+        {
+            Err(ClientError::RecordCountMismatch {
+                expected: 3,
+                got: 1,
+            }) => (),
+            x => panic!("Expected record count mismatch, got {x:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_day_data_reserves_announced_records() {
+        let fragment = |fragment_id: u16, start: u32, count: u32| {
+            frame(SmaInvGetDayData {
+                dst: SmaEndpoint::dummy(),
+                src: device_ep(),
+                counters: SmaInvCounter {
+                    packet_id: 1,
+                    fragment_id,
+                    first_fragment: fragment_id == 2,
+                },
+                start_time_idx: start,
+                end_time_idx: start + count,
+                records: (start..start + count)
+                    .map(|i| SmaInvMeterValue {
+                        timestamp: 1700000000 + 300 * i,
+                        energy_wh: 1234,
+                    })
+                    .collect(),
+                response: true,
+                ..Default::default()
+            })
+        };
+        let transport = MockTransport::new([
+            fragment(2, 0, 3),
+            fragment(1, 3, 3),
+            fragment(0, 6, 2),
+        ]);
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+
+        match sma_client
+            .get_day_data(&transport, &device_ep(), 1700000000, 1700002400)
+            .await
+        {
+            Ok(records) => {
+                assert_eq!(8, records.len());
+                // Three fragments of up to three records were reserved
+                // upfront and no reallocation grew the buffer.
+                assert_eq!(9, records.capacity());
+            }
+            Err(e) => panic!("Reading day data failed: {e:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_events_reassembles_fragments() {
+        let event = |timestamp, event_id| EventRecord {
+            timestamp,
+            event_id,
+            tag: 0x03A9,
+        };
+        let transport = MockTransport::new([
+            frame(SmaInvGetEvents {
+                dst: SmaEndpoint::dummy(),
+                src: device_ep(),
+                counters: SmaInvCounter {
+                    packet_id: 1,
+                    fragment_id: 1,
+                    first_fragment: true,
+                },
+                start_time_idx: 0,
+                end_time_idx: 2,
+                records: vec![event(1700000000, 10110), event(1700000300, 447)],
+                response: true,
+                ..Default::default()
+            }),
+            frame(SmaInvGetEvents {
+                dst: SmaEndpoint::dummy(),
+                src: device_ep(),
+                counters: SmaInvCounter {
+                    packet_id: 1,
+                    fragment_id: 0,
+                    first_fragment: false,
+                },
+                start_time_idx: 2,
+                end_time_idx: 3,
+                records: vec![event(1700000600, 10110)],
+                response: true,
+                ..Default::default()
+            }),
+        ]);
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+
+        match sma_client
+            .get_events(&transport, &device_ep(), 1700000000, 1700086400)
+            .await
+        {
+            Ok(records) => assert_eq!(
+                vec![
+                    event(1700000000, 10110),
+                    event(1700000300, 447),
+                    event(1700000600, 10110),
+                ],
+                records
+            ),
+            Err(e) => panic!("Reading events failed: {e:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_events_ignores_record_index_range() {
+        let transport = MockTransport::new([frame(SmaInvGetEvents {
+            dst: SmaEndpoint::dummy(),
+            src: device_ep(),
+            counters: SmaInvCounter {
+                packet_id: 1,
+                fragment_id: 0,
+                first_fragment: true,
+            },
+            start_time_idx: 0,
+            end_time_idx: 5,
+            records: vec![EventRecord {
+                timestamp: 1700000000,
+                event_id: 10110,
+                tag: 0x03A9,
+            }],
+            response: true,
+            ..Default::default()
+        })]);
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+
+        match sma_client
+            .get_events(&transport, &device_ep(), 1700000000, 1700086400)
+            .await
+        {
+            Ok(records) => assert_eq!(1, records.len()),
+            Err(e) => panic!("Reading events failed: {e:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn poll_devices_collects_per_device_results() {
+        let other_ep = SmaEndpoint {
+            susy_id: 0x1234,
+            serial: 0x9ABCDEF0,
+        };
+        let transport = MockTransport::new([
+            frame(SmaInvLogin {
+                dst: SmaEndpoint::dummy(),
+                src: device_ep(),
+                counters: counters(1),
+                response: true,
+                ..Default::default()
+            }),
+            frame(SmaInvGetDayData {
+                dst: SmaEndpoint::dummy(),
+                src: device_ep(),
+                counters: counters(2),
+                start_time_idx: 0,
+                end_time_idx: 1,
+                records: vec![SmaInvMeterValue {
+                    timestamp: 1700000000,
+                    energy_wh: 1234,
+                }],
+                response: true,
+                ..Default::default()
+            }),
+            // Packet 3 is the logout of the first device.
+            frame(SmaInvLogin {
+                dst: SmaEndpoint::dummy(),
+                src: other_ep.clone(),
+                counters: counters(4),
+                error_code: SmaInvLogin::ERROR_WRONG_PASSWORD,
+                response: true,
+                ..Default::default()
+            }),
+        ]);
+        let mut sma_client = SmaClient::new(SmaEndpoint::dummy());
+
+        let results = sma_client
+            .poll_devices(
+                &transport,
+                &[device_ep(), other_ep.clone()],
+                "0000",
+                1700000000,
+                1700000900,
+            )
+            .await;
+
+        assert_eq!(2, results.len());
+        assert_eq!(device_ep(), results[0].0);
+        match &results[0].1 {
+            Ok(records) => assert_eq!(1, records.len()),
+            Err(e) => panic!("Polling first device failed: {e:?}"),
+        }
+        assert_eq!(other_ep, results[1].0);
+        match &results[1].1 {
+            Err(ClientError::WrongPassword) => (),
+            x => panic!("Expected wrong password error, got {x:?}"),
+        }
+        // Login, day data and logout for the first device and only the
+        // failed login for the second one.
+        assert_eq!(4, transport.written().len());
+    }
+}
@@ -73,6 +73,16 @@ impl<T: AsRef<[u8]>> Cursor<T> {
         self.pos += count;
     }
 
+    /// Advances the cursor position by the given amount of bytes if enough
+    /// data is remaining. Returns [`Error::BufferTooSmall`] otherwise
+    /// and keeps the cursor position.
+    pub fn try_skip(&mut self, count: usize) -> Result<()> {
+        self.check_remaining(count)?;
+        self.pos += count;
+
+        Ok(())
+    }
+
     /// Reads data from the underlying buffer to the given slice and advances
     /// cursor position.
     /// Panics if there is not enough data remaining to fill the slice.
@@ -199,3 +209,27 @@ impl Cursor<&mut [u8]> {
         self.pos += 8;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_try_skip() {
+        let buffer = [0u8; 8];
+        let mut cursor = Cursor::new(&buffer[..]);
+
+        if let Err(e) = cursor.try_skip(6) {
+            panic!("Skipping within buffer failed: {e:?}");
+        }
+        match cursor.try_skip(4) {
+            Err(Error::BufferTooSmall {
+                size: 8,
+                expected: 10,
+            }) => (),
+            x => panic!("Skipped past buffer end: {x:?}"),
+        }
+        assert_eq!(6, cursor.position());
+        assert_eq!(2, cursor.remaining());
+    }
+}
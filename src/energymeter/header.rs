@@ -15,7 +15,7 @@
     You should have received a copy of the GNU Affero General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
-use super::{Cursor, Result, SmaEndpoint, SmaSerde};
+use super::{Cursor, Result, SmaEndpoint, SmaPacketHeader, SmaSerde};
 use byteorder::BigEndian;
 #[cfg(not(feature = "std"))]
 use core::{
@@ -38,9 +38,38 @@ pub struct SmaEmHeader {
 impl SmaEmHeader {
     /// Serialized length of the energymeter sub-protocol header.
     pub const LENGTH: usize = 10;
+
+    /// Extracts the source endpoint of a serialized energymeter message
+    /// without decoding its OBIS payload, e.g. for cheap filtering of
+    /// multicast frames by source. Only the common packet header is
+    /// validated.
+    pub fn peek_src(buf: &[u8]) -> Result<SmaEndpoint> {
+        let mut buffer = Cursor::new(buf);
+        buffer.check_remaining(SmaPacketHeader::LENGTH + Self::LENGTH)?;
+
+        let header = SmaPacketHeader::deserialize(&mut buffer)?;
+        header.check_protocol(SmaPacketHeader::SMA_PROTOCOL_EM)?;
+
+        SmaEndpoint::deserialize(&mut buffer)
+    }
+}
+
+/// Returns the elapsed milliseconds between two energymeter timestamps.
+/// The 32bit millisecond timestamp overflows about every 49.7 days,
+/// this function handles a single rollover between `older` and `newer`.
+///
+/// This is a helper for applications which compute the interval between
+/// received messages. The client only compares timestamps for equality
+/// to detect duplicate frames.
+pub fn wrapping_ms_delta(newer: u32, older: u32) -> u32 {
+    newer.wrapping_sub(older)
 }
 
 impl SmaSerde for SmaEmHeader {
+    fn serialized_len(&self) -> usize {
+        Self::LENGTH
+    }
+
     fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
         buffer.check_remaining(Self::LENGTH)?;
 
@@ -63,6 +92,11 @@ impl SmaSerde for SmaEmHeader {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        energymeter::{ObisValue, SmaEmMessage},
+        inverter::SmaInvLogout,
+        Error,
+    };
 
     #[test]
     fn test_sma_em_header_serialization() {
@@ -87,6 +121,7 @@ mod tests {
             0x00, 0x0F, 0x42, 0x40,
         ];
         assert_eq!(SmaEmHeader::LENGTH, cursor.position());
+        assert_eq!(header.serialized_len(), cursor.position());
         assert_eq!(expected, buffer);
     }
 
@@ -116,4 +151,59 @@ mod tests {
             }
         };
     }
+
+    #[test]
+    fn test_wrapping_ms_delta() {
+        assert_eq!(1000, wrapping_ms_delta(2000, 1000));
+        assert_eq!(0, wrapping_ms_delta(1000, 1000));
+        assert_eq!(1, wrapping_ms_delta(0, u32::MAX));
+        assert_eq!(1500, wrapping_ms_delta(1000, u32::MAX - 499));
+    }
+
+    #[test]
+    fn test_sma_em_header_peek_src() {
+        let mut message = SmaEmMessage {
+            src: SmaEndpoint {
+                susy_id: 0x015D,
+                serial: 1901439139,
+            },
+            timestamp_ms: 1_000_000,
+            ..Default::default()
+        };
+        #[allow(clippy::let_unit_value)]
+        let _ = message.payload.push(ObisValue {
+            id: 0x010400,
+            value: 1000,
+        });
+
+        let mut buffer = [0u8; 48];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaEmMessage serialization failed: {e:?}");
+        }
+
+        let mut cursor = Cursor::new(&buffer[..]);
+        let decoded = match SmaEmMessage::deserialize(&mut cursor) {
+            Ok(x) => x,
+            Err(e) => panic!("SmaEmMessage deserialization failed: {e:?}"),
+        };
+        match SmaEmHeader::peek_src(&buffer) {
+            Ok(src) => assert_eq!(decoded.src, src),
+            Err(e) => panic!("Peeking source failed: {e:?}"),
+        }
+        match SmaEmHeader::peek_src(&buffer[..20]) {
+            Err(Error::BufferTooSmall { .. }) => (),
+            x => panic!("Peeked source of truncated frame: {x:?}"),
+        }
+
+        let mut buffer = [0u8; SmaInvLogout::LENGTH];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = SmaInvLogout::default().serialize(&mut cursor) {
+            panic!("SmaInvLogout serialization failed: {e:?}");
+        }
+        match SmaEmHeader::peek_src(&buffer) {
+            Err(Error::UnsupportedProtocol { .. }) => (),
+            x => panic!("Peeked source of inverter frame: {x:?}"),
+        }
+    }
 }
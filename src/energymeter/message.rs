@@ -16,8 +16,8 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
 use super::{
-    Cursor, Error, ObisValue, Result, SmaEmHeader, SmaEndpoint,
-    SmaPacketFooter, SmaPacketHeader, SmaSerde,
+    Cursor, DeserializeOptions, Error, ObisValue, PhaseData, Result,
+    SmaEmHeader, SmaEndpoint, SmaPacketFooter, SmaPacketHeader, SmaSerde,
 };
 #[cfg(not(feature = "std"))]
 use heapless::Vec;
@@ -46,8 +46,12 @@ impl SmaEmMessage {
         Self::LENGTH_MIN + Self::MAX_RECORD_COUNT * ObisValue::LENGTH_MAX;
     /// Maximum number of OBIS values in the payload.
     pub const MAX_RECORD_COUNT: usize = 80;
+    /// Minimum data length of a received energymeter message as declared
+    /// in the common packet header. This covers the energymeter header
+    /// and at least one OBIS value.
+    const DATA_LEN_MIN: usize = SmaEmHeader::LENGTH + ObisValue::LENGTH_MIN;
 
-    /// Returns total serialized message length.
+    /// Serialized length of this message, see [`SmaSerde::serialized_len`].
     pub fn serialized_len(&self) -> usize {
         Self::LENGTH_MIN
             + self
@@ -56,22 +60,104 @@ impl SmaEmMessage {
                 .map(ObisValue::serialized_len)
                 .sum::<usize>()
     }
-}
 
-impl SmaSerde for SmaEmMessage {
-    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+    /// Checks that the message can be serialized, i.e. the payload length
+    /// and all contained OBIS IDs are valid.
+    pub fn validate(&self) -> Result<()> {
         if self.payload.len() > Self::MAX_RECORD_COUNT {
             return Err(Error::PayloadTooLarge {
                 len: self.payload.len(),
             });
         }
 
+        for obis in &self.payload {
+            obis.validate()?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if at least one more OBIS value fits into the payload.
+    pub fn has_capacity(&self) -> bool {
+        self.remaining_capacity() != 0
+    }
+
+    /// Returns the number of OBIS values which can be added to the payload
+    /// before it exceeds [`Self::MAX_RECORD_COUNT`].
+    pub fn remaining_capacity(&self) -> usize {
+        Self::MAX_RECORD_COUNT.saturating_sub(self.payload.len())
+    }
+
+    /// Splits the message into messages with at most
+    /// [`Self::MAX_RECORD_COUNT`] OBIS values each. All messages share the
+    /// source and timestamp of the original message.
+    #[cfg(feature = "std")]
+    pub fn split(self) -> Vec<SmaEmMessage> {
+        if self.payload.len() <= Self::MAX_RECORD_COUNT {
+            return vec![self];
+        }
+
+        self.payload
+            .chunks(Self::MAX_RECORD_COUNT)
+            .map(|payload| Self {
+                src: self.src.clone(),
+                timestamp_ms: self.timestamp_ms,
+                payload: payload.to_vec(),
+            })
+            .collect()
+    }
+
+    /// Returns true if the payload contains a value with the given OBIS ID.
+    pub fn contains_obis(&self, id: u32) -> bool {
+        self.payload.iter().any(|obis| obis.id == id)
+    }
+
+    /// Returns the payload as a map from OBIS ID to value.
+    /// If an ID occurs multiple times, the last value is kept.
+    #[cfg(feature = "std")]
+    pub fn as_map(&self) -> std::collections::HashMap<u32, u64> {
+        self.payload
+            .iter()
+            .map(|obis| (obis.id, obis.value))
+            .collect()
+    }
+
+    /// Returns the known per-phase power and energy values of the payload.
+    pub fn phases(&self) -> PhaseData {
+        PhaseData::from_payload(&self.payload)
+    }
+
+    /// Compares two messages ignoring the timestamp.
+    pub fn payload_eq(&self, other: &Self) -> bool {
+        self.src == other.src && self.payload == other.payload
+    }
+
+    /// Creates a copy of a received message with the given source, e.g.
+    /// for relaying or echoing the values of another energymeter.
+    pub fn reply(src: SmaEndpoint, received: &SmaEmMessage) -> SmaEmMessage {
+        Self {
+            src,
+            timestamp_ms: received.timestamp_ms,
+            payload: received.payload.clone(),
+        }
+    }
+}
+
+impl SmaSerde for SmaEmMessage {
+    fn serialized_len(&self) -> usize {
+        SmaEmMessage::serialized_len(self)
+    }
+
+    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+        self.validate()?;
+
         let len = self.serialized_len();
         buffer.check_remaining(len)?;
 
         let header = SmaPacketHeader {
             data_len: len - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH,
             protocol: SmaPacketHeader::SMA_PROTOCOL_EM,
+            ..Default::default()
         };
 
         let em_header = SmaEmHeader {
@@ -83,7 +169,6 @@ impl SmaSerde for SmaEmMessage {
         em_header.serialize(buffer)?;
 
         for obis in &self.payload {
-            obis.validate()?;
             obis.serialize(buffer)?;
         }
 
@@ -93,10 +178,23 @@ impl SmaSerde for SmaEmMessage {
     }
 
     fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
-        buffer.check_remaining(Self::LENGTH_MIN)?;
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
 
-        let header = SmaPacketHeader::deserialize(buffer)?;
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        buffer.check_remaining(options.min_len(Self::LENGTH_MIN))?;
+
+        let header = SmaPacketHeader::deserialize_with(buffer, options)?;
         header.check_protocol(SmaPacketHeader::SMA_PROTOCOL_EM)?;
+        if header.data_len < Self::DATA_LEN_MIN {
+            return Err(Error::InvalidDataLen {
+                len: header.data_len,
+                expected: Self::DATA_LEN_MIN,
+            });
+        }
         buffer.check_remaining(header.data_len)?;
         let padding_len = buffer.remaining() - header.data_len;
 
@@ -104,8 +202,27 @@ impl SmaSerde for SmaEmMessage {
 
         let mut payload = Vec::default();
         while buffer.remaining() - padding_len >= ObisValue::LENGTH_MIN {
-            let obis = ObisValue::deserialize(buffer)?;
-            obis.validate()?;
+            let limit = buffer.remaining() - padding_len;
+            let obis = match ObisValue::deserialize_bounded(
+                buffer,
+                limit,
+                options.obis_rules,
+            ) {
+                Ok(obis) => obis,
+                Err(Error::UnsupportedObisId { id })
+                    if options.lenient_obis =>
+                {
+                    // Only zero padding may follow, values after an unknown
+                    // ID would be dropped silently.
+                    let start = buffer.position();
+                    if (0..limit).any(|_| buffer.read_u8() != 0) {
+                        buffer.set_position(start);
+                        return Err(Error::UnsupportedObisId { id });
+                    }
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
 
             #[cfg(feature = "std")]
             payload.push(obis);
@@ -117,7 +234,7 @@ impl SmaSerde for SmaEmMessage {
             }
         }
 
-        SmaPacketFooter::deserialize(buffer)?;
+        SmaPacketFooter::deserialize_with(buffer, options)?;
 
         let message = Self {
             src: em_header.src,
@@ -180,6 +297,7 @@ mod tests {
             0x00, 0x00, 0x00, 0x00,
         ];
         assert_eq!(60, cursor.position());
+        assert_eq!(message.serialized_len(), cursor.position());
         assert_eq!(expected, buffer);
     }
 
@@ -232,4 +350,261 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_sma_em_message_capacity() {
+        let message = |count| SmaEmMessage {
+            src: SmaEndpoint::dummy(),
+            timestamp_ms: 0xAABBCCDD,
+            payload: (0..count)
+                .map(|value| ObisValue {
+                    id: 0x010400,
+                    value,
+                })
+                .collect(),
+        };
+
+        let full = message(80);
+        assert!(!full.has_capacity());
+        assert_eq!(0, full.remaining_capacity());
+
+        let almost_full = message(79);
+        assert!(almost_full.has_capacity());
+        assert_eq!(1, almost_full.remaining_capacity());
+
+        #[cfg(feature = "std")]
+        {
+            let overfull = message(81);
+            assert!(!overfull.has_capacity());
+            assert_eq!(0, overfull.remaining_capacity());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_sma_em_message_split() {
+        let message = SmaEmMessage {
+            src: SmaEndpoint::dummy(),
+            timestamp_ms: 0xAABBCCDD,
+            payload: (0..150)
+                .map(|value| ObisValue {
+                    id: 0x010400,
+                    value,
+                })
+                .collect(),
+        };
+
+        let messages = message.clone().split();
+        assert_eq!(2, messages.len());
+        assert_eq!(80, messages[0].payload.len());
+        assert_eq!(70, messages[1].payload.len());
+        for part in &messages {
+            assert_eq!(message.src, part.src);
+            assert_eq!(message.timestamp_ms, part.timestamp_ms);
+            if let Err(e) = part.validate() {
+                panic!("Split message failed validation: {e:?}");
+            }
+        }
+        assert_eq!(
+            message.payload,
+            messages
+                .into_iter()
+                .flat_map(|part| part.payload)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_sma_em_message_truncated_deserialization() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x0C, 0x00, 0x10,
+            0x60, 0x69,
+            0xDE, 0xAD,
+            0xDE, 0xAD, 0xBE, 0xEF,
+            0xAA, 0xBB, 0xCC, 0xDD,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaEmMessage::deserialize(&mut cursor) {
+            Err(Error::InvalidDataLen {
+                len: 10,
+                expected: 18,
+            }) => (),
+            x => panic!("Deserialized truncated message as {x:?}"),
+        }
+
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x28, 0x00, 0x10,
+            0x60, 0x69,
+            0xDE, 0xAD,
+            0xDE, 0xAD, 0xBE, 0xEF,
+            0xAA, 0xBB, 0xCC, 0xDD,
+            0x00, 0x01, 0x04, 0x00, 0x01, 0x02, 0x03, 0x04,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaEmMessage::deserialize(&mut cursor) {
+            Err(Error::BufferTooSmall { .. }) => (),
+            x => panic!("Deserialized truncated message as {x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_sma_em_message_obis_exceeds_data_len() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x10,
+            0x60, 0x69,
+            0xDE, 0xAD,
+            0xDE, 0xAD, 0xBE, 0xEF,
+            0xAA, 0xBB, 0xCC, 0xDD,
+            0x00, 0x01, 0x08, 0x00, 0x10, 0x20, 0x30, 0x40,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaEmMessage::deserialize(&mut cursor) {
+            Err(Error::BufferTooSmall {
+                size: 8,
+                expected: 12,
+            }) => (),
+            x => panic!("Deserialized truncated OBIS value as {x:?}"),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_sma_em_message_map_roundtrip() {
+        let map = std::collections::HashMap::from([
+            (0x010400, 1000),
+            (0x020800, 0x123456789),
+            (ObisValue::SOFTWARE_VERSION_ID, 0x02001252),
+        ]);
+        let payload = match map
+            .clone()
+            .into_iter()
+            .map(ObisValue::try_from)
+            .collect::<Result<Vec<_>>>()
+        {
+            Ok(x) => x,
+            Err(e) => panic!("Building payload failed: {e:?}"),
+        };
+        let message = SmaEmMessage {
+            src: SmaEndpoint::dummy(),
+            timestamp_ms: 0,
+            payload,
+        };
+
+        let serialized = match message.to_vec() {
+            Ok(x) => x,
+            Err(e) => panic!("SmaEmMessage serialization failed: {e:?}"),
+        };
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaEmMessage::deserialize(&mut cursor) {
+            Err(e) => panic!("SmaEmMessage deserialization failed: {e:?}"),
+            Ok(message) => assert_eq!(map, message.as_map()),
+        }
+
+        match ObisValue::try_from((0x00FF00, 0)) {
+            Err(Error::UnsupportedObisId { id: 0x00FF00 }) => (),
+            x => panic!("Expected unsupported OBIS ID, got {x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_sma_em_message_reply() {
+        let mut received = SmaEmMessage {
+            src: SmaEndpoint {
+                susy_id: 0x015D,
+                serial: 1901439139,
+            },
+            timestamp_ms: 0x12345678,
+            ..Default::default()
+        };
+        #[allow(clippy::let_unit_value)]
+        let _ = received.payload.push(ObisValue {
+            id: 0x010400,
+            value: 1000,
+        });
+        #[allow(clippy::let_unit_value)]
+        let _ = received.payload.push(ObisValue {
+            id: 0x020800,
+            value: 0x123456789,
+        });
+
+        let reply = SmaEmMessage::reply(SmaEndpoint::dummy(), &received);
+        assert_eq!(SmaEndpoint::dummy(), reply.src);
+        assert_ne!(received, reply);
+        assert_eq!(
+            SmaEmMessage {
+                src: SmaEndpoint::dummy(),
+                ..received
+            },
+            reply
+        );
+    }
+
+    #[test]
+    fn test_sma_em_message_lenient_obis_deserialization() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x28, 0x00, 0x10,
+            0x60, 0x69,
+            0xDE, 0xAD,
+            0xDE, 0xAD, 0xBE, 0xEF,
+            0xAA, 0xBB, 0xCC, 0xDD,
+            0x00, 0x01, 0x04, 0x00, 0x00, 0x00, 0x03, 0xE8,
+            0x00, 0x02, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0xD0,
+            0x00, 0x02, 0x06, 0x00, 0x12, 0x34, 0x56, 0x78,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaEmMessage::deserialize(&mut cursor) {
+            Err(Error::UnsupportedObisId { id: 0x00020600 }) => (),
+            x => panic!("Deserialized unknown OBIS ID as {x:?}"),
+        }
+
+        let options = DeserializeOptions {
+            lenient_obis: true,
+            ..Default::default()
+        };
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaEmMessage::deserialize_with(&mut cursor, &options) {
+            Err(Error::UnsupportedObisId { id: 0x00020600 }) => (),
+            x => panic!("Dropped the trailing OBIS value: {x:?}"),
+        }
+
+        let mut padded = serialized;
+        padded[48..56].fill(0);
+        let mut cursor = Cursor::new(&padded[..]);
+        match SmaEmMessage::deserialize_with(&mut cursor, &options) {
+            Err(e) => panic!("SmaEmMessage deserialization failed: {e:?}"),
+            Ok(message) => {
+                assert_eq!(
+                    &[
+                        ObisValue {
+                            id: 0x010400,
+                            value: 1000,
+                        },
+                        ObisValue {
+                            id: 0x020800,
+                            value: 2000,
+                        },
+                    ],
+                    &message.payload[..]
+                );
+                assert_eq!(padded.len(), cursor.position());
+            }
+        }
+    }
 }
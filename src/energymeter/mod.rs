@@ -19,14 +19,16 @@
 //! Module for handling the SMA speedwire energy meter sub protocol.
 
 use super::{
-    Cursor, Error, Result, SmaEndpoint, SmaPacketFooter, SmaPacketHeader,
-    SmaSerde,
+    Cursor, DeserializeOptions, Error, Result, SmaEndpoint, SmaPacketFooter,
+    SmaPacketHeader, SmaSerde,
 };
 
 mod header;
 mod message;
 mod obis;
+mod phases;
 
-use header::SmaEmHeader;
+pub use header::{wrapping_ms_delta, SmaEmHeader};
 pub use message::SmaEmMessage;
-pub use obis::ObisValue;
+pub use obis::{ObisRule, ObisValue};
+pub use phases::{PhaseData, PhaseValues};
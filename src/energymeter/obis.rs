@@ -15,8 +15,43 @@
     You should have received a copy of the GNU Affero General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
-use super::{Cursor, Error, Result, SmaSerde};
+use super::{Cursor, DeserializeOptions, Error, Result, SmaSerde};
 use byteorder::BigEndian;
+#[cfg(not(feature = "std"))]
+use core::{
+    iter::Iterator,
+    option::Option::{self, None, Some},
+    result::Result::{Err, Ok},
+};
+
+/// Additional rule for OBIS IDs which are not supported by the built-in
+/// tables, e.g. channels of newer firmware versions, see
+/// [`DeserializeOptions::obis_rules`].
+///
+/// All IDs with `id & mask == pattern` are deserialized with `len` bytes,
+/// which must be either [`ObisValue::LENGTH_MIN`] for 32bit values or
+/// [`ObisValue::LENGTH_MAX`] for 64bit values. Values with such IDs can
+/// not be serialized.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ObisRule {
+    pub mask: u32,
+    pub pattern: u32,
+    pub len: usize,
+}
+
+impl ObisRule {
+    /// Returns the serialized length of the first rule matching the given
+    /// OBIS ID or `None` if no rule matches.
+    fn find(rules: &[Self], id: u32) -> Option<Result<usize>> {
+        rules
+            .iter()
+            .find(|rule| id & rule.mask == rule.pattern)
+            .map(|rule| match rule.len {
+                ObisValue::LENGTH_MIN | ObisValue::LENGTH_MAX => Ok(rule.len),
+                len => Err(Error::UnsupportedObisLength { len }),
+            })
+    }
+}
 
 /// A tuple consisting of an OBIS ID and its value.
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -35,60 +70,356 @@ impl ObisValue {
     pub const LENGTH_MAX: usize = 12;
 
     /// Serialized length of this OBIS value.
+    /// Returns zero for unsupported OBIS IDs.
     pub fn serialized_len(&self) -> usize {
-        if self.id == 0x90000000 || self.id & 0xFF00 == 0x0400 {
-            8
-        } else if self.id & 0xFF00 == 0x0800 {
-            12
-        } else {
-            0
-        }
+        Self::obis_len(self.id).unwrap_or(0)
     }
 
-    /// Checks is the OBIS ID is valid and supported.
+    /// Checks is the OBIS ID is valid and supported and that the value
+    /// fits into the serialized size of the ID without truncation.
     pub fn validate(&self) -> Result<()> {
-        if self.id == 0x90000000
-            || self.id & 0xFF00 == 0x0400
-            || self.id & 0xFF00 == 0x0800
+        match Self::obis_len(self.id) {
+            Some(Self::LENGTH_MIN) if self.value > u32::MAX as u64 => {
+                Err(Error::ObisValueOutOfRange {
+                    id: self.id,
+                    value: self.value,
+                })
+            }
+            Some(_) => Ok(()),
+            None => Err(Error::UnsupportedObisId { id: self.id }),
+        }
+    }
+
+    /// Mask of the measurement type byte of an OBIS ID.
+    const TYPE_MASK: u32 = 0xFF00;
+    /// Measurement type of 32bit instantaneous values.
+    const TYPE_ACTUAL: u32 = 0x0400;
+    /// Measurement type of 64bit counter values.
+    const TYPE_COUNTER: u32 = 0x0800;
+
+    /// Returns the serialized length of the given OBIS ID
+    /// or `None` if it is unsupported.
+    ///
+    /// The width only depends on the measurement type, not on the
+    /// measurand. This covers all power and energy measurands emitted by
+    /// energymeters and home managers, i.e. active (1/2), reactive (3/4)
+    /// and apparent (9/10) power of the total and per-phase channels as
+    /// well as power factor, frequency, current and voltage.
+    fn obis_len(id: u32) -> Option<usize> {
+        if id == Self::SOFTWARE_VERSION_ID
+            || id & Self::TYPE_MASK == Self::TYPE_ACTUAL
         {
-            Ok(())
+            return Some(Self::LENGTH_MIN);
+        } else if id & Self::TYPE_MASK == Self::TYPE_COUNTER {
+            return Some(Self::LENGTH_MAX);
+        }
+
+        None
+    }
+
+    /// OBIS ID of the packed software version value.
+    pub const SOFTWARE_VERSION_ID: u32 = 0x90000000;
+
+    /// Decodes the packed software version of the device in the form
+    /// `major.minor.build.revision`, e.g. `0x02001252` as `2.0.18.R`.
+    /// Returns `None` if this is not a software version value.
+    #[cfg(feature = "std")]
+    pub fn software_version(&self) -> Option<String> {
+        if self.id != Self::SOFTWARE_VERSION_ID {
+            return None;
+        }
+
+        let [major, minor, build, revision] = (self.value as u32).to_be_bytes();
+        let revision = if revision.is_ascii_alphabetic() {
+            char::from(revision).to_string()
         } else {
-            Err(Error::UnsupportedObisId { id: self.id })
+            revision.to_string()
+        };
+
+        Some(format!("{major}.{minor}.{build}.{revision}"))
+    }
+
+    /// Deserializes an OBIS value which must fit into the next `limit`
+    /// bytes of the buffer, e.g. the remaining data length of a message
+    /// excluding the footer. IDs which are not supported by the built-in
+    /// tables are looked up in `rules`.
+    pub(crate) fn deserialize_bounded(
+        buffer: &mut Cursor<&[u8]>,
+        limit: usize,
+        rules: &[ObisRule],
+    ) -> Result<Self> {
+        let size = limit.min(buffer.remaining());
+        if size < Self::LENGTH_MIN {
+            return Err(Error::BufferTooSmall {
+                size,
+                expected: Self::LENGTH_MIN,
+            });
         }
+
+        let id = buffer.peek_u32::<BigEndian>(0);
+        let len = match Self::obis_len(id) {
+            Some(len) => len,
+            None => ObisRule::find(rules, id)
+                .ok_or(Error::UnsupportedObisId { id })??,
+        };
+        if size < len {
+            return Err(Error::BufferTooSmall {
+                size,
+                expected: len,
+            });
+        }
+
+        buffer.skip(4);
+        let value = if len == Self::LENGTH_MAX {
+            buffer.read_u64::<BigEndian>()
+        } else {
+            buffer.read_u32::<BigEndian>() as u64
+        };
+
+        Ok(Self { id, value })
+    }
+}
+
+/// Creates a validated OBIS value from an `(id, value)` pair.
+/// Unsupported IDs are rejected with [`Error::UnsupportedObisId`].
+///
+/// This allows collecting pairs into a payload, e.g.
+/// `pairs.into_iter().map(ObisValue::try_from).collect::<Result<Vec<_>>>()`.
+impl TryFrom<(u32, u64)> for ObisValue {
+    type Error = Error;
+
+    fn try_from((id, value): (u32, u64)) -> Result<Self> {
+        let obis = Self { id, value };
+        obis.validate()?;
+
+        Ok(obis)
     }
 }
 
 impl SmaSerde for ObisValue {
+    fn serialized_len(&self) -> usize {
+        ObisValue::serialized_len(self)
+    }
+
     fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
         self.validate()?;
         buffer.check_remaining(self.serialized_len())?;
 
         buffer.write_u32::<BigEndian>(self.id);
-        if self.id == 0x90000000 || self.id & 0xFF00 == 0x0400 {
-            buffer.write_u32::<BigEndian>(self.value as u32);
-        } else if self.id & 0xFF00 == 0x0800 {
+        if self.serialized_len() == Self::LENGTH_MAX {
             buffer.write_u64::<BigEndian>(self.value);
+        } else {
+            buffer.write_u32::<BigEndian>(self.value as u32);
         }
 
         Ok(())
     }
 
     fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
-        buffer.check_remaining(Self::LENGTH_MIN)?;
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
 
-        let id = buffer.read_u32::<BigEndian>();
-        let value = if id == 0x90000000 || id & 0xFF00 == 0x0400 {
-            buffer.read_u32::<BigEndian>() as u64
-        } else if id & 0xFF00 == 0x0800 {
-            buffer.check_remaining(8)?;
-            buffer.read_u64::<BigEndian>()
-        } else {
-            return Err(Error::UnsupportedObisId { id });
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        Self::deserialize_bounded(
+            buffer,
+            buffer.remaining(),
+            options.obis_rules,
+        )
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::energymeter::SmaEmMessage;
+
+    #[test]
+    fn test_obis_software_version() {
+        let version = ObisValue {
+            id: 0x90000000,
+            value: 0x02001252,
+        };
+        assert_eq!(Some("2.0.18.R".to_string()), version.software_version());
+
+        let power = ObisValue {
+            id: 0x010400,
+            value: 0x02001252,
+        };
+        assert_eq!(None, power.software_version());
+    }
+
+    #[test]
+    fn test_obis_validate_value_range() {
+        let obis = ObisValue {
+            id: 0x010400,
+            value: 0x1_0000_0000,
+        };
+        match obis.validate() {
+            Err(Error::ObisValueOutOfRange {
+                id: 0x010400,
+                value: 0x1_0000_0000,
+            }) => (),
+            x => panic!("Expected value out of range, got {x:?}"),
+        }
+        let mut buffer = [0u8; ObisValue::LENGTH_MAX];
+        if obis.serialize(&mut Cursor::new(&mut buffer[..])).is_ok() {
+            panic!("Serialized truncated OBIS value");
+        }
+
+        let counter = ObisValue {
+            id: 0x010800,
+            value: 0x1_0000_0000,
         };
+        if let Err(e) = counter.validate() {
+            panic!("Validating 64bit OBIS value failed: {e:?}");
+        }
+    }
+
+    #[test]
+    fn test_obis_reactive_apparent_widths() {
+        #[rustfmt::skip]
+        let serialized = [
+            // Reactive power Q+ and Q- actual values.
+            0x00, 0x03, 0x04, 0x00, 0x00, 0x00, 0x04, 0xD2,
+            0x00, 0x04, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // Reactive energy Q+ and Q- counters.
+            0x00, 0x03, 0x08, 0x00,
+            0x00, 0x00, 0x00, 0x01, 0x2A, 0x05, 0xF2, 0x00,
+            0x00, 0x04, 0x08, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x3B, 0x9A, 0xCA, 0x00,
+            // Apparent power S+ and S- actual values.
+            0x00, 0x09, 0x04, 0x00, 0x00, 0x00, 0x16, 0x2E,
+            0x00, 0x0A, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // Apparent energy S+ and S- counters.
+            0x00, 0x09, 0x08, 0x00,
+            0x00, 0x00, 0x00, 0x02, 0x54, 0x0B, 0xE4, 0x00,
+            0x00, 0x0A, 0x08, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x77, 0x35, 0x94, 0x00,
+            // L1 reactive and apparent power actual values.
+            0x00, 0x17, 0x04, 0x00, 0x00, 0x00, 0x01, 0x9A,
+            0x00, 0x1D, 0x04, 0x00, 0x00, 0x00, 0x07, 0x64,
+        ];
+        let expected = [
+            ObisValue {
+                id: 0x00030400,
+                value: 1234,
+            },
+            ObisValue {
+                id: 0x00040400,
+                value: 0,
+            },
+            ObisValue {
+                id: 0x00030800,
+                value: 5000000000,
+            },
+            ObisValue {
+                id: 0x00040800,
+                value: 1000000000,
+            },
+            ObisValue {
+                id: 0x00090400,
+                value: 5678,
+            },
+            ObisValue {
+                id: 0x000A0400,
+                value: 0,
+            },
+            ObisValue {
+                id: 0x00090800,
+                value: 10000000000,
+            },
+            ObisValue {
+                id: 0x000A0800,
+                value: 2000000000,
+            },
+            ObisValue {
+                id: 0x00170400,
+                value: 410,
+            },
+            ObisValue {
+                id: 0x001D0400,
+                value: 1892,
+            },
+        ];
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        for obis in &expected {
+            match ObisValue::deserialize(&mut cursor) {
+                Err(e) => panic!("ObisValue deserialization failed: {e:?}"),
+                Ok(value) => assert_eq!(*obis, value),
+            }
+        }
+        assert_eq!(0, cursor.remaining());
+
+        let mut buffer = [0u8; 96];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        for obis in &expected {
+            if let Err(e) = obis.serialize(&mut cursor) {
+                panic!("ObisValue serialization failed: {e:?}");
+            }
+        }
+        assert_eq!(serialized.len(), cursor.position());
+        assert_eq!(serialized, buffer[..serialized.len()]);
+    }
 
-        let obj = Self { id, value };
-        obj.validate()?;
+    #[test]
+    fn test_obis_custom_rule() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x10,
+            0x60, 0x69,
+            0xDE, 0xAD,
+            0xDE, 0xAD, 0xBE, 0xEF,
+            0xAA, 0xBB, 0xCC, 0xDD,
+            0x00, 0x01, 0x05, 0x00, 0x00, 0x00, 0x12, 0x34,
+            0x00, 0x00, 0x00, 0x00,
+        ];
 
-        Ok(obj)
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaEmMessage::deserialize(&mut cursor) {
+            Err(Error::UnsupportedObisId { id: 0x00010500 }) => (),
+            x => panic!("Deserialized unregistered OBIS ID as {x:?}"),
+        }
+
+        let options = DeserializeOptions {
+            obis_rules: &[ObisRule {
+                mask: 0xFFFFFFFF,
+                pattern: 0x00010500,
+                len: 6,
+            }],
+            ..Default::default()
+        };
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaEmMessage::deserialize_with(&mut cursor, &options) {
+            Err(Error::UnsupportedObisLength { len: 6 }) => (),
+            x => panic!("Deserialized invalid OBIS length as {x:?}"),
+        }
+
+        let options = DeserializeOptions {
+            obis_rules: &[ObisRule {
+                mask: 0xFFFFFFFF,
+                pattern: 0x00010500,
+                len: 8,
+            }],
+            ..Default::default()
+        };
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaEmMessage::deserialize_with(&mut cursor, &options) {
+            Err(e) => panic!("SmaEmMessage deserialization failed: {e:?}"),
+            Ok(message) => {
+                assert_eq!(
+                    &[ObisValue {
+                        id: 0x00010500,
+                        value: 0x1234,
+                    }],
+                    &message.payload[..]
+                );
+                assert_eq!(serialized.len(), cursor.position());
+            }
+        }
     }
 }
@@ -0,0 +1,182 @@
+/******************************************************************************\
+    sma-proto - A SMA Speedwire protocol library
+    Copyright (C) 2024 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+
+use super::ObisValue;
+#[cfg(not(feature = "std"))]
+use core::{
+    clone::Clone,
+    cmp::{Eq, PartialEq},
+    default::Default,
+    fmt::Debug,
+    marker::Copy,
+    option::Option::{self, Some},
+    prelude::rust_2021::derive,
+};
+
+/// Active and reactive power and energy of a single phase or the sum of
+/// all phases. Power values are in units of 0.1 W or 0.1 var and energy
+/// values in units of Ws or vars. Values missing in the payload are `None`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PhaseValues {
+    /// Imported active power.
+    pub active_power_import: Option<u64>,
+    /// Exported active power.
+    pub active_power_export: Option<u64>,
+    /// Imported reactive power.
+    pub reactive_power_import: Option<u64>,
+    /// Exported reactive power.
+    pub reactive_power_export: Option<u64>,
+    /// Imported active energy counter.
+    pub active_energy_import: Option<u64>,
+    /// Exported active energy counter.
+    pub active_energy_export: Option<u64>,
+    /// Imported reactive energy counter.
+    pub reactive_energy_import: Option<u64>,
+    /// Exported reactive energy counter.
+    pub reactive_energy_export: Option<u64>,
+}
+
+/// Energy meter measurands grouped by phase,
+/// see [`super::SmaEmMessage::phases`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PhaseData {
+    /// Sum of all phases.
+    pub total: PhaseValues,
+    /// Phase L1.
+    pub l1: PhaseValues,
+    /// Phase L2.
+    pub l2: PhaseValues,
+    /// Phase L3.
+    pub l3: PhaseValues,
+}
+
+impl PhaseData {
+    /// Distance between the measurand indices of two phases.
+    const PHASE_STRIDE: u32 = 20;
+    /// OBIS type of instantaneous values.
+    const TYPE_ACTUAL: u32 = 0x04;
+    /// OBIS type of counter values.
+    const TYPE_COUNTER: u32 = 0x08;
+
+    /// Buckets the known per-phase OBIS values of the payload.
+    /// Unknown IDs are ignored.
+    pub fn from_payload(payload: &[ObisValue]) -> Self {
+        let mut data = Self::default();
+        for obis in payload {
+            // IDs are encoded as channel, measurand, type and tariff bytes.
+            if obis.id & 0xFF0000FF != 0 {
+                continue;
+            }
+            let measurand = (obis.id >> 16) & 0xFF;
+            let kind = (obis.id >> 8) & 0xFF;
+
+            let phase = match measurand / Self::PHASE_STRIDE {
+                0 => &mut data.total,
+                1 => &mut data.l1,
+                2 => &mut data.l2,
+                3 => &mut data.l3,
+                _ => continue,
+            };
+            let field = match (measurand % Self::PHASE_STRIDE, kind) {
+                (1, Self::TYPE_ACTUAL) => &mut phase.active_power_import,
+                (2, Self::TYPE_ACTUAL) => &mut phase.active_power_export,
+                (3, Self::TYPE_ACTUAL) => &mut phase.reactive_power_import,
+                (4, Self::TYPE_ACTUAL) => &mut phase.reactive_power_export,
+                (1, Self::TYPE_COUNTER) => &mut phase.active_energy_import,
+                (2, Self::TYPE_COUNTER) => &mut phase.active_energy_export,
+                (3, Self::TYPE_COUNTER) => &mut phase.reactive_energy_import,
+                (4, Self::TYPE_COUNTER) => &mut phase.reactive_energy_export,
+                _ => continue,
+            };
+            *field = Some(obis.value);
+        }
+
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{energymeter::SmaEmMessage, SmaEndpoint};
+    #[cfg(not(feature = "std"))]
+    use heapless::Vec;
+
+    #[test]
+    fn test_phase_data_from_full_payload() {
+        let mut payload = Vec::new();
+        for base in [0, 20, 40, 60] {
+            for measurand in 1..=4 {
+                let id = (base + measurand) << 16;
+                #[allow(clippy::let_unit_value)]
+                let _ = payload.push(ObisValue {
+                    id: id | 0x0400,
+                    value: (base + measurand) as u64,
+                });
+                #[allow(clippy::let_unit_value)]
+                let _ = payload.push(ObisValue {
+                    id: id | 0x0800,
+                    value: 1000 + (base + measurand) as u64,
+                });
+            }
+        }
+        // Voltage, frequency and software version are ignored.
+        for id in [0x200400, 0x0E0400, ObisValue::SOFTWARE_VERSION_ID] {
+            #[allow(clippy::let_unit_value)]
+            let _ = payload.push(ObisValue { id, value: 1 });
+        }
+        let message = SmaEmMessage {
+            src: SmaEndpoint::dummy(),
+            timestamp_ms: 0,
+            payload,
+        };
+
+        let phases = message.phases();
+        for (base, phase) in [
+            (0, phases.total),
+            (20, phases.l1),
+            (40, phases.l2),
+            (60, phases.l3),
+        ] {
+            let expected = PhaseValues {
+                active_power_import: Some(base + 1),
+                active_power_export: Some(base + 2),
+                reactive_power_import: Some(base + 3),
+                reactive_power_export: Some(base + 4),
+                active_energy_import: Some(1000 + base + 1),
+                active_energy_export: Some(1000 + base + 2),
+                reactive_energy_import: Some(1000 + base + 3),
+                reactive_energy_export: Some(1000 + base + 4),
+            };
+            assert_eq!(expected, phase);
+        }
+    }
+
+    #[test]
+    fn test_phase_data_missing_values() {
+        let payload = [ObisValue {
+            id: 0x150400,
+            value: 1234,
+        }];
+
+        let phases = PhaseData::from_payload(&payload);
+        assert_eq!(Some(1234), phases.l1.active_power_import);
+        assert_eq!(PhaseValues::default(), phases.total);
+        assert_eq!(None, phases.l1.active_energy_import);
+    }
+}
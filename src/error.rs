@@ -15,11 +15,16 @@
     You should have received a copy of the GNU Affero General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
+use crate::inverter::InvalidPasswordError;
 #[cfg(not(feature = "std"))]
 use core::{fmt::Debug, prelude::rust_2021::derive};
 
 /// Errors returned from SMA speedwire protocol processing.
+///
+/// Some variants depend on enabled crate features, so matches must
+/// include a wildcard arm.
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// The provided buffer is too small.
     BufferTooSmall { size: usize, expected: usize },
@@ -28,7 +33,7 @@ pub enum Error {
     BufferNotConsumed { trailing: usize },
     /// The processed packet starts with an invalid SMA FOURCC value.
     InvalidFourCC { fourcc: u32 },
-    /// The packet header length is incorrect.
+    /// The start tag data length in the common packet header is not 4.
     InvalidStartTagLen { len: u16 },
     /// The start tag value in the common packet header is invalid.
     InvalidStartTag { tag: u16 },
@@ -51,8 +56,34 @@ pub enum Error {
     UnsupportedCommandClass { class: u8 },
     /// The opcode of this message has an unsupported value.
     UnsupportedOpcode { opcode: u32 },
+    /// The channel of the command word does not match the opcode.
+    UnsupportedChannel { channel: u8 },
     /// The payload of a packet exceeds the maximum supported length.
     PayloadTooLarge { len: usize },
+    /// The data length field in the common packet header is too small
+    /// for the contained sub-protocol.
+    InvalidDataLen { len: usize, expected: usize },
+    /// The data type of an inverter record is unsupported.
+    UnsupportedDataType { data_type: u8 },
+    /// The value cannot be represented in the wire format.
+    ValueOutOfRange { id: u32, value: i64 },
+    /// The login password is invalid.
+    InvalidPassword { reason: InvalidPasswordError },
+    /// The record at the given index is not newer than its predecessor.
+    NonMonotonicRecords { at: usize },
+    /// The serialized length of an OBIS rule is unsupported.
+    UnsupportedObisLength { len: usize },
+    /// The value exceeds the 32bit range of the OBIS ID.
+    ObisValueOutOfRange { id: u32, value: u64 },
+    /// The hex string contains an invalid digit or an incomplete byte at
+    /// the given character position.
+    InvalidHex { pos: usize },
+    /// The re-serialized message differs from the input starting at
+    /// the given byte offset.
+    RoundtripMismatch { offset: usize },
+    /// Reading from a stream failed with the given I/O error.
+    #[cfg(feature = "std")]
+    Io { kind: std::io::ErrorKind },
 }
 
 #[cfg(feature = "std")]
@@ -76,7 +107,7 @@ impl std::fmt::Display for Error {
                 write!(f, "Found invalid FOURCC value {fourcc:X}")
             }
             Self::InvalidStartTagLen { len } => {
-                write!(f, "Found invalid start tag length {len}")
+                write!(f, "Found invalid start tag length {len}, expected 4")
             }
             Self::InvalidStartTag { tag } => {
                 write!(f, "Found invalid start tag value {tag:X}")
@@ -96,6 +127,18 @@ impl std::fmt::Display for Error {
             Self::UnsupportedObisId { id } => {
                 write!(f, "Unsupported OBIS ID {id:X}")
             }
+            Self::UnsupportedObisLength { len } => {
+                write!(f, "Unsupported OBIS value length {len}")
+            }
+            Self::InvalidHex { pos } => {
+                write!(f, "Invalid hex string at position {pos}")
+            }
+            Self::ObisValueOutOfRange { id, value } => {
+                write!(
+                    f,
+                    "The value {value} exceeds the range of OBIS ID {id:X}"
+                )
+            }
             Self::InvalidWordcount { wordcount } => {
                 write!(
                     f,
@@ -109,6 +152,9 @@ impl std::fmt::Display for Error {
             Self::UnsupportedOpcode { opcode } => {
                 write!(f, "Found unsupported opcode {opcode:X}")
             }
+            Self::UnsupportedChannel { channel } => {
+                write!(f, "Found unsupported command channel {channel:X}")
+            }
             Self::PayloadTooLarge { len } => {
                 write!(
                     f,
@@ -116,6 +162,29 @@ impl std::fmt::Display for Error {
                     the supported maximum"
                 )
             }
+            Self::InvalidDataLen { len, expected } => {
+                write!(
+                    f,
+                    "The data length {len} in the packet header is invalid. \
+                    Expected at least {expected}"
+                )
+            }
+            Self::UnsupportedDataType { data_type } => {
+                write!(f, "Found unsupported record data type {data_type:X}")
+            }
+            Self::ValueOutOfRange { id, value } => {
+                write!(f, "The value {value} of ID {id:X} is out of range")
+            }
+            Self::InvalidPassword { reason } => {
+                write!(f, "The password is invalid: {reason}")
+            }
+            Self::NonMonotonicRecords { at } => {
+                write!(f, "The timestamp of record {at} is out of order")
+            }
+            Self::RoundtripMismatch { offset } => {
+                write!(f, "The re-serialized message differs at byte {offset}")
+            }
+            Self::Io { kind } => write!(f, "Reading the stream failed: {kind}"),
         }
     }
 }
@@ -15,20 +15,193 @@
     You should have received a copy of the GNU Affero General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
-use super::{Cursor, Result, SmaSerde};
+use super::{
+    Cursor, Error, Result, SmaInvGetAllSpotValues, SmaInvGetDayData,
+    SmaInvGetDeviceInfo, SmaInvGetEvents, SmaInvGetMonthData,
+    SmaInvGetTemperature, SmaInvGetTotalEnergy, SmaInvHeader, SmaInvIdentify,
+    SmaInvLogin, SmaInvLogout, SmaPacketHeader, SmaSerde,
+};
 use byteorder::BigEndian;
 #[cfg(not(feature = "std"))]
 use core::{
     clone::Clone,
     cmp::{Eq, PartialEq},
     fmt::Debug,
+    iter::Iterator,
+    marker::Copy,
+    option::Option::{self, None, Some},
     prelude::rust_2021::derive,
-    result::Result::Ok,
+    result::Result::{Err, Ok},
+};
+
+/// Canonical command class, channel and control words which are used when
+/// serializing an inverter message, see [`command_framing`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CommandFraming {
+    /// Command class.
+    pub class: u8,
+    /// Channel of the command word.
+    pub channel: u8,
+    /// Destination control word.
+    pub dst_ctrl: u16,
+    /// Source control word.
+    pub src_ctrl: u16,
+}
+
+impl CommandFraming {
+    const fn new(class: u8, channel: u8, dst_ctrl: u16, src_ctrl: u16) -> Self {
+        Self {
+            class,
+            channel,
+            dst_ctrl,
+            src_ctrl,
+        }
+    }
+
+    /// Looks up the framing of a message supported by this crate.
+    pub(crate) fn lookup(opcode: u32, is_response: bool) -> Result<Self> {
+        command_framing(opcode, is_response)
+            .ok_or(Error::UnsupportedOpcode { opcode })
+    }
+}
+
+/// Entry of the table of inverter commands supported by this crate.
+struct Command {
+    opcode: u32,
+    name: &'static str,
+    request: CommandFraming,
+    /// Framing of the response or `None` if the command has no response.
+    response: Option<CommandFraming>,
+}
+
+impl Command {
+    const fn new(
+        opcode: u32,
+        name: &'static str,
+        request: CommandFraming,
+        response: Option<CommandFraming>,
+    ) -> Self {
+        Self {
+            opcode,
+            name,
+            request,
+            response,
+        }
+    }
+}
+
+const A0_REQUEST: CommandFraming =
+    CommandFraming::new(0xA0, 0x00, 0x0000, 0x0000);
+const A0_RESPONSE: CommandFraming =
+    CommandFraming::new(0xA0, 0x01, 0x00A0, 0x0000);
+const E0_REQUEST: CommandFraming =
+    CommandFraming::new(0xE0, 0x00, 0x0000, 0x0000);
+const E0_RESPONSE: CommandFraming =
+    CommandFraming::new(0xE0, 0x01, 0x00A0, 0x0000);
+
+/// Inverter commands supported by this crate.
+const COMMANDS: [Command; 10] = [
+    Command::new(
+        SmaInvGetAllSpotValues::OPCODE,
+        "GetAllSpotValues",
+        A0_REQUEST,
+        Some(A0_RESPONSE),
+    ),
+    Command::new(
+        SmaInvGetDayData::OPCODE,
+        "GetDayData",
+        E0_REQUEST,
+        Some(E0_RESPONSE),
+    ),
+    Command::new(
+        SmaInvGetDeviceInfo::OPCODE,
+        "GetDeviceInfo",
+        A0_REQUEST,
+        Some(A0_RESPONSE),
+    ),
+    Command::new(
+        SmaInvGetEvents::OPCODE,
+        "GetEvents",
+        E0_REQUEST,
+        Some(E0_RESPONSE),
+    ),
+    Command::new(
+        SmaInvGetMonthData::OPCODE,
+        "GetMonthData",
+        E0_REQUEST,
+        Some(E0_RESPONSE),
+    ),
+    Command::new(
+        SmaInvGetTemperature::OPCODE,
+        "GetTemperature",
+        A0_REQUEST,
+        Some(A0_RESPONSE),
+    ),
+    Command::new(
+        SmaInvGetTotalEnergy::OPCODE,
+        "GetTotalEnergy",
+        A0_REQUEST,
+        Some(A0_RESPONSE),
+    ),
+    Command::new(
+        SmaInvIdentify::OPCODE,
+        "Identify",
+        A0_REQUEST,
+        Some(CommandFraming::new(0xA0, 0x01, 0x00C0, 0x0000)),
+    ),
+    Command::new(
+        SmaInvLogin::OPCODE,
+        "Login",
+        CommandFraming::new(0xA0, 0x0C, 0x0001, 0x0001),
+        Some(CommandFraming::new(0xE0, 0x0D, 0x0001, 0x0001)),
+    ),
+    Command::new(
+        SmaInvLogout::OPCODE,
+        "Logout",
+        CommandFraming::new(0xA0, 0x0E, 0x0003, 0x0003),
+        None,
+    ),
+];
+
+/// Opcodes of all supported inverter commands.
+pub(crate) const SUPPORTED_OPCODES: [u32; COMMANDS.len()] = {
+    let mut opcodes = [0; COMMANDS.len()];
+    let mut i = 0;
+    while i < COMMANDS.len() {
+        opcodes[i] = COMMANDS[i].opcode;
+        i += 1;
+    }
+    opcodes
 };
 
+fn find_command(opcode: u32) -> Option<&'static Command> {
+    COMMANDS.iter().find(|cmd| cmd.opcode == opcode)
+}
+
+/// Returns the canonical framing of a request or response with the given
+/// opcode or `None` if the opcode is not supported or has no response.
+///
+/// Login messages which carry an error code use the class 0xD0.
+pub fn command_framing(
+    opcode: u32,
+    is_response: bool,
+) -> Option<CommandFraming> {
+    let cmd = find_command(opcode)?;
+    if is_response {
+        cmd.response
+    } else {
+        Some(cmd.request)
+    }
+}
+
+/// Returns the name of the inverter command with the given opcode.
+pub(crate) fn command_name(opcode: u32) -> Option<&'static str> {
+    find_command(opcode).map(|cmd| cmd.name)
+}
+
 /// A speedwire command word consisting of an opcode and a channel.
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
-pub(crate) struct SmaCmdWord {
+pub struct SmaCmdWord {
     /// Channel number.
     pub channel: u8,
     /// 24bit command ID.
@@ -38,9 +211,45 @@ pub(crate) struct SmaCmdWord {
 impl SmaCmdWord {
     /// Serialized length of the command word.
     const LENGTH: usize = 4;
+    /// Offset of the command word from the start of an inverter packet.
+    const OFFSET: usize =
+        SmaPacketHeader::LENGTH + SmaInvHeader::LENGTH - Self::LENGTH;
+
+    /// Returns true if the channel marks this command as a response.
+    /// Responses have the lowest channel bit set, e.g. login requests use
+    /// channel 0x0C and responses channel 0x0D.
+    pub fn is_response(&self) -> bool {
+        self.channel & 0x01 != 0
+    }
+
+    /// Reads the command word of the inverter packet at the cursor position
+    /// without advancing the cursor or deserializing the packet.
+    pub fn peek(buffer: &Cursor<&[u8]>) -> Result<Self> {
+        buffer.check_remaining(Self::OFFSET + Self::LENGTH)?;
+
+        let fourcc = buffer.peek_u32::<BigEndian>(0);
+        if fourcc != SmaPacketHeader::SMA_FOURCC {
+            return Err(Error::InvalidFourCC { fourcc });
+        }
+
+        let protocol = buffer.peek_u16::<BigEndian>(16);
+        if protocol != SmaPacketHeader::SMA_PROTOCOL_INV {
+            return Err(Error::UnsupportedProtocol { protocol });
+        }
+
+        let word = buffer.peek_u32::<BigEndian>(Self::OFFSET);
+        Ok(Self {
+            channel: (word >> 24) as u8,
+            opcode: word & 0x00FF_FFFF,
+        })
+    }
 }
 
 impl SmaSerde for SmaCmdWord {
+    fn serialized_len(&self) -> usize {
+        Self::LENGTH
+    }
+
     fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
         buffer.check_remaining(Self::LENGTH)?;
         buffer.write_u8(self.channel);
@@ -58,3 +267,163 @@ impl SmaSerde for SmaCmdWord {
         Ok(Self { channel, opcode })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sma_cmd_word_login_direction() {
+        #[rustfmt::skip]
+        let request = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x3A, 0x00, 0x10,
+            0x60, 0x65,
+            0x0E, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x01,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x80,
+            0x0C, 0x04, 0xFD, 0xFF,
+        ];
+
+        match SmaCmdWord::peek(&Cursor::new(&request[..])) {
+            Err(e) => panic!("Peeking SmaCmdWord failed: {e:?}"),
+            Ok(cmd) => {
+                assert_eq!(0x0C, cmd.channel);
+                assert_eq!(0x04FDFF, cmd.opcode);
+                assert!(!cmd.is_response());
+            }
+        }
+
+        #[rustfmt::skip]
+        let response = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x2E, 0x00, 0x10,
+            0x60, 0x65,
+            0x0B, 0xE0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x80,
+            0x0D, 0x04, 0xFD, 0xFF,
+        ];
+
+        match SmaCmdWord::peek(&Cursor::new(&response[..])) {
+            Err(e) => panic!("Peeking SmaCmdWord failed: {e:?}"),
+            Ok(cmd) => {
+                assert_eq!(0x0D, cmd.channel);
+                assert_eq!(0x04FDFF, cmd.opcode);
+                assert!(cmd.is_response());
+            }
+        }
+    }
+
+    /// Extracts the framing of the inverter header of a golden frame.
+    fn framing_of(frame: &[u8]) -> CommandFraming {
+        CommandFraming {
+            class: frame[19],
+            channel: frame[42],
+            dst_ctrl: u16::from_be_bytes([frame[26], frame[27]]),
+            src_ctrl: u16::from_be_bytes([frame[34], frame[35]]),
+        }
+    }
+
+    #[test]
+    fn test_command_framing_golden_frames() {
+        #[rustfmt::skip]
+        let login_request = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x3A, 0x00, 0x10,
+            0x60, 0x65,
+            0x0E, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x01,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x80,
+            0x0C, 0x04, 0xFD, 0xFF,
+        ];
+        #[rustfmt::skip]
+        let login_response = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x2E, 0x00, 0x10,
+            0x60, 0x65,
+            0x0B, 0xE0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x80,
+            0x0D, 0x04, 0xFD, 0xFF,
+        ];
+        #[rustfmt::skip]
+        let logout_request = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x22, 0x00, 0x10,
+            0x60, 0x65,
+            0x08, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x03,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x03,
+            0x00, 0x00, 0x00, 0x00, 0x01, 0x80,
+            0x0E, 0x01, 0xFD, 0xFF,
+        ];
+        #[rustfmt::skip]
+        let identify_request = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x26, 0x00, 0x10,
+            0x60, 0x65,
+            0x09, 0xA0,
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x80,
+            0x00, 0x02, 0x00, 0x00,
+        ];
+        #[rustfmt::skip]
+        let identify_response = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x4E, 0x00, 0x10,
+            0x60, 0x65,
+            0x13, 0xA0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0xC0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x01, 0x80,
+            0x01, 0x02, 0x00, 0x00,
+        ];
+        #[rustfmt::skip]
+        let day_data_request = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x26, 0x00, 0x10,
+            0x60, 0x65,
+            0x09, 0xE0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x03, 0x80,
+            0x00, 0x02, 0x00, 0x70,
+        ];
+        #[rustfmt::skip]
+        let day_data_response = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x56, 0x00, 0x10,
+            0x60, 0x65,
+            0x15, 0xE0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0x00, 0x00, 0x03, 0x00, 0x08, 0x80,
+            0x01, 0x02, 0x00, 0x70,
+        ];
+
+        for (frame, opcode, is_response) in [
+            (&login_request[..], SmaInvLogin::OPCODE, false),
+            (&login_response[..], SmaInvLogin::OPCODE, true),
+            (&logout_request[..], SmaInvLogout::OPCODE, false),
+            (&identify_request[..], SmaInvIdentify::OPCODE, false),
+            (&identify_response[..], SmaInvIdentify::OPCODE, true),
+            (&day_data_request[..], SmaInvGetDayData::OPCODE, false),
+            (&day_data_response[..], SmaInvGetDayData::OPCODE, true),
+        ] {
+            assert_eq!(
+                Some(framing_of(frame)),
+                command_framing(opcode, is_response),
+                "Framing mismatch for {opcode:X}, response {is_response}"
+            );
+        }
+
+        assert_eq!(None, command_framing(SmaInvLogout::OPCODE, true));
+        assert_eq!(None, command_framing(0x123456, false));
+    }
+}
@@ -20,14 +20,20 @@ use byteorder::LittleEndian;
 #[cfg(not(feature = "std"))]
 use core::{
     clone::Clone,
-    cmp::{Eq, PartialEq},
+    cmp::{Eq, Ord, PartialEq, PartialOrd},
     fmt::Debug,
+    option::Option::{self, Some},
     prelude::rust_2021::derive,
     result::Result::Ok,
 };
+use core::{
+    cmp::Ordering,
+    hash::{Hash, Hasher},
+};
 
 /// SMA inverter sub-protocol packet and fragment counter.
-#[derive(Clone, Debug, Eq, PartialEq)]
+/// Equality, hashing and ordering are defined by [`Self::key`].
+#[derive(Clone, Debug)]
 pub struct SmaInvCounter {
     /// Decrementing packet fragment counter.
     pub fragment_id: u16,
@@ -50,9 +56,48 @@ impl Default for SmaInvCounter {
 impl SmaInvCounter {
     pub const LENGTH: usize = 4;
     pub const FIRST_FRAGMENT_BIT: u16 = 0x8000;
+
+    /// Packs packet ID, first fragment flag and fragment ID into a single
+    /// comparable key, e.g. for use in reassembly or deduplication maps.
+    /// Keys are ordered by packet ID first.
+    pub fn key(&self) -> u32 {
+        (u32::from(self.packet_id & !Self::FIRST_FRAGMENT_BIT) << 17)
+            | (u32::from(self.first_fragment) << 16)
+            | u32::from(self.fragment_id)
+    }
+}
+
+impl PartialEq for SmaInvCounter {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SmaInvCounter {}
+
+impl Hash for SmaInvCounter {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl PartialOrd for SmaInvCounter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SmaInvCounter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
 }
 
 impl SmaSerde for SmaInvCounter {
+    fn serialized_len(&self) -> usize {
+        Self::LENGTH
+    }
+
     fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
         buffer.check_remaining(Self::LENGTH)?;
 
@@ -87,3 +132,34 @@ impl SmaSerde for SmaInvCounter {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sma_inv_counter_key() {
+        let counter = |packet_id, fragment_id, first_fragment| SmaInvCounter {
+            fragment_id,
+            packet_id,
+            first_fragment,
+        };
+        let counters = [
+            counter(0, 0, false),
+            counter(0, 0, true),
+            counter(0, 1, false),
+            counter(1, 0, false),
+            counter(0x7FFF, 0xFFFF, true),
+        ];
+
+        for (i, a) in counters.iter().enumerate() {
+            assert_eq!(a.key(), a.clone().key());
+            for b in &counters[i + 1..] {
+                assert_ne!(a.key(), b.key());
+            }
+        }
+        assert!(counter(1, 0, false).key() > counter(0, 0xFFFF, true).key());
+        assert!(counter(1, 0, false) > counter(0, 0xFFFF, true));
+        assert_eq!(counter(1, 0, false), counter(0x8001, 0, false));
+    }
+}
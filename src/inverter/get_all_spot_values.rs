@@ -0,0 +1,470 @@
+/******************************************************************************\
+    sma-proto - A SMA Speedwire protocol library
+    Copyright (C) 2024 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::{
+    decode_spot_i32, CommandFraming, Cursor, DeserializeOptions, Error, Result,
+    SmaCmdWord, SmaEndpoint, SmaInvCounter, SmaInvHeader, SmaPacketFooter,
+    SmaPacketHeader, SmaSerde,
+};
+use byteorder::LittleEndian;
+#[cfg(not(feature = "std"))]
+use core::{
+    clone::Clone,
+    cmp::{Eq, PartialEq},
+    convert::TryFrom,
+    fmt::Debug,
+    prelude::rust_2021::derive,
+    result::Result::{Err, Ok},
+};
+#[cfg(not(feature = "std"))]
+use heapless::Vec;
+
+/// A logical GetAllSpotValues message request/response.
+/// This queries all spot values within an object ID range with a single
+/// round trip instead of one request per value.
+///
+/// The response records are heterogeneous. Numeric records are reduced to
+/// their object ID, the first 24bit of the record code with the lowest
+/// (class) byte cleared, and their value. Status and string records as well
+/// as values marked invalid by the device are skipped.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SmaInvGetAllSpotValues {
+    /// Destination application/device address.
+    pub dst: SmaEndpoint,
+    /// Source application/device address.
+    pub src: SmaEndpoint,
+    /// Non-zero in case of errors.
+    pub error_code: u16,
+    /// Packet counters.
+    pub counters: SmaInvCounter,
+    /// Overrides the destination control word,
+    /// defaults to 0x00 for requests and 0xA0 for responses.
+    /// Deserialization leaves this unset.
+    pub dst_ctrl: Option<u16>,
+    /// Overrides the source control word, defaults to 0.
+    /// Deserialization leaves this unset.
+    pub src_ctrl: Option<u16>,
+    /// Marks the message as a device response,
+    /// see [`SmaCmdWord::is_response`].
+    pub response: bool,
+    /// First object ID (request) or first record number (response).
+    pub first_idx: u32,
+    /// Last object ID (request) or last record number (response).
+    pub last_idx: u32,
+    #[cfg(not(feature = "std"))]
+    /// Valid spot values as `(object ID, value)` pairs.
+    pub records: Vec<(u32, i64), { Self::MAX_RECORD_COUNT }>,
+    /// Valid spot values as `(object ID, value)` pairs.
+    #[cfg(feature = "std")]
+    pub records: Vec<(u32, i64)>,
+}
+
+impl SmaInvGetAllSpotValues {
+    pub const OPCODE: u32 = 0x020051;
+    pub const LENGTH_MIN: usize = SmaPacketHeader::LENGTH
+        + SmaInvHeader::LENGTH
+        + 8
+        + SmaPacketFooter::LENGTH;
+    pub const LENGTH_MAX: usize =
+        Self::LENGTH_MIN + Self::MAX_RECORD_COUNT * Self::RECORD_LENGTH;
+    pub const MAX_RECORD_COUNT: usize = 34;
+    /// First object ID of the complete spot value range.
+    pub const FIRST_OBJECT: u32 = 0x00000000;
+    /// Last object ID of the complete spot value range.
+    pub const LAST_OBJECT: u32 = 0x00FFFFFF;
+
+    /// Serialized length of a numeric record.
+    const RECORD_LENGTH: usize = 28;
+    /// Serialized length of a status or string record.
+    const RECORD_LENGTH_LONG: usize = 40;
+    /// Length of the record code and timestamp.
+    const RECORD_HEADER_LENGTH: usize = 8;
+
+    const DATA_TYPE_U32: u8 = 0x00;
+    const DATA_TYPE_STATUS: u8 = 0x08;
+    const DATA_TYPE_STRING: u8 = 0x10;
+    const DATA_TYPE_S32: u8 = 0x40;
+
+    const NAN_U32: u32 = 0xFFFF_FFFF;
+
+    /// Checks that the number of records does not exceed the maximum and
+    /// that all values fit into a 32bit wire value which is not a NaN
+    /// sentinel.
+    pub fn validate(&self) -> Result<()> {
+        if self.records.len() > Self::MAX_RECORD_COUNT {
+            return Err(Error::PayloadTooLarge {
+                len: self.records.len(),
+            });
+        }
+
+        for (id, value) in &self.records {
+            Self::encode_value(*id, *value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the data type and raw wire value of a numeric record.
+    /// Non-negative values are encoded as unsigned, negative values as
+    /// signed 32bit integers.
+    fn encode_value(id: u32, value: i64) -> Result<(u8, u32)> {
+        if let Ok(raw) = u32::try_from(value) {
+            if raw != Self::NAN_U32 {
+                return Ok((Self::DATA_TYPE_U32, raw));
+            }
+        } else if let Ok(raw) = i32::try_from(value) {
+            if decode_spot_i32(raw as u32).is_some() {
+                return Ok((Self::DATA_TYPE_S32, raw as u32));
+            }
+        }
+
+        Err(Error::ValueOutOfRange { id, value })
+    }
+
+    /// Compares two messages ignoring packet counters.
+    pub fn payload_eq(&self, other: &Self) -> bool {
+        self.dst == other.dst
+            && self.src == other.src
+            && self.error_code == other.error_code
+            && self.response == other.response
+            && self.first_idx == other.first_idx
+            && self.last_idx == other.last_idx
+            && self.records == other.records
+    }
+
+    /// Serializes a single numeric record, see [`Self::encode_value`].
+    fn serialize_record(
+        buffer: &mut Cursor<&mut [u8]>,
+        id: u32,
+        value: i64,
+    ) -> Result<()> {
+        let (data_type, raw) = Self::encode_value(id, value)?;
+
+        let code = (u32::from(data_type) << 24) | (id & 0x00FF_FF00) | 0x01;
+        buffer.write_u32::<LittleEndian>(code);
+        buffer.write_u32::<LittleEndian>(0);
+        for _ in 0..5 {
+            buffer.write_u32::<LittleEndian>(raw);
+        }
+
+        Ok(())
+    }
+}
+
+impl SmaSerde for SmaInvGetAllSpotValues {
+    fn serialized_len(&self) -> usize {
+        Self::LENGTH_MIN + self.records.len() * Self::RECORD_LENGTH
+    }
+
+    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+        self.validate()?;
+
+        let len = self.serialized_len();
+        buffer.check_remaining(len)?;
+
+        let data_len = len - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH;
+        let header = SmaPacketHeader {
+            data_len,
+            protocol: SmaPacketHeader::SMA_PROTOCOL_INV,
+            ..Default::default()
+        };
+
+        let framing = CommandFraming::lookup(Self::OPCODE, self.response)?;
+
+        let inv_header = SmaInvHeader {
+            wordcount: (data_len / 4) as u8,
+            class: framing.class,
+            dst: self.dst.clone(),
+            dst_ctrl: self.dst_ctrl.unwrap_or(framing.dst_ctrl),
+            src: self.src.clone(),
+            src_ctrl: self.src_ctrl.unwrap_or(framing.src_ctrl),
+            error_code: self.error_code,
+            counters: self.counters.clone(),
+            cmd: SmaCmdWord {
+                channel: framing.channel,
+                opcode: Self::OPCODE,
+            },
+        };
+
+        header.serialize(buffer)?;
+        inv_header.serialize(buffer)?;
+
+        buffer.write_u32::<LittleEndian>(self.first_idx);
+        buffer.write_u32::<LittleEndian>(self.last_idx);
+
+        for (id, value) in &self.records {
+            Self::serialize_record(buffer, *id, *value)?;
+        }
+
+        SmaPacketFooter::default().serialize(buffer)?;
+
+        Ok(())
+    }
+
+    fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
+
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        buffer.check_remaining(options.min_len(Self::LENGTH_MIN))?;
+
+        let header = SmaPacketHeader::deserialize_with(buffer, options)?;
+        header.check_protocol(SmaPacketHeader::SMA_PROTOCOL_INV)?;
+        buffer.check_remaining(header.data_len)?;
+        let padding_len = buffer.remaining() - header.data_len;
+
+        let inv_header = SmaInvHeader::deserialize(buffer)?;
+        inv_header.check_wordcount(header.data_len)?;
+        inv_header.check_framing(Self::OPCODE)?;
+
+        let first_idx = buffer.read_u32::<LittleEndian>();
+        let last_idx = buffer.read_u32::<LittleEndian>();
+
+        let mut records = Vec::default();
+        while buffer.remaining() - padding_len >= Self::RECORD_HEADER_LENGTH {
+            let code = buffer.peek_u32::<LittleEndian>(0);
+            let data_type = (code >> 24) as u8;
+            let record_len = match data_type {
+                Self::DATA_TYPE_U32 | Self::DATA_TYPE_S32 => {
+                    Self::RECORD_LENGTH
+                }
+                Self::DATA_TYPE_STATUS | Self::DATA_TYPE_STRING => {
+                    Self::RECORD_LENGTH_LONG
+                }
+                data_type => {
+                    return Err(Error::UnsupportedDataType { data_type })
+                }
+            };
+
+            let size = buffer.remaining() - padding_len;
+            if size < record_len {
+                return Err(Error::BufferTooSmall {
+                    size,
+                    expected: record_len,
+                });
+            }
+
+            if record_len == Self::RECORD_LENGTH_LONG {
+                buffer.skip(record_len);
+                continue;
+            }
+
+            buffer.skip(Self::RECORD_HEADER_LENGTH + 8);
+            let raw = buffer.read_u32::<LittleEndian>();
+            buffer.skip(8);
+
+            let value = match data_type {
+                Self::DATA_TYPE_S32 => decode_spot_i32(raw).map(i64::from),
+                Self::DATA_TYPE_U32 if raw != Self::NAN_U32 => {
+                    Some(i64::from(raw))
+                }
+                _ => None,
+            };
+            let Some(value) = value else {
+                continue;
+            };
+            let record = (code & 0x00FF_FF00, value);
+
+            #[cfg(feature = "std")]
+            records.push(record);
+            #[cfg(not(feature = "std"))]
+            if records.push(record).is_err() {
+                return Err(Error::PayloadTooLarge {
+                    len: records.len() + 1,
+                });
+            }
+        }
+
+        SmaPacketFooter::deserialize_with(buffer, options)?;
+
+        Ok(Self {
+            dst: inv_header.dst,
+            src: inv_header.src,
+            error_code: inv_header.error_code,
+            counters: inv_header.counters,
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: inv_header.cmd.is_response(),
+            first_idx,
+            last_idx,
+            records,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sma_inv_get_all_spot_values_serialization() {
+        let message = SmaInvGetAllSpotValues {
+            src: SmaEndpoint::dummy(),
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 5,
+                ..Default::default()
+            },
+            first_idx: SmaInvGetAllSpotValues::FIRST_OBJECT,
+            last_idx: SmaInvGetAllSpotValues::LAST_OBJECT,
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvGetAllSpotValues::LENGTH_MIN];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetAllSpotValues serialization failed: {e:?}");
+        }
+
+        #[rustfmt::skip]
+        let expected = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x26, 0x00, 0x10,
+            0x60, 0x65,
+            0x09, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x05, 0x80,
+            0x00, 0x02, 0x00, 0x51,
+            0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(SmaInvGetAllSpotValues::LENGTH_MIN, cursor.position());
+        assert_eq!(message.serialized_len(), cursor.position());
+        assert_eq!(expected, buffer);
+    }
+
+    #[test]
+    fn test_sma_inv_get_all_spot_values_response_deserialization() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0xBE, 0x00, 0x10,
+            0x60, 0x65,
+            0x2F, 0xA0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x05, 0x80,
+            0x01, 0x02, 0x00, 0x51,
+            0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00,
+            0x01, 0x3F, 0x26, 0x40, 0x00, 0xF1, 0x53, 0x65,
+            0xD2, 0x04, 0x00, 0x00, 0xD2, 0x04, 0x00, 0x00,
+            0xD2, 0x04, 0x00, 0x00, 0xD2, 0x04, 0x00, 0x00,
+            0xD2, 0x04, 0x00, 0x00,
+            0x01, 0x1E, 0x41, 0x00, 0x00, 0xF1, 0x53, 0x65,
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            0xFF, 0xFF, 0xFF, 0xFF,
+            0x01, 0x53, 0x46, 0x40, 0x00, 0xF1, 0x53, 0x65,
+            0xFB, 0xFF, 0xFF, 0xFF, 0xFB, 0xFF, 0xFF, 0xFF,
+            0xFB, 0xFF, 0xFF, 0xFF, 0xFB, 0xFF, 0xFF, 0xFF,
+            0xFB, 0xFF, 0xFF, 0xFF,
+            0x01, 0x48, 0x21, 0x08, 0x00, 0xF1, 0x53, 0x65,
+            0x33, 0x01, 0x00, 0x10, 0xFE, 0xFF, 0xFF, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x01, 0x1E, 0x25, 0x40, 0x00, 0xF1, 0x53, 0x65,
+            0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x80,
+            0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x80,
+            0x00, 0x00, 0x00, 0x80,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let expected = SmaInvGetAllSpotValues {
+            dst: SmaEndpoint::dummy(),
+            src: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            error_code: 0,
+            counters: SmaInvCounter {
+                packet_id: 5,
+                fragment_id: 0,
+                first_fragment: true,
+            },
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: true,
+            first_idx: 0,
+            last_idx: 4,
+            records: {
+                let mut records = Vec::default();
+                #[allow(clippy::let_unit_value)]
+                let _ = records.push((0x00263F00, 1234));
+                #[allow(clippy::let_unit_value)]
+                let _ = records.push((0x00465300, -5));
+                records
+            },
+        };
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaInvGetAllSpotValues::deserialize(&mut cursor) {
+            Err(e) => {
+                panic!("SmaInvGetAllSpotValues deserialization failed: {e:?}")
+            }
+            Ok(message) => {
+                assert_eq!(expected, message);
+                assert_eq!(serialized.len(), cursor.position());
+            }
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_get_all_spot_values_roundtrip() {
+        let message = SmaInvGetAllSpotValues {
+            dst: SmaEndpoint::dummy(),
+            src: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            last_idx: 1,
+            records: {
+                let mut records = Vec::default();
+                #[allow(clippy::let_unit_value)]
+                let _ = records.push((0x00263F00, 4_000_000_000));
+                #[allow(clippy::let_unit_value)]
+                let _ = records.push((0x00465300, -5));
+                records
+            },
+            response: true,
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvGetAllSpotValues::LENGTH_MIN + 56];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetAllSpotValues serialization failed: {e:?}");
+        }
+        assert_eq!(message.serialized_len(), cursor.position());
+
+        let mut cursor = Cursor::new(&buffer[..]);
+        match SmaInvGetAllSpotValues::deserialize(&mut cursor) {
+            Err(e) => {
+                panic!("SmaInvGetAllSpotValues deserialization failed: {e:?}")
+            }
+            Ok(deserialized) => assert_eq!(message, deserialized),
+        }
+    }
+}
@@ -16,8 +16,9 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
 use super::{
-    Cursor, Error, Result, SmaCmdWord, SmaEndpoint, SmaInvCounter,
-    SmaInvHeader, SmaInvMeterValue, SmaPacketFooter, SmaPacketHeader, SmaSerde,
+    CommandFraming, Cursor, DeserializeOptions, Error, Result, SmaCmdWord,
+    SmaEndpoint, SmaInvCounter, SmaInvHeader, SmaInvMeterValue,
+    SmaPacketFooter, SmaPacketHeader, SmaSerde,
 };
 use byteorder::LittleEndian;
 #[cfg(not(feature = "std"))]
@@ -25,6 +26,7 @@ use core::{
     clone::Clone,
     cmp::{Eq, PartialEq},
     fmt::Debug,
+    option::Option::{None, Some},
     prelude::rust_2021::derive,
     result::Result::{Err, Ok},
 };
@@ -42,6 +44,16 @@ pub struct SmaInvGetDayData {
     pub error_code: u16,
     /// Packet counters.
     pub counters: SmaInvCounter,
+    /// Overrides the destination control word,
+    /// defaults to 0x00 for requests and 0xA0 for responses.
+    /// Deserialization leaves this unset.
+    pub dst_ctrl: Option<u16>,
+    /// Overrides the source control word, defaults to 0.
+    /// Deserialization leaves this unset.
+    pub src_ctrl: Option<u16>,
+    /// Marks the message as a device response,
+    /// see [`SmaCmdWord::is_response`].
+    pub response: bool,
     /// Start timestamp (request) or start record number (response).
     pub start_time_idx: u32,
     /// End timestamp (request) or end record number (response).
@@ -64,47 +76,129 @@ impl SmaInvGetDayData {
         Self::LENGTH_MIN + Self::MAX_RECORD_COUNT * SmaInvMeterValue::LENGTH;
     pub const MAX_RECORD_COUNT: usize = 81;
 
+    /// Serialized length of this message.
+    /// Saturates at `usize::MAX` for unrepresentable record counts.
     pub fn serialized_len(&self) -> usize {
-        Self::LENGTH_MIN + self.records.len() * SmaInvMeterValue::LENGTH
+        Self::len_for_records(self.records.len()).unwrap_or(usize::MAX)
     }
-}
 
-impl SmaSerde for SmaInvGetDayData {
-    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+    /// Creates a request for the records between the `start` and `end`
+    /// timestamps with a default packet counter and
+    /// no records.
+    pub fn request(
+        src: SmaEndpoint,
+        dst: SmaEndpoint,
+        start: u32,
+        end: u32,
+    ) -> Self {
+        Self {
+            dst,
+            src,
+            error_code: 0,
+            counters: SmaInvCounter::default(),
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: false,
+            start_time_idx: start,
+            end_time_idx: end,
+            records: Vec::new(),
+        }
+    }
+
+    /// Checks that the number of records does not exceed the maximum.
+    pub fn validate(&self) -> Result<()> {
         if self.records.len() > Self::MAX_RECORD_COUNT {
             return Err(Error::PayloadTooLarge {
                 len: self.records.len(),
             });
         }
 
-        let len = self.serialized_len();
+        Ok(())
+    }
+
+    /// Returns the serialized length of a message with `count` records or
+    /// [`Error::PayloadTooLarge`] if it is not representable.
+    fn len_for_records(count: usize) -> Result<usize> {
+        count
+            .checked_mul(SmaInvMeterValue::LENGTH)
+            .and_then(|len| len.checked_add(Self::LENGTH_MIN))
+            .ok_or(Error::PayloadTooLarge { len: count })
+    }
+
+    /// Checks that the record timestamps are strictly increasing.
+    /// A violation indicates a dropped or reordered fragment.
+    pub fn validate_ordering(&self) -> Result<()> {
+        Self::validate_record_ordering(&self.records)
+    }
+
+    /// Returns the number of records announced by the record index range
+    /// of a response, i.e. `end_time_idx - start_time_idx`. This is
+    /// meaningless for requests, which carry timestamps instead.
+    pub fn expected_record_count(&self) -> u32 {
+        self.end_time_idx.wrapping_sub(self.start_time_idx)
+    }
+
+    /// Checks that the timestamps of the given records, e.g. reassembled
+    /// from multiple responses, are strictly increasing.
+    /// Returns [`Error::NonMonotonicRecords`] with the index of the first
+    /// record which is not newer than its predecessor.
+    pub fn validate_record_ordering(
+        records: &[SmaInvMeterValue],
+    ) -> Result<()> {
+        match records
+            .windows(2)
+            .position(|pair| pair[1].timestamp <= pair[0].timestamp)
+        {
+            Some(idx) => Err(Error::NonMonotonicRecords { at: idx + 1 }),
+            None => Ok(()),
+        }
+    }
+
+    /// Compares two messages ignoring packet counters.
+    pub fn payload_eq(&self, other: &Self) -> bool {
+        self.dst == other.dst
+            && self.src == other.src
+            && self.error_code == other.error_code
+            && self.response == other.response
+            && self.start_time_idx == other.start_time_idx
+            && self.end_time_idx == other.end_time_idx
+            && self.records == other.records
+    }
+}
+
+impl SmaSerde for SmaInvGetDayData {
+    fn serialized_len(&self) -> usize {
+        SmaInvGetDayData::serialized_len(self)
+    }
+
+    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+        self.validate()?;
+
+        let len = Self::len_for_records(self.records.len())?;
         buffer.check_remaining(len)?;
 
         let data_len = len - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH;
         let header = SmaPacketHeader {
             data_len,
             protocol: SmaPacketHeader::SMA_PROTOCOL_INV,
+            ..Default::default()
         };
 
-        let (channel, dst_ctrl) = if self.records.is_empty() {
-            (0, 0x00)
-        } else {
-            (1, 0xA0)
-        };
+        let framing = CommandFraming::lookup(Self::OPCODE, self.response)?;
 
         let inv_header = SmaInvHeader {
             wordcount: (data_len / 4) as u8,
-            class: 0xE0,
+            class: framing.class,
             dst: self.dst.clone(),
-            dst_ctrl,
+            dst_ctrl: self.dst_ctrl.unwrap_or(framing.dst_ctrl),
             src: self.src.clone(),
+            src_ctrl: self.src_ctrl.unwrap_or(framing.src_ctrl),
             error_code: self.error_code,
             counters: self.counters.clone(),
             cmd: SmaCmdWord {
-                channel,
+                channel: framing.channel,
                 opcode: Self::OPCODE,
             },
-            ..Default::default()
         };
 
         header.serialize(buffer)?;
@@ -123,17 +217,23 @@ impl SmaSerde for SmaInvGetDayData {
     }
 
     fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
-        buffer.check_remaining(Self::LENGTH_MIN)?;
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
+
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        buffer.check_remaining(options.min_len(Self::LENGTH_MIN))?;
 
-        let header = SmaPacketHeader::deserialize(buffer)?;
+        let header = SmaPacketHeader::deserialize_with(buffer, options)?;
         header.check_protocol(SmaPacketHeader::SMA_PROTOCOL_INV)?;
         buffer.check_remaining(header.data_len)?;
         let padding_len = buffer.remaining() - header.data_len;
 
         let inv_header = SmaInvHeader::deserialize(buffer)?;
         inv_header.check_wordcount(header.data_len)?;
-        inv_header.check_class(0xE0)?;
-        inv_header.check_opcode(Self::OPCODE)?;
+        inv_header.check_framing(Self::OPCODE)?;
 
         let start_time_idx = buffer.read_u32::<LittleEndian>();
         let end_time_idx = buffer.read_u32::<LittleEndian>();
@@ -152,13 +252,16 @@ impl SmaSerde for SmaInvGetDayData {
             }
         }
 
-        SmaPacketFooter::deserialize(buffer)?;
+        SmaPacketFooter::deserialize_with(buffer, options)?;
 
         Ok(Self {
             dst: inv_header.dst,
             src: inv_header.src,
             error_code: inv_header.error_code,
             counters: inv_header.counters,
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: inv_header.cmd.is_response(),
             start_time_idx,
             end_time_idx,
             records,
@@ -183,6 +286,9 @@ mod tests {
                 packet_id: 3,
                 ..Default::default()
             },
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: false,
             start_time_idx: 1700000000,
             end_time_idx: 1750000000,
             records: Vec::new(),
@@ -209,9 +315,122 @@ mod tests {
             0x00, 0x00, 0x00, 0x00,
         ];
         assert_eq!(SmaInvGetDayData::LENGTH_MIN, cursor.position());
+        assert_eq!(message.serialized_len(), cursor.position());
         assert_eq!(expected, buffer);
     }
 
+    #[test]
+    fn test_sma_inv_get_day_data_request() {
+        let message = SmaInvGetDayData::request(
+            SmaEndpoint::dummy(),
+            SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            1700000000,
+            1750000000,
+        );
+
+        let mut buffer = [0u8; SmaInvGetDayData::LENGTH_MIN];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetDayData serialization failed: {e:?}");
+        }
+
+        #[rustfmt::skip]
+        let expected = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x26, 0x00, 0x10,
+            0x60, 0x65,
+            0x09, 0xE0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x80,
+            0x00, 0x02, 0x00, 0x70,
+            0x00, 0xF1, 0x53, 0x65, 0x80, 0xE1, 0x4E, 0x68,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(SmaInvGetDayData::LENGTH_MIN, cursor.position());
+        assert_eq!(expected, buffer);
+    }
+
+    #[test]
+    fn test_sma_inv_get_day_data_ctrl_override() {
+        let message = SmaInvGetDayData {
+            src: SmaEndpoint::dummy(),
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            dst_ctrl: Some(0x0001),
+            src_ctrl: Some(0x0001),
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvGetDayData::LENGTH_MIN];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetDayData serialization failed: {e:?}");
+        }
+
+        assert_eq!([0x00, 0x01], buffer[26..28]);
+        assert_eq!([0x00, 0x01], buffer[34..36]);
+    }
+
+    #[test]
+    fn test_sma_inv_get_day_data_len_overflow() {
+        assert_eq!(
+            Some(SmaInvGetDayData::LENGTH_MAX),
+            SmaInvGetDayData::len_for_records(
+                SmaInvGetDayData::MAX_RECORD_COUNT
+            )
+            .ok()
+        );
+
+        let count = usize::MAX / SmaInvMeterValue::LENGTH + 1;
+        match SmaInvGetDayData::len_for_records(count) {
+            Err(Error::PayloadTooLarge { len }) => assert_eq!(count, len),
+            x => panic!("Computed length of huge message as {x:?}"),
+        }
+
+        let count = (usize::MAX - SmaInvGetDayData::LENGTH_MIN)
+            / SmaInvMeterValue::LENGTH
+            + 1;
+        match SmaInvGetDayData::len_for_records(count) {
+            Err(Error::PayloadTooLarge { len }) => assert_eq!(count, len),
+            x => panic!("Computed length of huge message as {x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_get_day_data_ordering() {
+        let record = |timestamp| SmaInvMeterValue {
+            timestamp,
+            energy_wh: 0,
+        };
+        let mut message = SmaInvGetDayData::default();
+        for timestamp in [300, 600, 900] {
+            #[allow(clippy::let_unit_value)]
+            let _ = message.records.push(record(timestamp));
+        }
+        if let Err(e) = message.validate_ordering() {
+            panic!("Ordered records failed validation: {e:?}");
+        }
+
+        message.records.swap(1, 2);
+        match message.validate_ordering() {
+            Err(Error::NonMonotonicRecords { at: 2 }) => (),
+            x => panic!("Validated reordered records as {x:?}"),
+        }
+
+        message.records[2].timestamp = 900;
+        match message.validate_ordering() {
+            Err(Error::NonMonotonicRecords { at: 2 }) => (),
+            x => panic!("Validated duplicate timestamp as {x:?}"),
+        }
+    }
+
     #[test]
     fn test_sma_inv_get_day_data_deserialization() {
         #[rustfmt::skip]
@@ -239,6 +458,9 @@ mod tests {
                 packet_id: 3,
                 ..Default::default()
             },
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: false,
             start_time_idx: 1700000000,
             end_time_idx: 1750000000,
             records: Vec::new(),
@@ -254,6 +476,64 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sma_inv_meter_value_iter_records() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x56, 0x00, 0x10,
+            0x60, 0x65,
+            0x15, 0xE0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0x00, 0x00, 0x03, 0x00, 0x08, 0x80,
+            0x01, 0x02, 0x00, 0x70,
+            0x04, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00,
+            0x00, 0xF1, 0x53, 0x65, 0xF6, 0x97, 0xC2, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x2C, 0xF2, 0x53, 0x65, 0xFF, 0x97, 0xC2, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x58, 0xF3, 0x53, 0x65, 0x08, 0x98, 0xC2, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x84, 0xF4, 0x53, 0x65, 0x10, 0x98, 0xC2, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        let expected = [
+            (1700000000, 12752886),
+            (1700000300, 12752895),
+            (1700000600, 12752904),
+            (1700000900, 12752912),
+        ];
+        let payload =
+            &serialized[SmaPacketHeader::LENGTH + SmaInvHeader::LENGTH + 8..];
+
+        let mut records = SmaInvMeterValue::iter_records(payload);
+        for (timestamp, energy_wh) in expected {
+            match records.next() {
+                Some(Ok(record)) => {
+                    assert_eq!(timestamp, record.timestamp);
+                    assert_eq!(energy_wh, record.energy_wh);
+                }
+                x => panic!("Expected record, got {x:?}"),
+            }
+        }
+        assert!(records.next().is_none());
+
+        let mut records =
+            SmaInvMeterValue::iter_records(&payload[..payload.len() - 10]);
+        for _ in 0..3 {
+            if !matches!(records.next(), Some(Ok(_))) {
+                panic!("Expected record");
+            }
+        }
+        match records.next() {
+            Some(Err(Error::BufferTooSmall { .. })) => (),
+            x => panic!("Expected truncated record error, got {x:?}"),
+        }
+        assert!(records.next().is_none());
+    }
+
     #[test]
     fn test_sma_inv_get_day_data_response_deserialization() {
         #[rustfmt::skip]
@@ -290,6 +570,9 @@ mod tests {
                 fragment_id: 3,
                 first_fragment: true,
             },
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: true,
             start_time_idx: 4,
             end_time_idx: 8,
             records: {
@@ -322,6 +605,11 @@ mod tests {
         match SmaInvGetDayData::deserialize(&mut cursor) {
             Err(e) => panic!("SmaCmdGetDayData deserialization failed: {e:?}"),
             Ok(message) => {
+                assert_eq!(4, message.expected_record_count());
+                assert_eq!(
+                    message.records.len(),
+                    message.expected_record_count() as usize
+                );
                 assert_eq!(expected, message);
                 assert_eq!(
                     SmaInvGetDayData::LENGTH_MIN + 48,
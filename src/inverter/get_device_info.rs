@@ -0,0 +1,433 @@
+/******************************************************************************\
+    sma-proto - A SMA Speedwire protocol library
+    Copyright (C) 2024 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+
+use super::{
+    CommandFraming, Cursor, DeserializeOptions, Error, Result, SmaCmdWord,
+    SmaEndpoint, SmaInvCounter, SmaInvHeader, SmaPacketFooter, SmaPacketHeader,
+    SmaSerde,
+};
+use byteorder::LittleEndian;
+#[cfg(not(feature = "std"))]
+use core::{
+    clone::Clone,
+    cmp::{Eq, PartialEq},
+    fmt::Debug,
+    option::Option::{self, None, Some},
+    prelude::rust_2021::derive,
+    result::Result::{Err, Ok},
+};
+
+/// A logical GetDeviceInfo message request/response.
+/// This queries the software version of a device within the object ID
+/// range [`Self::FIRST_OBJECT`] to [`Self::LAST_OBJECT`].
+///
+/// Devices which require a two-step exchange answer the first request with
+/// a grant token instead of a version record. The token must be echoed in
+/// the follow-up request, which is then answered with the version.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SmaInvGetDeviceInfo {
+    /// Destination application/device address.
+    pub dst: SmaEndpoint,
+    /// Source application/device address.
+    pub src: SmaEndpoint,
+    /// Non-zero in case of errors.
+    pub error_code: u16,
+    /// Packet counters.
+    pub counters: SmaInvCounter,
+    /// Overrides the destination control word,
+    /// defaults to 0x00 for requests and 0xA0 for responses.
+    /// Deserialization leaves this unset.
+    pub dst_ctrl: Option<u16>,
+    /// Overrides the source control word, defaults to 0.
+    /// Deserialization leaves this unset.
+    pub src_ctrl: Option<u16>,
+    /// Marks the message as a device response,
+    /// see [`SmaCmdWord::is_response`].
+    pub response: bool,
+    /// First object ID (request) or first record number (response).
+    pub first_idx: u32,
+    /// Last object ID (request) or last record number (response).
+    pub last_idx: u32,
+    /// Unix timestamp of the software version record.
+    pub timestamp: u32,
+    /// Packed firmware version in the format of
+    /// [`crate::energymeter::ObisValue::software_version`].
+    /// Absent in requests and if the response contains no version record.
+    pub firmware_version: Option<u32>,
+    /// Grant token of a two-step exchange, appended after the records.
+    /// Absent in single-step exchanges.
+    pub token: Option<u32>,
+}
+
+impl SmaInvGetDeviceInfo {
+    pub const OPCODE: u32 = 0x020058;
+    pub const LENGTH_MIN: usize = SmaPacketHeader::LENGTH
+        + SmaInvHeader::LENGTH
+        + 8
+        + SmaPacketFooter::LENGTH;
+    pub const LENGTH_MAX: usize = Self::LENGTH_MIN + Self::RECORD_LENGTH;
+    /// First object ID of the device info range.
+    pub const FIRST_OBJECT: u32 = 0x00823400;
+    /// Last object ID of the device info range.
+    pub const LAST_OBJECT: u32 = 0x008234FF;
+    /// Object ID of the firmware version.
+    pub const FIRMWARE_VERSION_ID: u32 = 0x00823400;
+
+    /// Serialized length of a device info record.
+    const RECORD_LENGTH: usize = 40;
+    /// Offset of the packed firmware version within a record.
+    const VERSION_OFFSET: usize = 24;
+    /// Serialized length of the grant token.
+    const TOKEN_LENGTH: usize = 4;
+
+    /// Compares two messages ignoring packet counters.
+    pub fn payload_eq(&self, other: &Self) -> bool {
+        self.dst == other.dst
+            && self.src == other.src
+            && self.error_code == other.error_code
+            && self.response == other.response
+            && self.first_idx == other.first_idx
+            && self.last_idx == other.last_idx
+            && self.timestamp == other.timestamp
+            && self.firmware_version == other.firmware_version
+            && self.token == other.token
+    }
+}
+
+impl SmaSerde for SmaInvGetDeviceInfo {
+    fn serialized_len(&self) -> usize {
+        let len = if self.firmware_version.is_some() {
+            Self::LENGTH_MAX
+        } else {
+            Self::LENGTH_MIN
+        };
+
+        if self.token.is_some() {
+            len + Self::TOKEN_LENGTH
+        } else {
+            len
+        }
+    }
+
+    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+        let len = self.serialized_len();
+        buffer.check_remaining(len)?;
+
+        let data_len = len - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH;
+        let header = SmaPacketHeader {
+            data_len,
+            protocol: SmaPacketHeader::SMA_PROTOCOL_INV,
+            ..Default::default()
+        };
+
+        let framing = CommandFraming::lookup(Self::OPCODE, self.response)?;
+
+        let inv_header = SmaInvHeader {
+            wordcount: (data_len / 4) as u8,
+            class: framing.class,
+            dst: self.dst.clone(),
+            dst_ctrl: self.dst_ctrl.unwrap_or(framing.dst_ctrl),
+            src: self.src.clone(),
+            src_ctrl: self.src_ctrl.unwrap_or(framing.src_ctrl),
+            error_code: self.error_code,
+            counters: self.counters.clone(),
+            cmd: SmaCmdWord {
+                channel: framing.channel,
+                opcode: Self::OPCODE,
+            },
+        };
+
+        header.serialize(buffer)?;
+        inv_header.serialize(buffer)?;
+
+        buffer.write_u32::<LittleEndian>(self.first_idx);
+        buffer.write_u32::<LittleEndian>(self.last_idx);
+
+        if let Some(firmware_version) = self.firmware_version {
+            buffer.write_u32::<LittleEndian>(Self::FIRMWARE_VERSION_ID | 0x01);
+            buffer.write_u32::<LittleEndian>(self.timestamp);
+            buffer.write_bytes(&[0; Self::VERSION_OFFSET - 8]);
+            buffer.write_u32::<LittleEndian>(firmware_version);
+            buffer.write_bytes(&[0; Self::RECORD_LENGTH - 28]);
+        }
+
+        if let Some(token) = self.token {
+            buffer.write_u32::<LittleEndian>(token);
+        }
+
+        SmaPacketFooter::default().serialize(buffer)?;
+
+        Ok(())
+    }
+
+    fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
+
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        buffer.check_remaining(options.min_len(Self::LENGTH_MIN))?;
+
+        let header = SmaPacketHeader::deserialize_with(buffer, options)?;
+        header.check_protocol(SmaPacketHeader::SMA_PROTOCOL_INV)?;
+        buffer.check_remaining(header.data_len)?;
+        let padding_len = buffer.remaining() - header.data_len;
+
+        let inv_header = SmaInvHeader::deserialize(buffer)?;
+        inv_header.check_wordcount(header.data_len)?;
+        inv_header.check_framing(Self::OPCODE)?;
+
+        let first_idx = buffer.read_u32::<LittleEndian>();
+        let last_idx = buffer.read_u32::<LittleEndian>();
+
+        let mut timestamp = 0;
+        let mut firmware_version = None;
+        while buffer.remaining() - padding_len >= Self::RECORD_LENGTH {
+            let code = buffer.read_u32::<LittleEndian>();
+            let record_timestamp = buffer.read_u32::<LittleEndian>();
+            buffer.skip(Self::VERSION_OFFSET - 8);
+            let version = buffer.read_u32::<LittleEndian>();
+            buffer.skip(Self::RECORD_LENGTH - 28);
+
+            if code & 0x00FF_FF00 == Self::FIRMWARE_VERSION_ID {
+                timestamp = record_timestamp;
+                firmware_version = Some(version);
+            }
+        }
+
+        let token = if buffer.remaining() - padding_len == Self::TOKEN_LENGTH {
+            Some(buffer.read_u32::<LittleEndian>())
+        } else {
+            None
+        };
+
+        let size = buffer.remaining() - padding_len;
+        if size != 0 {
+            return Err(Error::BufferTooSmall {
+                size,
+                expected: Self::RECORD_LENGTH,
+            });
+        }
+
+        SmaPacketFooter::deserialize_with(buffer, options)?;
+
+        Ok(Self {
+            dst: inv_header.dst,
+            src: inv_header.src,
+            error_code: inv_header.error_code,
+            counters: inv_header.counters,
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: inv_header.cmd.is_response(),
+            first_idx,
+            last_idx,
+            timestamp,
+            firmware_version,
+            token,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sma_inv_get_device_info_serialization() {
+        let message = SmaInvGetDeviceInfo {
+            src: SmaEndpoint::dummy(),
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 2,
+                ..Default::default()
+            },
+            first_idx: SmaInvGetDeviceInfo::FIRST_OBJECT,
+            last_idx: SmaInvGetDeviceInfo::LAST_OBJECT,
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvGetDeviceInfo::LENGTH_MIN];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetDeviceInfo serialization failed: {e:?}");
+        }
+
+        #[rustfmt::skip]
+        let expected = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x26, 0x00, 0x10,
+            0x60, 0x65,
+            0x09, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x80,
+            0x00, 0x02, 0x00, 0x58,
+            0x00, 0x34, 0x82, 0x00, 0xFF, 0x34, 0x82, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(SmaInvGetDeviceInfo::LENGTH_MIN, cursor.position());
+        assert_eq!(message.serialized_len(), cursor.position());
+        assert_eq!(expected, buffer);
+    }
+
+    #[test]
+    fn test_sma_inv_get_device_info_deserialization() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x4E, 0x00, 0x10,
+            0x60, 0x65,
+            0x13, 0xA0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x80,
+            0x01, 0x02, 0x00, 0x58,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x01, 0x34, 0x82, 0x00, 0x00, 0xF1, 0x53, 0x65,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x52, 0x12, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut cursor = Cursor::new(&serialized[..]);
+
+        let expected = SmaInvGetDeviceInfo {
+            dst: SmaEndpoint::dummy(),
+            src: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 2,
+                ..Default::default()
+            },
+            response: true,
+            timestamp: 1700000000,
+            firmware_version: Some(0x02001252),
+            ..Default::default()
+        };
+
+        match SmaInvGetDeviceInfo::deserialize(&mut cursor) {
+            Err(e) => {
+                panic!("SmaInvGetDeviceInfo deserialization failed: {e:?}")
+            }
+            Ok(message) => {
+                assert_eq!(SmaInvGetDeviceInfo::LENGTH_MAX, cursor.position());
+                assert_eq!(expected, message);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_get_device_info_roundtrip() {
+        let message = SmaInvGetDeviceInfo {
+            dst: SmaEndpoint::dummy(),
+            src: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            response: true,
+            timestamp: 1700000000,
+            firmware_version: Some(0x03010A52),
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvGetDeviceInfo::LENGTH_MAX];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetDeviceInfo serialization failed: {e:?}");
+        }
+
+        let mut cursor = Cursor::new(&buffer[..]);
+        match SmaInvGetDeviceInfo::deserialize(&mut cursor) {
+            Err(e) => {
+                panic!("SmaInvGetDeviceInfo deserialization failed: {e:?}")
+            }
+            Ok(deserialized) => assert!(message.payload_eq(&deserialized)),
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_get_device_info_grant_deserialization() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x2A, 0x00, 0x10,
+            0x60, 0x65,
+            0x0A, 0xA0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x80,
+            0x01, 0x02, 0x00, 0x58,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x01, 0x00, 0xFE, 0xCA,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut cursor = Cursor::new(&serialized[..]);
+
+        match SmaInvGetDeviceInfo::deserialize(&mut cursor) {
+            Err(e) => {
+                panic!("SmaInvGetDeviceInfo deserialization failed: {e:?}")
+            }
+            Ok(message) => {
+                assert_eq!(serialized.len(), cursor.position());
+                assert!(message.response);
+                assert_eq!(Some(0xCAFE0001), message.token);
+                assert_eq!(None, message.firmware_version);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_get_device_info_token_roundtrip() {
+        let message = SmaInvGetDeviceInfo {
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            src: SmaEndpoint::dummy(),
+            first_idx: SmaInvGetDeviceInfo::FIRST_OBJECT,
+            last_idx: SmaInvGetDeviceInfo::LAST_OBJECT,
+            token: Some(0xCAFE0001),
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvGetDeviceInfo::LENGTH_MIN + 4];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetDeviceInfo serialization failed: {e:?}");
+        }
+        assert_eq!(message.serialized_len(), cursor.position());
+        assert_eq!([0x01, 0x00, 0xFE, 0xCA], buffer[54..58]);
+
+        let mut cursor = Cursor::new(&buffer[..]);
+        match SmaInvGetDeviceInfo::deserialize(&mut cursor) {
+            Err(e) => {
+                panic!("SmaInvGetDeviceInfo deserialization failed: {e:?}")
+            }
+            Ok(deserialized) => assert!(message.payload_eq(&deserialized)),
+        }
+    }
+}
@@ -0,0 +1,376 @@
+/******************************************************************************\
+    sma-proto - A SMA Speedwire protocol library
+    Copyright (C) 2024 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::{
+    CommandFraming, Cursor, DeserializeOptions, Error, Result, SmaCmdWord,
+    SmaEndpoint, SmaInvCounter, SmaInvHeader, SmaPacketFooter, SmaPacketHeader,
+    SmaSerde,
+};
+use byteorder::LittleEndian;
+#[cfg(not(feature = "std"))]
+use core::{
+    clone::Clone,
+    cmp::{Eq, PartialEq},
+    fmt::Debug,
+    option::Option::None,
+    prelude::rust_2021::derive,
+    result::Result::{Err, Ok},
+};
+#[cfg(not(feature = "std"))]
+use heapless::Vec;
+
+/// A single timestamped entry of the inverter event log.
+///
+/// Event records are 48 bytes long. Besides the decoded fields they contain
+/// the entry number, the device address, event flags and parameter values
+/// which are skipped during deserialization and serialized as zero.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EventRecord {
+    /// Unix timestamp of the event.
+    pub timestamp: u32,
+    /// Event number as shown in the device event log.
+    pub event_id: u16,
+    /// Object ID of the text describing the event.
+    pub tag: u32,
+}
+
+impl EventRecord {
+    pub const LENGTH: usize = 48;
+    /// Offset of the event ID in the record.
+    const EVENT_ID_OFFSET: usize = 12;
+    /// Offset of the tag in the record.
+    const TAG_OFFSET: usize = 24;
+}
+
+impl SmaSerde for EventRecord {
+    fn serialized_len(&self) -> usize {
+        Self::LENGTH
+    }
+
+    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+        buffer.check_remaining(Self::LENGTH)?;
+
+        buffer.write_u32::<LittleEndian>(self.timestamp);
+        buffer.write_bytes(&[0; Self::EVENT_ID_OFFSET - 4]);
+        buffer.write_u16::<LittleEndian>(self.event_id);
+        buffer.write_bytes(&[0; Self::TAG_OFFSET - Self::EVENT_ID_OFFSET - 2]);
+        buffer.write_u32::<LittleEndian>(self.tag);
+        buffer.write_bytes(&[0; Self::LENGTH - Self::TAG_OFFSET - 4]);
+
+        Ok(())
+    }
+
+    fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
+        buffer.check_remaining(Self::LENGTH)?;
+
+        let timestamp = buffer.read_u32::<LittleEndian>();
+        buffer.skip(Self::EVENT_ID_OFFSET - 4);
+        let event_id = buffer.read_u16::<LittleEndian>();
+        buffer.skip(Self::TAG_OFFSET - Self::EVENT_ID_OFFSET - 2);
+        let tag = buffer.read_u32::<LittleEndian>();
+        buffer.skip(Self::LENGTH - Self::TAG_OFFSET - 4);
+
+        Ok(Self {
+            timestamp,
+            event_id,
+            tag,
+        })
+    }
+}
+
+/// A logical GetEvents message request/response which reads the user
+/// event log of the device. Like [`SmaInvGetDayData`](super::SmaInvGetDayData)
+/// responses, large event logs are split into multiple fragments.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SmaInvGetEvents {
+    /// Destination application/device address.
+    pub dst: SmaEndpoint,
+    /// Source application/device address.
+    pub src: SmaEndpoint,
+    /// Non-zero in case of errors.
+    pub error_code: u16,
+    /// Packet counters.
+    pub counters: SmaInvCounter,
+    /// Overrides the destination control word,
+    /// defaults to 0x00 for requests and 0xA0 for responses.
+    /// Deserialization leaves this unset.
+    pub dst_ctrl: Option<u16>,
+    /// Overrides the source control word, defaults to 0.
+    /// Deserialization leaves this unset.
+    pub src_ctrl: Option<u16>,
+    /// Marks the message as a device response,
+    /// see [`SmaCmdWord::is_response`].
+    pub response: bool,
+    /// Start timestamp (request) or start record number (response).
+    pub start_time_idx: u32,
+    /// End timestamp (request) or end record number (response).
+    pub end_time_idx: u32,
+    #[cfg(not(feature = "std"))]
+    /// Timestamped event log entries.
+    pub records: Vec<EventRecord, { Self::MAX_RECORD_COUNT }>,
+    /// Timestamped event log entries.
+    #[cfg(feature = "std")]
+    pub records: Vec<EventRecord>,
+}
+
+impl SmaInvGetEvents {
+    pub const OPCODE: u32 = 0x021070;
+    pub const LENGTH_MIN: usize = SmaPacketHeader::LENGTH
+        + SmaInvHeader::LENGTH
+        + 8
+        + SmaPacketFooter::LENGTH;
+    pub const LENGTH_MAX: usize =
+        Self::LENGTH_MIN + Self::MAX_RECORD_COUNT * EventRecord::LENGTH;
+    pub const MAX_RECORD_COUNT: usize = 20;
+
+    /// Checks that the number of records does not exceed the maximum.
+    pub fn validate(&self) -> Result<()> {
+        if self.records.len() > Self::MAX_RECORD_COUNT {
+            return Err(Error::PayloadTooLarge {
+                len: self.records.len(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl SmaSerde for SmaInvGetEvents {
+    fn serialized_len(&self) -> usize {
+        self.records
+            .len()
+            .saturating_mul(EventRecord::LENGTH)
+            .saturating_add(Self::LENGTH_MIN)
+    }
+
+    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+        self.validate()?;
+
+        let len = self.serialized_len();
+        buffer.check_remaining(len)?;
+
+        let data_len = len - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH;
+        let header = SmaPacketHeader {
+            data_len,
+            protocol: SmaPacketHeader::SMA_PROTOCOL_INV,
+            ..Default::default()
+        };
+
+        let framing = CommandFraming::lookup(Self::OPCODE, self.response)?;
+
+        let inv_header = SmaInvHeader {
+            wordcount: (data_len / 4) as u8,
+            class: framing.class,
+            dst: self.dst.clone(),
+            dst_ctrl: self.dst_ctrl.unwrap_or(framing.dst_ctrl),
+            src: self.src.clone(),
+            src_ctrl: self.src_ctrl.unwrap_or(framing.src_ctrl),
+            error_code: self.error_code,
+            counters: self.counters.clone(),
+            cmd: SmaCmdWord {
+                channel: framing.channel,
+                opcode: Self::OPCODE,
+            },
+        };
+
+        header.serialize(buffer)?;
+        inv_header.serialize(buffer)?;
+
+        buffer.write_u32::<LittleEndian>(self.start_time_idx);
+        buffer.write_u32::<LittleEndian>(self.end_time_idx);
+
+        for record in &self.records {
+            record.serialize(buffer)?;
+        }
+
+        SmaPacketFooter::default().serialize(buffer)?;
+
+        Ok(())
+    }
+
+    fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
+
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        buffer.check_remaining(options.min_len(Self::LENGTH_MIN))?;
+
+        let header = SmaPacketHeader::deserialize_with(buffer, options)?;
+        header.check_protocol(SmaPacketHeader::SMA_PROTOCOL_INV)?;
+        buffer.check_remaining(header.data_len)?;
+        let padding_len = buffer.remaining() - header.data_len;
+
+        let inv_header = SmaInvHeader::deserialize(buffer)?;
+        inv_header.check_wordcount(header.data_len)?;
+        inv_header.check_framing(Self::OPCODE)?;
+
+        let start_time_idx = buffer.read_u32::<LittleEndian>();
+        let end_time_idx = buffer.read_u32::<LittleEndian>();
+
+        let mut records = Vec::default();
+        while buffer.remaining() - padding_len >= EventRecord::LENGTH {
+            let record = EventRecord::deserialize(buffer)?;
+
+            #[cfg(feature = "std")]
+            records.push(record);
+            #[cfg(not(feature = "std"))]
+            if records.push(record).is_err() {
+                return Err(Error::PayloadTooLarge {
+                    len: records.len() + 1,
+                });
+            }
+        }
+
+        SmaPacketFooter::deserialize_with(buffer, options)?;
+
+        Ok(Self {
+            dst: inv_header.dst,
+            src: inv_header.src,
+            error_code: inv_header.error_code,
+            counters: inv_header.counters,
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: inv_header.cmd.is_response(),
+            start_time_idx,
+            end_time_idx,
+            records,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sma_inv_get_events_serialization() {
+        let message = SmaInvGetEvents {
+            src: SmaEndpoint::dummy(),
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 3,
+                ..Default::default()
+            },
+            start_time_idx: 1700000000,
+            end_time_idx: 1750000000,
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvGetEvents::LENGTH_MIN];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetEvents serialization failed: {e:?}");
+        }
+
+        #[rustfmt::skip]
+        let expected = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x26, 0x00, 0x10,
+            0x60, 0x65,
+            0x09, 0xE0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x03, 0x80,
+            0x00, 0x02, 0x10, 0x70,
+            0x00, 0xF1, 0x53, 0x65, 0x80, 0xE1, 0x4E, 0x68,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(SmaInvGetEvents::LENGTH_MIN, cursor.position());
+        assert_eq!(message.serialized_len(), cursor.position());
+        assert_eq!(expected, buffer);
+    }
+
+    #[test]
+    fn test_sma_inv_get_events_deserialization() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x86, 0x00, 0x10,
+            0x60, 0x65,
+            0x21, 0xE0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x03, 0x80,
+            0x01, 0x02, 0x10, 0x70,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+            // Event 1
+            0x00, 0xF1, 0x53, 0x65, 0x01, 0x00, 0x78, 0x56,
+            0xCE, 0xAB, 0xCD, 0xAB, 0x7E, 0x27, 0x00, 0x00,
+            0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xA9, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // Event 2
+            0x2C, 0xF2, 0x53, 0x65, 0x02, 0x00, 0x78, 0x56,
+            0xCE, 0xAB, 0xCD, 0xAB, 0xBF, 0x01, 0x00, 0x00,
+            0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x54, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let mut records = Vec::new();
+        for record in [
+            EventRecord {
+                timestamp: 1700000000,
+                event_id: 10110,
+                tag: 0x03A9,
+            },
+            EventRecord {
+                timestamp: 1700000300,
+                event_id: 447,
+                tag: 0x0454,
+            },
+        ] {
+            #[allow(clippy::let_unit_value)]
+            let _ = records.push(record);
+        }
+        let expected = SmaInvGetEvents {
+            dst: SmaEndpoint::dummy(),
+            src: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 3,
+                ..Default::default()
+            },
+            start_time_idx: 0,
+            end_time_idx: 2,
+            records,
+            response: true,
+            ..Default::default()
+        };
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaInvGetEvents::deserialize(&mut cursor) {
+            Err(e) => panic!("SmaInvGetEvents deserialization failed: {e:?}"),
+            Ok(message) => {
+                assert_eq!(expected, message);
+                assert_eq!(serialized.len(), cursor.position());
+            }
+        }
+    }
+}
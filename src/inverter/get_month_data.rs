@@ -0,0 +1,361 @@
+/******************************************************************************\
+    sma-proto - A SMA Speedwire protocol library
+    Copyright (C) 2024 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::{
+    CommandFraming, Cursor, DeserializeOptions, Error, Result, SmaCmdWord,
+    SmaEndpoint, SmaInvCounter, SmaInvGetDayData, SmaInvHeader,
+    SmaInvMeterValue, SmaPacketFooter, SmaPacketHeader, SmaSerde,
+};
+use byteorder::LittleEndian;
+#[cfg(not(feature = "std"))]
+use core::{
+    clone::Clone,
+    cmp::{Eq, PartialEq},
+    fmt::Debug,
+    option::Option::None,
+    prelude::rust_2021::derive,
+    result::Result::{Err, Ok},
+};
+#[cfg(not(feature = "std"))]
+use heapless::Vec;
+
+/// A logical GetMonthData message request/response.
+/// The month archive holds one total energy production counter value per
+/// day, while [`SmaInvGetDayData`] returns the 5 minute values of the
+/// same counter.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SmaInvGetMonthData {
+    /// Destination application/device address.
+    pub dst: SmaEndpoint,
+    /// Source application/device address.
+    pub src: SmaEndpoint,
+    /// Non-zero in case of errors.
+    pub error_code: u16,
+    /// Packet counters.
+    pub counters: SmaInvCounter,
+    /// Overrides the destination control word,
+    /// defaults to 0x00 for requests and 0xA0 for responses.
+    /// Deserialization leaves this unset.
+    pub dst_ctrl: Option<u16>,
+    /// Overrides the source control word, defaults to 0.
+    /// Deserialization leaves this unset.
+    pub src_ctrl: Option<u16>,
+    /// Marks the message as a device response,
+    /// see [`SmaCmdWord::is_response`].
+    pub response: bool,
+    /// Start timestamp (request) or start record number (response).
+    pub start_time_idx: u32,
+    /// End timestamp (request) or end record number (response).
+    pub end_time_idx: u32,
+    #[cfg(not(feature = "std"))]
+    /// Daily timestamped total energy production values.
+    pub records: Vec<SmaInvMeterValue, { Self::MAX_RECORD_COUNT }>,
+    /// Daily timestamped total energy production values.
+    #[cfg(feature = "std")]
+    pub records: Vec<SmaInvMeterValue>,
+}
+
+impl SmaInvGetMonthData {
+    pub const OPCODE: u32 = 0x022070;
+    pub const LENGTH_MIN: usize = SmaPacketHeader::LENGTH
+        + SmaInvHeader::LENGTH
+        + 8
+        + SmaPacketFooter::LENGTH;
+    pub const LENGTH_MAX: usize =
+        Self::LENGTH_MIN + Self::MAX_RECORD_COUNT * SmaInvMeterValue::LENGTH;
+    pub const MAX_RECORD_COUNT: usize = SmaInvGetDayData::MAX_RECORD_COUNT;
+
+    /// Serialized length of this message.
+    /// Saturates at `usize::MAX` for unrepresentable record counts.
+    pub fn serialized_len(&self) -> usize {
+        Self::len_for_records(self.records.len()).unwrap_or(usize::MAX)
+    }
+
+    /// Checks that the number of records does not exceed the maximum.
+    pub fn validate(&self) -> Result<()> {
+        if self.records.len() > Self::MAX_RECORD_COUNT {
+            return Err(Error::PayloadTooLarge {
+                len: self.records.len(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns the serialized length of a message with `count` records or
+    /// [`Error::PayloadTooLarge`] if it is not representable.
+    fn len_for_records(count: usize) -> Result<usize> {
+        count
+            .checked_mul(SmaInvMeterValue::LENGTH)
+            .and_then(|len| len.checked_add(Self::LENGTH_MIN))
+            .ok_or(Error::PayloadTooLarge { len: count })
+    }
+
+    /// Checks that the record timestamps are strictly increasing,
+    /// see [`SmaInvGetDayData::validate_record_ordering`].
+    pub fn validate_ordering(&self) -> Result<()> {
+        SmaInvGetDayData::validate_record_ordering(&self.records)
+    }
+
+    /// Compares two messages ignoring packet counters.
+    pub fn payload_eq(&self, other: &Self) -> bool {
+        self.dst == other.dst
+            && self.src == other.src
+            && self.error_code == other.error_code
+            && self.response == other.response
+            && self.start_time_idx == other.start_time_idx
+            && self.end_time_idx == other.end_time_idx
+            && self.records == other.records
+    }
+}
+
+impl SmaSerde for SmaInvGetMonthData {
+    fn serialized_len(&self) -> usize {
+        SmaInvGetMonthData::serialized_len(self)
+    }
+
+    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+        self.validate()?;
+
+        let len = Self::len_for_records(self.records.len())?;
+        buffer.check_remaining(len)?;
+
+        let data_len = len - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH;
+        let header = SmaPacketHeader {
+            data_len,
+            protocol: SmaPacketHeader::SMA_PROTOCOL_INV,
+            ..Default::default()
+        };
+
+        let framing = CommandFraming::lookup(Self::OPCODE, self.response)?;
+
+        let inv_header = SmaInvHeader {
+            wordcount: (data_len / 4) as u8,
+            class: framing.class,
+            dst: self.dst.clone(),
+            dst_ctrl: self.dst_ctrl.unwrap_or(framing.dst_ctrl),
+            src: self.src.clone(),
+            src_ctrl: self.src_ctrl.unwrap_or(framing.src_ctrl),
+            error_code: self.error_code,
+            counters: self.counters.clone(),
+            cmd: SmaCmdWord {
+                channel: framing.channel,
+                opcode: Self::OPCODE,
+            },
+        };
+
+        header.serialize(buffer)?;
+        inv_header.serialize(buffer)?;
+
+        buffer.write_u32::<LittleEndian>(self.start_time_idx);
+        buffer.write_u32::<LittleEndian>(self.end_time_idx);
+
+        for record in &self.records {
+            record.serialize(buffer)?;
+        }
+
+        SmaPacketFooter::default().serialize(buffer)?;
+
+        Ok(())
+    }
+
+    fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
+
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        buffer.check_remaining(options.min_len(Self::LENGTH_MIN))?;
+
+        let header = SmaPacketHeader::deserialize_with(buffer, options)?;
+        header.check_protocol(SmaPacketHeader::SMA_PROTOCOL_INV)?;
+        buffer.check_remaining(header.data_len)?;
+        let padding_len = buffer.remaining() - header.data_len;
+
+        let inv_header = SmaInvHeader::deserialize(buffer)?;
+        inv_header.check_wordcount(header.data_len)?;
+        inv_header.check_framing(Self::OPCODE)?;
+
+        let start_time_idx = buffer.read_u32::<LittleEndian>();
+        let end_time_idx = buffer.read_u32::<LittleEndian>();
+
+        let mut records = Vec::default();
+        while buffer.remaining() - padding_len >= SmaInvMeterValue::LENGTH {
+            let record = SmaInvMeterValue::deserialize(buffer)?;
+
+            #[cfg(feature = "std")]
+            records.push(record);
+            #[cfg(not(feature = "std"))]
+            if records.push(record).is_err() {
+                return Err(Error::PayloadTooLarge {
+                    len: records.len() + 1,
+                });
+            }
+        }
+
+        SmaPacketFooter::deserialize_with(buffer, options)?;
+
+        Ok(Self {
+            dst: inv_header.dst,
+            src: inv_header.src,
+            error_code: inv_header.error_code,
+            counters: inv_header.counters,
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: inv_header.cmd.is_response(),
+            start_time_idx,
+            end_time_idx,
+            records,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sma_inv_get_month_data_serialization() {
+        let message = SmaInvGetMonthData {
+            src: SmaEndpoint::dummy(),
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 3,
+                ..Default::default()
+            },
+            start_time_idx: 1700000000,
+            end_time_idx: 1750000000,
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvGetMonthData::LENGTH_MIN];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetMonthData serialization failed: {e:?}");
+        }
+
+        #[rustfmt::skip]
+        let expected = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x26, 0x00, 0x10,
+            0x60, 0x65,
+            0x09, 0xE0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x03, 0x80,
+            0x00, 0x02, 0x20, 0x70,
+            0x00, 0xF1, 0x53, 0x65, 0x80, 0xE1, 0x4E, 0x68,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(SmaInvGetMonthData::LENGTH_MIN, cursor.position());
+        assert_eq!(message.serialized_len(), cursor.position());
+        assert_eq!(expected, buffer);
+    }
+
+    #[test]
+    fn test_sma_inv_get_month_data_response_deserialization() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x3E, 0x00, 0x10,
+            0x60, 0x65,
+            0x0F, 0xE0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x08, 0x80,
+            0x01, 0x02, 0x20, 0x70,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+            0x00, 0x99, 0x5C, 0x65, 0xF6, 0x97, 0xC2, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x80, 0xEA, 0x5D, 0x65, 0x3A, 0xC5, 0xC2, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let expected = SmaInvGetMonthData {
+            dst: SmaEndpoint::dummy(),
+            src: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 8,
+                fragment_id: 0,
+                first_fragment: true,
+            },
+            response: true,
+            start_time_idx: 0,
+            end_time_idx: 2,
+            records: {
+                let mut records = Vec::default();
+                #[allow(clippy::let_unit_value)]
+                let _ = records.push(SmaInvMeterValue {
+                    timestamp: 1700567296,
+                    energy_wh: 12752886,
+                });
+                #[allow(clippy::let_unit_value)]
+                let _ = records.push(SmaInvMeterValue {
+                    timestamp: 1700653696,
+                    energy_wh: 12764474,
+                });
+                records
+            },
+            ..Default::default()
+        };
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaInvGetMonthData::deserialize(&mut cursor) {
+            Err(e) => {
+                panic!("SmaInvGetMonthData deserialization failed: {e:?}")
+            }
+            Ok(message) => {
+                assert_eq!(expected, message);
+                assert_eq!(
+                    SmaInvGetMonthData::LENGTH_MIN + 24,
+                    cursor.position()
+                );
+                if let Err(e) = message.validate_ordering() {
+                    panic!("Daily records failed validation: {e:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_get_month_data_rejects_day_data() {
+        let message = SmaInvGetDayData::default();
+        let mut buffer = [0u8; SmaInvGetDayData::LENGTH_MIN];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetDayData serialization failed: {e:?}");
+        }
+
+        let mut cursor = Cursor::new(&buffer[..]);
+        match SmaInvGetMonthData::deserialize(&mut cursor) {
+            Err(Error::UnsupportedOpcode {
+                opcode: SmaInvGetDayData::OPCODE,
+            }) => (),
+            x => panic!("Deserialized day data as month data: {x:?}"),
+        }
+    }
+}
@@ -0,0 +1,405 @@
+/******************************************************************************\
+    sma-proto - A SMA Speedwire protocol library
+    Copyright (C) 2024 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::{
+    decode_spot_i32, CommandFraming, Cursor, DeserializeOptions, Error, Result,
+    SmaCmdWord, SmaEndpoint, SmaInvCounter, SmaInvHeader, SmaPacketFooter,
+    SmaPacketHeader, SmaSerde,
+};
+use byteorder::LittleEndian;
+#[cfg(not(feature = "std"))]
+use core::{
+    clone::Clone,
+    cmp::{Eq, PartialEq},
+    fmt::Debug,
+    option::Option::{self, None, Some},
+    prelude::rust_2021::derive,
+    result::Result::{Err, Ok},
+};
+
+/// A logical GetTemperature message request/response.
+/// This queries the internal device temperature of an inverter.
+/// The value is transferred as signed spot value in units of 0.1 °C.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SmaInvGetTemperature {
+    /// Destination application/device address.
+    pub dst: SmaEndpoint,
+    /// Source application/device address.
+    pub src: SmaEndpoint,
+    /// Non-zero in case of errors.
+    pub error_code: u16,
+    /// Packet counters.
+    pub counters: SmaInvCounter,
+    /// Overrides the destination control word,
+    /// defaults to 0x00 for requests and 0xA0 for responses.
+    /// Deserialization leaves this unset.
+    pub dst_ctrl: Option<u16>,
+    /// Overrides the source control word, defaults to 0.
+    /// Deserialization leaves this unset.
+    pub src_ctrl: Option<u16>,
+    /// Marks the message as a device response,
+    /// see [`SmaCmdWord::is_response`].
+    pub response: bool,
+    /// First object ID (request) or first record number (response).
+    pub first_idx: u32,
+    /// Last object ID (request) or last record number (response).
+    pub last_idx: u32,
+    /// Unix timestamp of the temperature value.
+    pub timestamp: u32,
+    /// Raw device temperature in 0.1 °C, see [`Self::temperature_c`].
+    /// Absent in requests and if the device reported an invalid value.
+    pub raw_temperature: Option<i32>,
+}
+
+impl SmaInvGetTemperature {
+    pub const OPCODE: u32 = 0x020052;
+    pub const LENGTH_MIN: usize = SmaPacketHeader::LENGTH
+        + SmaInvHeader::LENGTH
+        + 8
+        + SmaPacketFooter::LENGTH;
+    pub const LENGTH_MAX: usize = Self::LENGTH_MIN + Self::RECORD_LENGTH;
+    /// First object ID of the temperature range.
+    pub const FIRST_OBJECT: u32 = 0x00237700;
+    /// Last object ID of the temperature range.
+    pub const LAST_OBJECT: u32 = 0x002377FF;
+    /// Object ID of the internal device temperature.
+    pub const TEMPERATURE_ID: u32 = 0x00237700;
+
+    /// Serialized length of a temperature record.
+    const RECORD_LENGTH: usize = 28;
+    /// Record data type of signed 32bit values.
+    const DATA_TYPE_S32: u32 = 0x40;
+
+    /// Device temperature in °C with the 0.1 scale applied.
+    pub fn temperature_c(&self) -> Option<f32> {
+        self.raw_temperature.map(|raw| raw as f32 / 10.0)
+    }
+
+    /// Compares two messages ignoring packet counters.
+    pub fn payload_eq(&self, other: &Self) -> bool {
+        self.dst == other.dst
+            && self.src == other.src
+            && self.error_code == other.error_code
+            && self.response == other.response
+            && self.first_idx == other.first_idx
+            && self.last_idx == other.last_idx
+            && self.timestamp == other.timestamp
+            && self.raw_temperature == other.raw_temperature
+    }
+}
+
+impl SmaSerde for SmaInvGetTemperature {
+    fn serialized_len(&self) -> usize {
+        if self.raw_temperature.is_some() {
+            Self::LENGTH_MAX
+        } else {
+            Self::LENGTH_MIN
+        }
+    }
+
+    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+        let len = self.serialized_len();
+        buffer.check_remaining(len)?;
+
+        let data_len = len - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH;
+        let header = SmaPacketHeader {
+            data_len,
+            protocol: SmaPacketHeader::SMA_PROTOCOL_INV,
+            ..Default::default()
+        };
+
+        let framing = CommandFraming::lookup(Self::OPCODE, self.response)?;
+
+        let inv_header = SmaInvHeader {
+            wordcount: (data_len / 4) as u8,
+            class: framing.class,
+            dst: self.dst.clone(),
+            dst_ctrl: self.dst_ctrl.unwrap_or(framing.dst_ctrl),
+            src: self.src.clone(),
+            src_ctrl: self.src_ctrl.unwrap_or(framing.src_ctrl),
+            error_code: self.error_code,
+            counters: self.counters.clone(),
+            cmd: SmaCmdWord {
+                channel: framing.channel,
+                opcode: Self::OPCODE,
+            },
+        };
+
+        header.serialize(buffer)?;
+        inv_header.serialize(buffer)?;
+
+        buffer.write_u32::<LittleEndian>(self.first_idx);
+        buffer.write_u32::<LittleEndian>(self.last_idx);
+
+        if let Some(raw) = self.raw_temperature {
+            let code =
+                (Self::DATA_TYPE_S32 << 24) | Self::TEMPERATURE_ID | 0x01;
+            buffer.write_u32::<LittleEndian>(code);
+            buffer.write_u32::<LittleEndian>(self.timestamp);
+            for _ in 0..5 {
+                buffer.write_u32::<LittleEndian>(raw as u32);
+            }
+        }
+
+        SmaPacketFooter::default().serialize(buffer)?;
+
+        Ok(())
+    }
+
+    fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
+
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        buffer.check_remaining(options.min_len(Self::LENGTH_MIN))?;
+
+        let header = SmaPacketHeader::deserialize_with(buffer, options)?;
+        header.check_protocol(SmaPacketHeader::SMA_PROTOCOL_INV)?;
+        buffer.check_remaining(header.data_len)?;
+        let padding_len = buffer.remaining() - header.data_len;
+
+        let inv_header = SmaInvHeader::deserialize(buffer)?;
+        inv_header.check_wordcount(header.data_len)?;
+        inv_header.check_framing(Self::OPCODE)?;
+
+        let first_idx = buffer.read_u32::<LittleEndian>();
+        let last_idx = buffer.read_u32::<LittleEndian>();
+
+        let mut timestamp = 0;
+        let mut raw_temperature = None;
+        while buffer.remaining() - padding_len >= Self::RECORD_LENGTH {
+            let code = buffer.read_u32::<LittleEndian>();
+            let record_timestamp = buffer.read_u32::<LittleEndian>();
+            buffer.skip(8);
+            let raw = buffer.read_u32::<LittleEndian>();
+            buffer.skip(8);
+
+            if code & 0x00FF_FF00 == Self::TEMPERATURE_ID {
+                timestamp = record_timestamp;
+                raw_temperature = decode_spot_i32(raw);
+            }
+        }
+
+        let size = buffer.remaining() - padding_len;
+        if size != 0 {
+            return Err(Error::BufferTooSmall {
+                size,
+                expected: Self::RECORD_LENGTH,
+            });
+        }
+
+        SmaPacketFooter::deserialize_with(buffer, options)?;
+
+        Ok(Self {
+            dst: inv_header.dst,
+            src: inv_header.src,
+            error_code: inv_header.error_code,
+            counters: inv_header.counters,
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: inv_header.cmd.is_response(),
+            first_idx,
+            last_idx,
+            timestamp,
+            raw_temperature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    fn serialized_response(raw: [u8; 4]) -> [u8; 86] {
+        let mut serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x42, 0x00, 0x10,
+            0x60, 0x65,
+            0x10, 0xA0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x07, 0x80,
+            0x01, 0x02, 0x00, 0x52,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x01, 0x77, 0x23, 0x40, 0x00, 0xF1, 0x53, 0x65,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        serialized[70..74].copy_from_slice(&raw);
+        serialized
+    }
+
+    #[test]
+    fn test_sma_inv_get_temperature_serialization() {
+        let message = SmaInvGetTemperature {
+            src: SmaEndpoint::dummy(),
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 7,
+                ..Default::default()
+            },
+            first_idx: SmaInvGetTemperature::FIRST_OBJECT,
+            last_idx: SmaInvGetTemperature::LAST_OBJECT,
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvGetTemperature::LENGTH_MIN];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetTemperature serialization failed: {e:?}");
+        }
+
+        #[rustfmt::skip]
+        let expected = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x26, 0x00, 0x10,
+            0x60, 0x65,
+            0x09, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x07, 0x80,
+            0x00, 0x02, 0x00, 0x52,
+            0x00, 0x77, 0x23, 0x00, 0xFF, 0x77, 0x23, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(SmaInvGetTemperature::LENGTH_MIN, cursor.position());
+        assert_eq!(expected, buffer);
+    }
+
+    #[test]
+    fn test_sma_inv_get_temperature_deserialization() {
+        let serialized = serialized_response([0xC5, 0x01, 0x00, 0x00]);
+        let expected = SmaInvGetTemperature {
+            dst: SmaEndpoint::dummy(),
+            src: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 7,
+                ..Default::default()
+            },
+            timestamp: 1700000000,
+            raw_temperature: Some(453),
+            response: true,
+            ..Default::default()
+        };
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaInvGetTemperature::deserialize(&mut cursor) {
+            Err(e) => {
+                panic!("SmaInvGetTemperature deserialization failed: {e:?}")
+            }
+            Ok(message) => {
+                assert_eq!(expected, message);
+                assert_eq!(Some(45.3), message.temperature_c());
+                assert_eq!(SmaInvGetTemperature::LENGTH_MAX, cursor.position());
+            }
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_get_temperature_deserialization_nan() {
+        let serialized = serialized_response([0x00, 0x00, 0x00, 0x80]);
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaInvGetTemperature::deserialize(&mut cursor) {
+            Err(e) => {
+                panic!("SmaInvGetTemperature deserialization failed: {e:?}")
+            }
+            Ok(message) => {
+                assert!(message.response);
+                assert_eq!(1700000000, message.timestamp);
+                assert_eq!(None, message.raw_temperature);
+                assert_eq!(None, message.temperature_c());
+            }
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_get_temperature_roundtrip() {
+        let message = SmaInvGetTemperature {
+            dst: SmaEndpoint::dummy(),
+            src: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            timestamp: 1700000000,
+            raw_temperature: Some(-125),
+            response: true,
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvGetTemperature::LENGTH_MAX];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetTemperature serialization failed: {e:?}");
+        }
+
+        let mut cursor = Cursor::new(&buffer[..]);
+        match SmaInvGetTemperature::deserialize(&mut cursor) {
+            Err(e) => {
+                panic!("SmaInvGetTemperature deserialization failed: {e:?}")
+            }
+            Ok(deserialized) => {
+                assert!(message.payload_eq(&deserialized));
+                assert_eq!(Some(-12.5), deserialized.temperature_c());
+            }
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_get_temperature_roundtrip_invalid_response() {
+        let message = SmaInvGetTemperature {
+            dst: SmaEndpoint::dummy(),
+            src: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            response: true,
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvGetTemperature::LENGTH_MIN];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetTemperature serialization failed: {e:?}");
+        }
+
+        let mut cursor = Cursor::new(&buffer[..]);
+        match SmaInvGetTemperature::deserialize(&mut cursor) {
+            Err(e) => {
+                panic!("SmaInvGetTemperature deserialization failed: {e:?}")
+            }
+            Ok(deserialized) => {
+                assert!(message.payload_eq(&deserialized));
+                assert!(deserialized.response);
+                assert_eq!(None, deserialized.temperature_c());
+            }
+        }
+    }
+}
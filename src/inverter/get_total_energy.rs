@@ -0,0 +1,337 @@
+/******************************************************************************\
+    sma-proto - A SMA Speedwire protocol library
+    Copyright (C) 2024 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::{
+    CommandFraming, Cursor, DeserializeOptions, Error, Result, SmaCmdWord,
+    SmaEndpoint, SmaInvCounter, SmaInvHeader, SmaPacketFooter, SmaPacketHeader,
+    SmaSerde,
+};
+use byteorder::LittleEndian;
+#[cfg(not(feature = "std"))]
+use core::{
+    clone::Clone,
+    cmp::{Eq, PartialEq},
+    fmt::Debug,
+    option::Option::{self, None, Some},
+    prelude::rust_2021::derive,
+    result::Result::{Err, Ok},
+};
+
+/// A logical GetTotalEnergy message request/response.
+/// This queries the current total energy production counter of a device
+/// as a single value, which avoids transferring a full day of records with
+/// [`super::SmaInvGetDayData`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SmaInvGetTotalEnergy {
+    /// Destination application/device address.
+    pub dst: SmaEndpoint,
+    /// Source application/device address.
+    pub src: SmaEndpoint,
+    /// Non-zero in case of errors.
+    pub error_code: u16,
+    /// Packet counters.
+    pub counters: SmaInvCounter,
+    /// Overrides the destination control word,
+    /// defaults to 0x00 for requests and 0xA0 for responses.
+    /// Deserialization leaves this unset.
+    pub dst_ctrl: Option<u16>,
+    /// Overrides the source control word, defaults to 0.
+    /// Deserialization leaves this unset.
+    pub src_ctrl: Option<u16>,
+    /// Marks the message as a device response,
+    /// see [`SmaCmdWord::is_response`].
+    pub response: bool,
+    /// First object ID (request) or first record number (response).
+    pub first_idx: u32,
+    /// Last object ID (request) or last record number (response).
+    pub last_idx: u32,
+    /// Unix timestamp of the energy counter value.
+    pub timestamp: u32,
+    /// Total produced energy in Wh.
+    /// Absent in requests and if the device reported an invalid value.
+    pub energy_wh: Option<u64>,
+}
+
+impl SmaInvGetTotalEnergy {
+    pub const OPCODE: u32 = 0x020054;
+    pub const LENGTH_MIN: usize = SmaPacketHeader::LENGTH
+        + SmaInvHeader::LENGTH
+        + 8
+        + SmaPacketFooter::LENGTH;
+    pub const LENGTH_MAX: usize = Self::LENGTH_MIN + Self::RECORD_LENGTH;
+    /// First object ID of the total energy counter range.
+    pub const FIRST_OBJECT: u32 = 0x00260100;
+    /// Last object ID of the total energy counter range.
+    pub const LAST_OBJECT: u32 = 0x002601FF;
+    /// Object ID of the total energy counter.
+    pub const TOTAL_ENERGY_ID: u32 = 0x00260100;
+
+    /// Serialized length of an energy counter record.
+    const RECORD_LENGTH: usize = 16;
+
+    const NAN_U64: u64 = 0xFFFF_FFFF_FFFF_FFFF;
+
+    /// Compares two messages ignoring packet counters.
+    pub fn payload_eq(&self, other: &Self) -> bool {
+        self.dst == other.dst
+            && self.src == other.src
+            && self.error_code == other.error_code
+            && self.response == other.response
+            && self.first_idx == other.first_idx
+            && self.last_idx == other.last_idx
+            && self.timestamp == other.timestamp
+            && self.energy_wh == other.energy_wh
+    }
+}
+
+impl SmaSerde for SmaInvGetTotalEnergy {
+    fn serialized_len(&self) -> usize {
+        if self.energy_wh.is_some() {
+            Self::LENGTH_MAX
+        } else {
+            Self::LENGTH_MIN
+        }
+    }
+
+    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+        let len = self.serialized_len();
+        buffer.check_remaining(len)?;
+
+        let data_len = len - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH;
+        let header = SmaPacketHeader {
+            data_len,
+            protocol: SmaPacketHeader::SMA_PROTOCOL_INV,
+            ..Default::default()
+        };
+
+        let framing = CommandFraming::lookup(Self::OPCODE, self.response)?;
+
+        let inv_header = SmaInvHeader {
+            wordcount: (data_len / 4) as u8,
+            class: framing.class,
+            dst: self.dst.clone(),
+            dst_ctrl: self.dst_ctrl.unwrap_or(framing.dst_ctrl),
+            src: self.src.clone(),
+            src_ctrl: self.src_ctrl.unwrap_or(framing.src_ctrl),
+            error_code: self.error_code,
+            counters: self.counters.clone(),
+            cmd: SmaCmdWord {
+                channel: framing.channel,
+                opcode: Self::OPCODE,
+            },
+        };
+
+        header.serialize(buffer)?;
+        inv_header.serialize(buffer)?;
+
+        buffer.write_u32::<LittleEndian>(self.first_idx);
+        buffer.write_u32::<LittleEndian>(self.last_idx);
+
+        if let Some(energy_wh) = self.energy_wh {
+            buffer.write_u32::<LittleEndian>(Self::TOTAL_ENERGY_ID | 0x01);
+            buffer.write_u32::<LittleEndian>(self.timestamp);
+            buffer.write_u64::<LittleEndian>(energy_wh);
+        }
+
+        SmaPacketFooter::default().serialize(buffer)?;
+
+        Ok(())
+    }
+
+    fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
+
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        buffer.check_remaining(options.min_len(Self::LENGTH_MIN))?;
+
+        let header = SmaPacketHeader::deserialize_with(buffer, options)?;
+        header.check_protocol(SmaPacketHeader::SMA_PROTOCOL_INV)?;
+        buffer.check_remaining(header.data_len)?;
+        let padding_len = buffer.remaining() - header.data_len;
+
+        let inv_header = SmaInvHeader::deserialize(buffer)?;
+        inv_header.check_wordcount(header.data_len)?;
+        inv_header.check_framing(Self::OPCODE)?;
+
+        let first_idx = buffer.read_u32::<LittleEndian>();
+        let last_idx = buffer.read_u32::<LittleEndian>();
+
+        let mut timestamp = 0;
+        let mut energy_wh = None;
+        while buffer.remaining() - padding_len >= Self::RECORD_LENGTH {
+            let code = buffer.read_u32::<LittleEndian>();
+            let record_timestamp = buffer.read_u32::<LittleEndian>();
+            let value = buffer.read_u64::<LittleEndian>();
+
+            if code & 0x00FF_FF00 == Self::TOTAL_ENERGY_ID {
+                timestamp = record_timestamp;
+                if value != Self::NAN_U64 {
+                    energy_wh = Some(value);
+                }
+            }
+        }
+
+        let size = buffer.remaining() - padding_len;
+        if size != 0 {
+            return Err(Error::BufferTooSmall {
+                size,
+                expected: Self::RECORD_LENGTH,
+            });
+        }
+
+        SmaPacketFooter::deserialize_with(buffer, options)?;
+
+        Ok(Self {
+            dst: inv_header.dst,
+            src: inv_header.src,
+            error_code: inv_header.error_code,
+            counters: inv_header.counters,
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: inv_header.cmd.is_response(),
+            first_idx,
+            last_idx,
+            timestamp,
+            energy_wh,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sma_inv_get_total_energy_serialization() {
+        let message = SmaInvGetTotalEnergy {
+            src: SmaEndpoint::dummy(),
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 6,
+                ..Default::default()
+            },
+            first_idx: SmaInvGetTotalEnergy::FIRST_OBJECT,
+            last_idx: SmaInvGetTotalEnergy::LAST_OBJECT,
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvGetTotalEnergy::LENGTH_MIN];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetTotalEnergy serialization failed: {e:?}");
+        }
+
+        #[rustfmt::skip]
+        let expected = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x26, 0x00, 0x10,
+            0x60, 0x65,
+            0x09, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x06, 0x80,
+            0x00, 0x02, 0x00, 0x54,
+            0x00, 0x01, 0x26, 0x00, 0xFF, 0x01, 0x26, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(SmaInvGetTotalEnergy::LENGTH_MIN, cursor.position());
+        assert_eq!(expected, buffer);
+    }
+
+    #[test]
+    fn test_sma_inv_get_total_energy_deserialization() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x36, 0x00, 0x10,
+            0x60, 0x65,
+            0x0D, 0xA0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x06, 0x80,
+            0x01, 0x02, 0x00, 0x54,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x01, 0x01, 0x26, 0x00, 0x00, 0xF1, 0x53, 0x65,
+            0x40, 0xE2, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let expected = SmaInvGetTotalEnergy {
+            dst: SmaEndpoint::dummy(),
+            src: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 6,
+                ..Default::default()
+            },
+            timestamp: 1700000000,
+            energy_wh: Some(123456),
+            response: true,
+            ..Default::default()
+        };
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaInvGetTotalEnergy::deserialize(&mut cursor) {
+            Err(e) => {
+                panic!("SmaInvGetTotalEnergy deserialization failed: {e:?}")
+            }
+            Ok(message) => {
+                assert_eq!(expected, message);
+                assert_eq!(SmaInvGetTotalEnergy::LENGTH_MAX, cursor.position());
+            }
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_get_total_energy_roundtrip() {
+        let message = SmaInvGetTotalEnergy {
+            dst: SmaEndpoint::dummy(),
+            src: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            timestamp: 1700000000,
+            energy_wh: Some(0x0123456789),
+            response: true,
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvGetTotalEnergy::LENGTH_MAX];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvGetTotalEnergy serialization failed: {e:?}");
+        }
+
+        let mut cursor = Cursor::new(&buffer[..]);
+        match SmaInvGetTotalEnergy::deserialize(&mut cursor) {
+            Err(e) => {
+                panic!("SmaInvGetTotalEnergy deserialization failed: {e:?}")
+            }
+            Ok(deserialized) => assert!(message.payload_eq(&deserialized)),
+        }
+    }
+}
@@ -16,7 +16,8 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
 use super::{
-    Cursor, Error, Result, SmaCmdWord, SmaEndpoint, SmaInvCounter, SmaSerde,
+    CommandFraming, Cursor, Error, Result, SmaCmdWord, SmaEndpoint,
+    SmaInvCounter, SmaSerde,
 };
 use byteorder::BigEndian;
 #[cfg(not(feature = "std"))]
@@ -54,6 +55,11 @@ pub struct SmaInvHeader {
 impl SmaInvHeader {
     /// Serialized length of the inveter sub-protocol header.
     pub const LENGTH: usize = 28;
+    /// Known class variants as `(class, variant)` pairs, which some
+    /// firmware versions send instead of the class of the command table.
+    /// [`Self::check_class`] accepts only these variants in place of
+    /// `class`, any other class byte is rejected.
+    pub const CLASS_VARIANTS: [(u8, u8); 2] = [(0xA0, 0xA1), (0xE0, 0xD0)];
 
     pub fn check_wordcount(&self, data_len: usize) -> Result<()> {
         if self.wordcount != (data_len / 4) as u8 {
@@ -65,8 +71,12 @@ impl SmaInvHeader {
         Ok(())
     }
 
+    /// Checks the command class, accepting the known variants of
+    /// [`Self::CLASS_VARIANTS`].
     pub fn check_class(&self, class: u8) -> Result<()> {
-        if self.class != class {
+        if self.class != class
+            && !Self::CLASS_VARIANTS.contains(&(class, self.class))
+        {
             return Err(Error::UnsupportedCommandClass { class: self.class });
         }
 
@@ -82,9 +92,36 @@ impl SmaInvHeader {
 
         Ok(())
     }
+
+    /// Checks the opcode, command class and channel against the canonical
+    /// framing of the request or response, see
+    /// [`command_framing`](super::command_framing).
+    pub fn check_framing(&self, opcode: u32) -> Result<CommandFraming> {
+        self.check_opcode(opcode)?;
+        let framing = CommandFraming::lookup(opcode, self.cmd.is_response())?;
+        self.check_class(framing.class)?;
+        self.check_channel(&framing)?;
+
+        Ok(framing)
+    }
+
+    /// Checks the channel of the command word against the given framing.
+    pub fn check_channel(&self, framing: &CommandFraming) -> Result<()> {
+        if self.cmd.channel != framing.channel {
+            return Err(Error::UnsupportedChannel {
+                channel: self.cmd.channel,
+            });
+        }
+
+        Ok(())
+    }
 }
 
 impl SmaSerde for SmaInvHeader {
+    fn serialized_len(&self) -> usize {
+        Self::LENGTH
+    }
+
     fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
         buffer.check_remaining(Self::LENGTH)?;
 
@@ -180,6 +217,7 @@ mod tests {
             0x10, 0x20, 0x30, 0x40,
         ];
         assert_eq!(SmaInvHeader::LENGTH, cursor.position());
+        assert_eq!(header.serialized_len(), cursor.position());
         assert_eq!(expected, buffer);
     }
 
@@ -228,4 +266,61 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_sma_inv_header_check_class() {
+        let header = |class| SmaInvHeader {
+            class,
+            ..Default::default()
+        };
+
+        for (class, variant) in SmaInvHeader::CLASS_VARIANTS {
+            if let Err(e) = header(class).check_class(class) {
+                panic!("check_class of {class:X} failed: {e:?}");
+            }
+            if let Err(e) = header(variant).check_class(class) {
+                panic!("check_class of variant {variant:X} failed: {e:?}");
+            }
+        }
+
+        for (class, expected) in [
+            (0xB0, 0xA0),
+            (0xC0, 0xD0),
+            (0xA8, 0xA0),
+            (0xA0, 0xA1),
+            (0xD0, 0xA0),
+        ] {
+            match header(class).check_class(expected) {
+                Err(Error::UnsupportedCommandClass { class: x })
+                    if x == class => {}
+                x => panic!("Accepted {class:X} as {expected:X}: {x:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_header_check_framing() {
+        let header = |class, channel, opcode| SmaInvHeader {
+            class,
+            cmd: SmaCmdWord { channel, opcode },
+            ..Default::default()
+        };
+
+        match header(0xE0, 0x01, 0x020070).check_framing(0x020070) {
+            Ok(framing) => assert_eq!(0x01, framing.channel),
+            Err(e) => panic!("check_framing of day data failed: {e:?}"),
+        }
+        match header(0xE0, 0x03, 0x020070).check_framing(0x020070) {
+            Err(Error::UnsupportedChannel { channel: 0x03 }) => (),
+            x => panic!("Expected unsupported channel, got {x:?}"),
+        }
+        match header(0xA0, 0x01, 0x020070).check_framing(0x020070) {
+            Err(Error::UnsupportedCommandClass { class: 0xA0 }) => (),
+            x => panic!("Expected unsupported class, got {x:?}"),
+        }
+        match header(0xA0, 0x0F, 0x01FDFF).check_framing(0x01FDFF) {
+            Err(Error::UnsupportedOpcode { opcode: 0x01FDFF }) => (),
+            x => panic!("Expected unsupported logout response, got {x:?}"),
+        }
+    }
 }
@@ -16,18 +16,67 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
 use super::{
-    Cursor, Result, SmaCmdWord, SmaEndpoint, SmaInvCounter, SmaInvHeader,
-    SmaPacketFooter, SmaPacketHeader, SmaSerde,
+    CommandFraming, Cursor, DeserializeOptions, Result, SmaCmdWord,
+    SmaEndpoint, SmaInvCounter, SmaInvHeader, SmaPacketFooter, SmaPacketHeader,
+    SmaSerde,
 };
+use byteorder::{BigEndian, ByteOrder};
 #[cfg(not(feature = "std"))]
 use core::{
     clone::Clone,
     cmp::{Eq, PartialEq},
+    convert::From,
     fmt::Debug,
+    marker::Copy,
+    option::Option::{self, None, Some},
     prelude::rust_2021::derive,
     result::Result::Ok,
 };
 
+/// Class of an SMA device as reported in the identify response.
+/// The codes match the SMA device class tags 8000 + code, e.g. 8001 for
+/// solar inverters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DeviceClass {
+    SolarInverter,
+    WindTurbineInverter,
+    BatteryInverter,
+    HybridInverter,
+    Consumer,
+    SensorSystem,
+    EnergyMeter,
+    Communication,
+    /// Unrecognized device class code.
+    Unknown(u16),
+}
+
+impl From<u16> for DeviceClass {
+    fn from(code: u16) -> Self {
+        match code {
+            1 => Self::SolarInverter,
+            2 => Self::WindTurbineInverter,
+            7 => Self::BatteryInverter,
+            9 => Self::HybridInverter,
+            33 => Self::Consumer,
+            64 => Self::SensorSystem,
+            65 => Self::EnergyMeter,
+            128 => Self::Communication,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
+/// Decoded information of an identify response.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeviceIdentity {
+    /// Address of the identified device.
+    pub endpoint: SmaEndpoint,
+    /// Class of the identified device.
+    pub class: DeviceClass,
+    /// Serial number embedded in the identity data.
+    pub serial: u32,
+}
+
 /// A logical SMA inverter identify message.
 /// This message is sent to the broadcast serial/SUSy ID gets a response
 /// with the corresponding source SMA endpoint.
@@ -41,44 +90,56 @@ pub struct SmaInvIdentify {
     pub error_code: u16,
     /// Packet counters.
     pub counters: SmaInvCounter,
+    /// Overrides the destination control word,
+    /// defaults to 0x00 for requests and 0xC0 for responses.
+    /// Deserialization leaves this unset.
+    pub dst_ctrl: Option<u16>,
+    /// Overrides the source control word, defaults to 0.
+    /// Deserialization leaves this unset.
+    pub src_ctrl: Option<u16>,
+    /// Marks the message as a device response,
+    /// see [`SmaCmdWord::is_response`].
+    pub response: bool,
     /// Unknown identity binary data in response packet.
     pub identity: Option<[u8; Self::PAYLOAD_MAX]>,
 }
 
 impl SmaSerde for SmaInvIdentify {
-    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
-        let data_len = if self.identity.is_some() {
-            buffer.check_remaining(Self::LENGTH_MAX)?;
-            Self::LENGTH_MAX - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH
+    fn serialized_len(&self) -> usize {
+        if self.identity.is_some() {
+            Self::LENGTH_MAX
         } else {
-            buffer.check_remaining(Self::LENGTH_MIN)?;
-            Self::LENGTH_MIN - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH
-        };
+            Self::LENGTH_MIN
+        }
+    }
+
+    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+        let len = self.serialized_len();
+        buffer.check_remaining(len)?;
+
+        let data_len = len - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH;
 
         let header = SmaPacketHeader {
             data_len,
             protocol: SmaPacketHeader::SMA_PROTOCOL_INV,
+            ..Default::default()
         };
 
-        let (dst_ctrl, channel) = if self.identity.is_some() {
-            (0xC0, 1)
-        } else {
-            (0, 0)
-        };
+        let framing = CommandFraming::lookup(Self::OPCODE, self.response)?;
 
         let inv_header = SmaInvHeader {
             wordcount: (data_len / 4) as u8,
-            class: 0xA0,
+            class: framing.class,
             dst: self.dst.clone(),
-            dst_ctrl,
+            dst_ctrl: self.dst_ctrl.unwrap_or(framing.dst_ctrl),
             src: self.src.clone(),
+            src_ctrl: self.src_ctrl.unwrap_or(framing.src_ctrl),
             error_code: self.error_code,
             counters: self.counters.clone(),
             cmd: SmaCmdWord {
-                channel,
+                channel: framing.channel,
                 opcode: Self::OPCODE,
             },
-            ..Default::default()
         };
 
         header.serialize(buffer)?;
@@ -96,16 +157,22 @@ impl SmaSerde for SmaInvIdentify {
     }
 
     fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
-        buffer.check_remaining(Self::LENGTH_MIN)?;
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
+
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        buffer.check_remaining(options.min_len(Self::LENGTH_MIN))?;
 
-        let header = SmaPacketHeader::deserialize(buffer)?;
+        let header = SmaPacketHeader::deserialize_with(buffer, options)?;
         header.check_protocol(SmaPacketHeader::SMA_PROTOCOL_INV)?;
         buffer.check_remaining(header.data_len)?;
 
         let inv_header = SmaInvHeader::deserialize(buffer)?;
         inv_header.check_wordcount(header.data_len)?;
-        inv_header.check_class(0xA0)?;
-        inv_header.check_opcode(Self::OPCODE)?;
+        inv_header.check_framing(Self::OPCODE)?;
 
         let mut identity = [0; Self::PAYLOAD_MAX];
         let identity =
@@ -113,17 +180,20 @@ impl SmaSerde for SmaInvIdentify {
                 buffer.read_bytes(&mut identity);
                 Some(identity)
             } else {
-                buffer.skip(Self::PAYLOAD_MIN);
+                buffer.try_skip(Self::PAYLOAD_MIN)?;
                 None
             };
 
-        SmaPacketFooter::deserialize(buffer)?;
+        SmaPacketFooter::deserialize_with(buffer, options)?;
 
         Ok(Self {
             dst: inv_header.dst,
             src: inv_header.src,
             error_code: inv_header.error_code,
             counters: inv_header.counters,
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: inv_header.cmd.is_response(),
             identity,
         })
     }
@@ -141,6 +211,33 @@ impl SmaInvIdentify {
         + SmaPacketFooter::LENGTH;
     pub const PAYLOAD_MIN: usize = 8;
     pub const PAYLOAD_MAX: usize = 48;
+    /// Offset of the device class code in the identity data.
+    const DEVICE_CLASS_OFFSET: usize = 44;
+    /// Offset of the big endian serial number in the identity data.
+    const SERIAL_OFFSET: usize = 24;
+
+    /// Decodes the identity data of a response.
+    /// Returns `None` if the message does not contain identity data.
+    pub fn parse_identity(&self) -> Option<DeviceIdentity> {
+        let identity = self.identity.as_ref()?;
+
+        Some(DeviceIdentity {
+            endpoint: self.src.clone(),
+            class: DeviceClass::from(u16::from(
+                identity[Self::DEVICE_CLASS_OFFSET],
+            )),
+            serial: BigEndian::read_u32(&identity[Self::SERIAL_OFFSET..]),
+        })
+    }
+
+    /// Compares two messages ignoring packet counters.
+    pub fn payload_eq(&self, other: &Self) -> bool {
+        self.dst == other.dst
+            && self.src == other.src
+            && self.error_code == other.error_code
+            && self.response == other.response
+            && self.identity == other.identity
+    }
 }
 
 #[cfg(test)]
@@ -160,6 +257,9 @@ mod tests {
                 packet_id: 0,
                 ..Default::default()
             },
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: false,
             identity: None,
         };
 
@@ -184,6 +284,7 @@ mod tests {
             0x00, 0x00, 0x00, 0x00,
         ];
         assert_eq!(SmaInvIdentify::LENGTH_MIN, cursor.position());
+        assert_eq!(cmd.serialized_len(), cursor.position());
         assert_eq!(expected, buffer);
     }
 
@@ -214,6 +315,9 @@ mod tests {
                 packet_id: 0,
                 ..Default::default()
             },
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: false,
             identity: None,
         };
 
@@ -259,6 +363,9 @@ mod tests {
                 packet_id: 1,
                 ..Default::default()
             },
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: true,
             identity: Some([
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03,
                 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -274,7 +381,24 @@ mod tests {
             Ok(cmd) => {
                 assert_eq!(expected, cmd);
                 assert_eq!(SmaInvIdentify::LENGTH_MAX, cursor.position());
+                assert_eq!(
+                    Some(DeviceIdentity {
+                        endpoint: expected.src.clone(),
+                        class: DeviceClass::SolarInverter,
+                        serial: 0xABCDABDE,
+                    }),
+                    cmd.parse_identity()
+                );
             }
         }
     }
+
+    #[test]
+    fn test_sma_inv_device_class_codes() {
+        assert_eq!(DeviceClass::SolarInverter, DeviceClass::from(1));
+        assert_eq!(DeviceClass::BatteryInverter, DeviceClass::from(7));
+        assert_eq!(DeviceClass::EnergyMeter, DeviceClass::from(65));
+        assert_eq!(DeviceClass::Unknown(3), DeviceClass::from(3));
+        assert_eq!(None, SmaInvIdentify::default().parse_identity());
+    }
 }
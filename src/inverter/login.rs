@@ -16,8 +16,9 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
 use super::{
-    Cursor, Error, Result, SmaCmdWord, SmaEndpoint, SmaInvCounter,
-    SmaInvHeader, SmaPacketFooter, SmaPacketHeader, SmaSerde,
+    CommandFraming, Cursor, DeserializeOptions, Error, Result, SmaCmdWord,
+    SmaEndpoint, SmaInvCounter, SmaInvHeader, SmaPacketFooter, SmaPacketHeader,
+    SmaSerde, TimeSource,
 };
 use byteorder::LittleEndian;
 #[cfg(not(feature = "std"))]
@@ -30,13 +31,29 @@ use core::{
 };
 
 /// Invalid input password error.
-#[derive(Clone, Debug)]
-pub struct InvalidPasswordError();
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InvalidPasswordError {
+    /// The password contains non-ASCII characters.
+    InvalidCharacters,
+    /// The password has `len` characters, which is more than
+    /// [`SmaInvLogin::PASSWORD_LEN`].
+    TooLong { len: usize },
+}
 
 #[cfg(feature = "std")]
 impl std::fmt::Display for InvalidPasswordError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "The supplied password contains invalid characters")
+        match self {
+            Self::InvalidCharacters => {
+                write!(f, "The supplied password contains invalid characters")
+            }
+            Self::TooLong { len } => write!(
+                f,
+                "The supplied password has {len} characters, at most {} \
+                are supported",
+                SmaInvLogin::PASSWORD_LEN
+            ),
+        }
     }
 }
 
@@ -51,12 +68,24 @@ pub struct SmaInvLogin {
     pub error_code: u16,
     /// Packet counters.
     pub counters: SmaInvCounter,
+    /// Overrides the destination control word, defaults to 1.
+    /// Deserialization leaves this unset.
+    pub dst_ctrl: Option<u16>,
+    /// Overrides the source control word, defaults to 1.
+    /// Deserialization leaves this unset.
+    pub src_ctrl: Option<u16>,
+    /// Marks the message as a device response,
+    /// see [`SmaCmdWord::is_response`].
+    pub response: bool,
     /// User group ID on the inverter.
     pub user_group: u32,
     /// Session timeout in seconds.
     pub timeout: u32,
     /// Unix timestamp of the request.
     pub timestamp: u32,
+    /// Word following the timestamp. It is zero in requests but some
+    /// devices report a non-zero status value in responses.
+    pub status: u32,
     /// Up to 12 character zero padded password.
     /// Required for command, usually absent in response.
     pub password: Option<[u8; Self::PASSWORD_LEN]>,
@@ -69,50 +98,59 @@ impl Default for SmaInvLogin {
             src: SmaEndpoint::default(),
             error_code: 0,
             counters: SmaInvCounter::default(),
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: false,
             user_group: 7,
             timeout: 900,
             timestamp: 0,
+            status: 0,
             password: None,
         }
     }
 }
 
 impl SmaSerde for SmaInvLogin {
-    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
-        let data_len = if self.password.is_some() {
-            buffer.check_remaining(Self::LENGTH_MAX)?;
-            Self::LENGTH_MAX - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH
+    fn serialized_len(&self) -> usize {
+        if self.password.is_some() {
+            Self::LENGTH_MAX
         } else {
-            buffer.check_remaining(Self::LENGTH_MIN)?;
-            Self::LENGTH_MIN - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH
-        };
+            Self::LENGTH_MIN
+        }
+    }
+
+    fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+        self.validate()?;
+
+        let len = self.serialized_len();
+        buffer.check_remaining(len)?;
+
+        let data_len = len - SmaPacketHeader::LENGTH - SmaPacketFooter::LENGTH;
 
         let header = SmaPacketHeader {
             data_len,
             protocol: SmaPacketHeader::SMA_PROTOCOL_INV,
+            ..Default::default()
         };
 
-        let (class, channel) = if self.password.is_some() {
-            if self.error_code == 0 {
-                (0xA0, 0x0C)
-            } else {
-                (0xD0, 0x0C)
-            }
+        let framing = CommandFraming::lookup(Self::OPCODE, self.response)?;
+        let class = if self.error_code != 0 {
+            Self::ERROR_CLASS
         } else {
-            (0xE0, 0x0D)
+            framing.class
         };
 
         let inv_header = SmaInvHeader {
             wordcount: (data_len / 4) as u8,
             class,
             dst: self.dst.clone(),
-            dst_ctrl: 1,
+            dst_ctrl: self.dst_ctrl.unwrap_or(framing.dst_ctrl),
             src: self.src.clone(),
-            src_ctrl: 1,
+            src_ctrl: self.src_ctrl.unwrap_or(framing.src_ctrl),
             error_code: self.error_code,
             counters: self.counters.clone(),
             cmd: SmaCmdWord {
-                channel,
+                channel: framing.channel,
                 opcode: Self::OPCODE,
             },
         };
@@ -123,11 +161,11 @@ impl SmaSerde for SmaInvLogin {
         buffer.write_u32::<LittleEndian>(self.user_group);
         buffer.write_u32::<LittleEndian>(self.timeout);
         buffer.write_u32::<LittleEndian>(self.timestamp);
-        buffer.write_u32::<LittleEndian>(0); // padding
+        buffer.write_u32::<LittleEndian>(self.status);
 
         if let Some(password) = &self.password {
             for char in password {
-                buffer.write_u8(char + 0x88);
+                buffer.write_u8(char.wrapping_add(0x88));
             }
         }
 
@@ -137,50 +175,59 @@ impl SmaSerde for SmaInvLogin {
     }
 
     fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
-        buffer.check_remaining(Self::LENGTH_MIN)?;
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
 
-        let header = SmaPacketHeader::deserialize(buffer)?;
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        buffer.check_remaining(options.min_len(Self::LENGTH_MIN))?;
+
+        let header = SmaPacketHeader::deserialize_with(buffer, options)?;
         header.check_protocol(SmaPacketHeader::SMA_PROTOCOL_INV)?;
         buffer.check_remaining(header.data_len)?;
 
         let inv_header = SmaInvHeader::deserialize(buffer)?;
         inv_header.check_wordcount(header.data_len)?;
-        if inv_header.check_class(0xA0).is_err()
-            && inv_header.check_class(0xD0).is_err()
-        {
-            inv_header.check_class(0xE0)?;
-        }
         inv_header.check_opcode(Self::OPCODE)?;
+        let framing =
+            CommandFraming::lookup(Self::OPCODE, inv_header.cmd.is_response())?;
+        if inv_header.check_class(Self::ERROR_CLASS).is_err() {
+            inv_header.check_class(framing.class)?;
+        }
+        inv_header.check_channel(&framing)?;
 
         let user_group = buffer.read_u32::<LittleEndian>();
         let timeout = buffer.read_u32::<LittleEndian>();
         let timestamp = buffer.read_u32::<LittleEndian>();
-        let padding = buffer.read_u32::<LittleEndian>();
-        if padding != 0 {
-            return Err(Error::InvalidPadding { padding });
-        }
+        let status = buffer.read_u32::<LittleEndian>();
 
         let payload_len = header.data_len - SmaInvHeader::LENGTH;
         let password = if payload_len >= Self::PAYLOAD_MAX {
             let mut password = [0; Self::PASSWORD_LEN];
             for char in password.iter_mut() {
-                *char = buffer.read_u8() - 0x88;
+                *char = buffer.read_u8().wrapping_sub(0x88);
             }
             Some(password)
         } else {
             None
         };
 
-        SmaPacketFooter::deserialize(buffer)?;
+        SmaPacketFooter::deserialize_with(buffer, options)?;
 
         Ok(Self {
             dst: inv_header.dst,
             src: inv_header.src,
             error_code: inv_header.error_code,
             counters: inv_header.counters,
+            dst_ctrl: None,
+            src_ctrl: None,
+            response: inv_header.cmd.is_response(),
             user_group,
             timeout,
             timestamp,
+            status,
             password,
         })
     }
@@ -200,14 +247,63 @@ impl SmaInvLogin {
     pub const PAYLOAD_MAX: usize = 28;
     pub const PASSWORD_LEN: usize = 12;
 
+    /// Response error code for an invalid password.
+    pub const ERROR_WRONG_PASSWORD: u16 = 0x0100;
+    /// Command class of login messages which carry an error code.
+    const ERROR_CLASS: u8 = 0xD0;
+
+    /// Compares two messages ignoring packet counters and timestamp.
+    pub fn payload_eq(&self, other: &Self) -> bool {
+        self.dst == other.dst
+            && self.src == other.src
+            && self.error_code == other.error_code
+            && self.response == other.response
+            && self.user_group == other.user_group
+            && self.timeout == other.timeout
+            && self.status == other.status
+            && self.password == other.password
+    }
+
+    /// Sets the login timestamp to the current time of the given source.
+    /// Returns the error of the source if the time is not available.
+    pub fn with_time_source<T: TimeSource>(
+        self,
+        time: &T,
+    ) -> core::result::Result<Self, T::Error> {
+        Ok(Self {
+            timestamp: time.now_unix_secs()?,
+            ..self
+        })
+    }
+
+    /// Checks that the password only contains ASCII characters.
+    pub fn validate(&self) -> Result<()> {
+        match &self.password {
+            Some(password) if !password.is_ascii() => {
+                Err(Error::InvalidPassword {
+                    reason: InvalidPasswordError::InvalidCharacters,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Encodes a password for a login request. Returns an
+    /// [`InvalidPasswordError`] if the password contains non-ASCII
+    /// characters or is longer than [`Self::PASSWORD_LEN`] characters.
     pub fn pw_from_str(
         passwd: &str,
     ) -> core::result::Result<[u8; Self::PASSWORD_LEN], InvalidPasswordError>
     {
+        let len = passwd.chars().count();
+        if len > Self::PASSWORD_LEN {
+            return Err(InvalidPasswordError::TooLong { len });
+        }
+
         let mut buffer = [0; Self::PASSWORD_LEN];
         for (src, dst) in passwd.chars().zip(buffer.iter_mut()) {
             if !src.is_ascii() {
-                return Err(InvalidPasswordError());
+                return Err(InvalidPasswordError::InvalidCharacters);
             }
             *dst = src as u8;
         }
@@ -220,6 +316,60 @@ impl SmaInvLogin {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_sma_inv_login_password_length() {
+        match SmaInvLogin::pw_from_str("123456789012") {
+            Ok(password) => assert_eq!(*b"123456789012", password),
+            Err(e) => panic!("Encoding 12 character password failed: {e:?}"),
+        }
+        // Characters which wrap around in the wire encoding are accepted.
+        match SmaInvLogin::pw_from_str("xyz") {
+            Ok(password) => assert_eq!(*b"xyz\0\0\0\0\0\0\0\0\0", password),
+            Err(e) => panic!("Encoding wrapping password failed: {e:?}"),
+        }
+        match SmaInvLogin::pw_from_str("1234567890123") {
+            Err(InvalidPasswordError::TooLong { len: 13 }) => (),
+            x => panic!("Encoded 13 character password as {x:?}"),
+        }
+        match SmaInvLogin::pw_from_str("pässwört") {
+            Err(InvalidPasswordError::InvalidCharacters) => (),
+            x => panic!("Encoded non-ASCII password as {x:?}"),
+        }
+        // Length is counted in characters, not bytes.
+        match SmaInvLogin::pw_from_str("äääääääääääää") {
+            Err(InvalidPasswordError::TooLong { len: 13 }) => (),
+            x => panic!("Encoded long non-ASCII password as {x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_login_password_wrapping_roundtrip() {
+        // Characters from 'x' (0x78) on wrap around when encoded.
+        let message = SmaInvLogin {
+            src: SmaEndpoint::dummy(),
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            timestamp: 1700000000,
+            password: Some(*b"pa\x7Fxz~\0\0\0\0\0\0"),
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvLogin::LENGTH_MAX];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvLogin serialization failed: {e:?}");
+        }
+        assert_eq!([0xF8, 0xE9, 0x07, 0x00, 0x02, 0x06, 0x88], buffer[62..69]);
+
+        let mut cursor = Cursor::new(&buffer[..]);
+        match SmaInvLogin::deserialize(&mut cursor) {
+            Err(e) => panic!("SmaInvLogin deserialization failed: {e:?}"),
+            Ok(decoded) => assert_eq!(message, decoded),
+        }
+    }
+
     #[test]
     fn test_sma_inv_login_serialization() {
         let message = SmaInvLogin {
@@ -261,9 +411,75 @@ mod tests {
             0x00, 0x00, 0x00, 0x00,
         ];
         assert_eq!(SmaInvLogin::LENGTH_MAX, cursor.position());
+        assert_eq!(message.serialized_len(), cursor.position());
         assert_eq!(expected, buffer);
     }
 
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_sma_inv_login_to_vec() {
+        let message = SmaInvLogin {
+            src: SmaEndpoint::dummy(),
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 2,
+                ..Default::default()
+            },
+            timestamp: 1700000000,
+            password: Some(SmaInvLogin::pw_from_str("12345").unwrap()),
+            ..Default::default()
+        };
+
+        #[rustfmt::skip]
+        let expected = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x3A, 0x00, 0x10,
+            0x60, 0x65,
+            0x0E, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x01,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x80,
+            0x0C, 0x04, 0xFD, 0xFF,
+            0x07, 0x00, 0x00, 0x00, 0x84, 0x03, 0x00, 0x00,
+            0x00, 0xF1, 0x53, 0x65, 0x00, 0x00, 0x00, 0x00,
+            0xB9, 0xBA, 0xBB, 0xBC, 0xBD, 0x88, 0x88, 0x88,
+            0x88, 0x88, 0x88, 0x88,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        match message.to_vec() {
+            Err(e) => panic!("SmaInvLogin serialization failed: {e:?}"),
+            Ok(serialized) => assert_eq!(&expected[..], &serialized[..]),
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_login_ctrl_override() {
+        let message = SmaInvLogin {
+            src: SmaEndpoint::dummy(),
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            dst_ctrl: Some(0x00A0),
+            src_ctrl: Some(0x0003),
+            password: Some(SmaInvLogin::pw_from_str("12345").unwrap()),
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; SmaInvLogin::LENGTH_MAX];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvLogin serialization failed: {e:?}");
+        }
+
+        assert_eq!([0x00, 0xA0], buffer[26..28]);
+        assert_eq!([0x00, 0x03], buffer[34..36]);
+    }
+
     #[test]
     fn test_sma_inv_login_deserialization() {
         #[rustfmt::skip]
@@ -308,6 +524,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sma_inv_login_verify_roundtrip() {
+        #[rustfmt::skip]
+        let mut serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x3A, 0x00, 0x10,
+            0x60, 0x65,
+            0x0E, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x01,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x80,
+            0x0C, 0x04, 0xFD, 0xFF,
+            0x07, 0x00, 0x00, 0x00, 0x84, 0x03, 0x00, 0x00,
+            0x00, 0xF1, 0x53, 0x65, 0x00, 0x00, 0x00, 0x00,
+            0xB9, 0xBA, 0xBB, 0xBC, 0xBD, 0x88, 0x88, 0x88,
+            0x88, 0x88, 0x88, 0x88,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        if let Err(e) = SmaInvLogin::verify_roundtrip(&serialized) {
+            panic!("SmaInvLogin roundtrip failed: {e:?}");
+        }
+
+        // The control word is not retained by deserialization.
+        serialized[27] = 0xA0;
+        match SmaInvLogin::verify_roundtrip(&serialized) {
+            Err(Error::RoundtripMismatch { offset: 27 }) => (),
+            x => panic!("Lossy roundtrip returned {x:?}"),
+        }
+    }
+
     #[test]
     fn test_sma_inv_login_response_deserialization() {
         #[rustfmt::skip]
@@ -337,6 +584,7 @@ mod tests {
             },
             timestamp: 1700000000,
             password: None,
+            response: true,
             ..Default::default()
         };
 
@@ -350,6 +598,83 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sma_inv_login_missing_footer_deserialization() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x2E, 0x00, 0x10,
+            0x60, 0x65,
+            0x0B, 0xE0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x80,
+            0x0D, 0x04, 0xFD, 0xFF,
+            0x07, 0x00, 0x00, 0x00, 0x84, 0x03, 0x00, 0x00,
+            0x00, 0xF1, 0x53, 0x65, 0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaInvLogin::deserialize(&mut cursor) {
+            Err(Error::BufferTooSmall { .. }) => (),
+            x => panic!("Deserialized login without footer as {x:?}"),
+        }
+
+        let options = DeserializeOptions {
+            lenient_footer: true,
+            ..Default::default()
+        };
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaInvLogin::deserialize_with(&mut cursor, &options) {
+            Err(e) => panic!("SmaInvLogin deserialization failed: {e:?}"),
+            Ok(message) => {
+                assert_eq!(1700000000, message.timestamp);
+                assert_eq!(900, message.timeout);
+                assert_eq!(serialized.len(), cursor.position());
+            }
+        }
+
+        // Truncated payload is still rejected.
+        let mut cursor = Cursor::new(&serialized[..serialized.len() - 2]);
+        match SmaInvLogin::deserialize_with(&mut cursor, &options) {
+            Err(Error::BufferTooSmall { .. }) => (),
+            x => panic!("Deserialized truncated login as {x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_sma_inv_login_response_status_deserialization() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x2E, 0x00, 0x10,
+            0x60, 0x65,
+            0x0B, 0xE0,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x80,
+            0x0D, 0x04, 0xFD, 0xFF,
+            0x07, 0x00, 0x00, 0x00, 0x84, 0x03, 0x00, 0x00,
+            0x00, 0xF1, 0x53, 0x65, 0x01, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        let message = match SmaInvLogin::deserialize(&mut cursor) {
+            Err(e) => panic!("SmaInvLogin deserialization failed: {e:?}"),
+            Ok(message) => message,
+        };
+        assert_eq!(1, message.status);
+        assert_eq!(1700000000, message.timestamp);
+
+        let mut buffer = [0u8; SmaInvLogin::LENGTH_MIN];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = message.serialize(&mut cursor) {
+            panic!("SmaInvLogin serialization failed: {e:?}");
+        }
+        assert_eq!(serialized, buffer);
+    }
+
     #[test]
     fn test_sma_inv_login_failed_response_deserialization() {
         #[rustfmt::skip]
@@ -381,6 +706,7 @@ mod tests {
             },
             timestamp: 1700000000,
             error_code: 1,
+            response: true,
             password: Some(SmaInvLogin::pw_from_str("12345").unwrap()),
             ..Default::default()
         };
@@ -393,5 +719,75 @@ mod tests {
                 assert_eq!(SmaInvLogin::LENGTH_MAX, cursor.position());
             }
         }
+
+        let mut buffer = [0u8; SmaInvLogin::LENGTH_MAX];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        if let Err(e) = expected.serialize(&mut cursor) {
+            panic!("SmaInvLogin serialization failed: {e:?}");
+        }
+        assert_eq!(serialized, buffer);
+    }
+
+    #[test]
+    fn test_sma_inv_login_with_time_source() {
+        struct FakeClock(u32);
+
+        impl TimeSource for FakeClock {
+            type Error = ();
+
+            fn now_unix_secs(&self) -> core::result::Result<u32, ()> {
+                Ok(self.0)
+            }
+        }
+
+        let message = match SmaInvLogin::default()
+            .with_time_source(&FakeClock(1700000000))
+        {
+            Ok(x) => x,
+            Err(e) => panic!("Time source failed: {e:?}"),
+        };
+        assert_eq!(1700000000, message.timestamp);
+        assert_eq!(
+            SmaInvLogin {
+                timestamp: 1700000000,
+                ..Default::default()
+            },
+            message
+        );
+    }
+
+    #[test]
+    fn test_sma_inv_login_payload_eq() {
+        let message = SmaInvLogin {
+            src: SmaEndpoint::dummy(),
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 2,
+                ..Default::default()
+            },
+            timestamp: 1700000000,
+            password: Some(SmaInvLogin::pw_from_str("12345").unwrap()),
+            ..Default::default()
+        };
+        let other = SmaInvLogin {
+            counters: SmaInvCounter {
+                packet_id: 3,
+                ..Default::default()
+            },
+            timestamp: 1700000300,
+            ..message.clone()
+        };
+
+        assert_ne!(message, other);
+        assert!(message.payload_eq(&other));
+
+        let other = SmaInvLogin {
+            password: Some(SmaInvLogin::pw_from_str("54321").unwrap()),
+            ..message.clone()
+        };
+        assert!(!message.payload_eq(&other));
     }
 }
@@ -16,8 +16,9 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
 use super::{
-    Cursor, Error, Result, SmaCmdWord, SmaEndpoint, SmaInvCounter,
-    SmaInvHeader, SmaPacketFooter, SmaPacketHeader, SmaSerde,
+    CommandFraming, Cursor, DeserializeOptions, Error, Result, SmaCmdWord,
+    SmaEndpoint, SmaInvCounter, SmaInvHeader, SmaPacketFooter, SmaPacketHeader,
+    SmaSerde,
 };
 use byteorder::LittleEndian;
 #[cfg(not(feature = "std"))]
@@ -41,9 +42,19 @@ pub struct SmaInvLogout {
     pub error_code: u16,
     /// Packet counters.
     pub counters: SmaInvCounter,
+    /// Overrides the destination control word, defaults to 3.
+    /// Deserialization leaves this unset.
+    pub dst_ctrl: Option<u16>,
+    /// Overrides the source control word, defaults to 3.
+    /// Deserialization leaves this unset.
+    pub src_ctrl: Option<u16>,
 }
 
 impl SmaSerde for SmaInvLogout {
+    fn serialized_len(&self) -> usize {
+        Self::LENGTH
+    }
+
     fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
         buffer.check_remaining(Self::LENGTH)?;
 
@@ -52,19 +63,21 @@ impl SmaSerde for SmaInvLogout {
         let header = SmaPacketHeader {
             data_len,
             protocol: SmaPacketHeader::SMA_PROTOCOL_INV,
+            ..Default::default()
         };
 
+        let framing = CommandFraming::lookup(Self::OPCODE, false)?;
         let inv_header = SmaInvHeader {
             wordcount: (data_len / 4) as u8,
-            class: 0xA0,
+            class: framing.class,
             dst: self.dst.clone(),
-            dst_ctrl: 3,
+            dst_ctrl: self.dst_ctrl.unwrap_or(framing.dst_ctrl),
             src: self.src.clone(),
-            src_ctrl: 3,
+            src_ctrl: self.src_ctrl.unwrap_or(framing.src_ctrl),
             error_code: self.error_code,
             counters: self.counters.clone(),
             cmd: SmaCmdWord {
-                channel: 0x0E,
+                channel: framing.channel,
                 opcode: Self::OPCODE,
             },
         };
@@ -78,29 +91,37 @@ impl SmaSerde for SmaInvLogout {
     }
 
     fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
-        buffer.check_remaining(Self::LENGTH)?;
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
+
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        buffer.check_remaining(options.min_len(Self::LENGTH))?;
 
-        let header = SmaPacketHeader::deserialize(buffer)?;
+        let header = SmaPacketHeader::deserialize_with(buffer, options)?;
         header.check_protocol(SmaPacketHeader::SMA_PROTOCOL_INV)?;
         buffer.check_remaining(header.data_len)?;
 
         let inv_header = SmaInvHeader::deserialize(buffer)?;
         inv_header.check_wordcount(header.data_len)?;
-        inv_header.check_class(0xA0)?;
-        inv_header.check_opcode(Self::OPCODE)?;
+        inv_header.check_framing(Self::OPCODE)?;
 
         let padding = buffer.read_u32::<LittleEndian>();
         if padding != 0xFFFFFFFF {
             return Err(Error::InvalidPadding { padding });
         }
 
-        SmaPacketFooter::deserialize(buffer)?;
+        SmaPacketFooter::deserialize_with(buffer, options)?;
 
         Ok(Self {
             src: inv_header.src,
             dst: inv_header.dst,
             error_code: inv_header.error_code,
             counters: inv_header.counters,
+            dst_ctrl: None,
+            src_ctrl: None,
         })
     }
 }
@@ -111,6 +132,13 @@ impl SmaInvLogout {
         + SmaInvHeader::LENGTH
         + 4
         + SmaPacketFooter::LENGTH;
+
+    /// Compares two messages ignoring packet counters.
+    pub fn payload_eq(&self, other: &Self) -> bool {
+        self.dst == other.dst
+            && self.src == other.src
+            && self.error_code == other.error_code
+    }
 }
 
 #[cfg(test)]
@@ -153,9 +181,84 @@ mod tests {
             0x00, 0x00, 0x00, 0x00,
         ];
         assert_eq!(SmaInvLogout::LENGTH, cursor.position());
+        assert_eq!(cmd.serialized_len(), cursor.position());
         assert_eq!(expected, buffer);
     }
 
+    #[test]
+    fn test_sma_inv_logout_serialize_into_array() {
+        let cmd = SmaInvLogout {
+            src: SmaEndpoint::dummy(),
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let (buffer, len) =
+            match cmd.serialize_into_array::<{ SmaInvLogout::LENGTH }>() {
+                Ok(x) => x,
+                Err(e) => panic!("SmaInvLogout serialization failed: {e:?}"),
+            };
+        assert_eq!(SmaInvLogout::LENGTH, len);
+
+        let mut expected = [0u8; SmaInvLogout::LENGTH];
+        if let Err(e) = cmd.serialize(&mut Cursor::new(&mut expected[..])) {
+            panic!("SmaInvLogout serialization failed: {e:?}");
+        }
+        assert_eq!(expected, buffer);
+
+        match cmd.serialize_into_array::<16>() {
+            Err(Error::BufferTooSmall {
+                size: 16,
+                expected: SmaInvLogout::LENGTH,
+            }) => (),
+            x => panic!("Expected buffer too small, got {x:?}"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_sma_inv_logout_write_to() {
+        let cmd = SmaInvLogout {
+            src: SmaEndpoint::dummy(),
+            dst: SmaEndpoint {
+                susy_id: 0x5678,
+                serial: 0xABCDABCE,
+            },
+            counters: SmaInvCounter {
+                packet_id: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut sink = Vec::new();
+        if let Err(e) = cmd.write_to(&mut sink) {
+            panic!("SmaInvLogout serialization failed: {e:?}");
+        }
+
+        #[rustfmt::skip]
+        let expected = [
+            0x53, 0x4D, 0x41, 0x00, 0x00, 0x04, 0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x22, 0x00, 0x10,
+            0x60, 0x65,
+            0x08, 0xA0,
+            0x56, 0x78, 0xAB, 0xCD, 0xAB, 0xCE, 0x00, 0x03,
+            0xDE, 0xAD, 0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x03,
+            0x00, 0x00, 0x00, 0x00, 0x01, 0x80,
+            0x0E, 0x01, 0xFD, 0xFF,
+            0xFF, 0xFF, 0xFF, 0xFF,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(&expected[..], &sink[..]);
+    }
+
     #[test]
     fn test_sma_inv_logout_deserialization() {
         #[rustfmt::skip]
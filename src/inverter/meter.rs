@@ -15,15 +15,17 @@
     You should have received a copy of the GNU Affero General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
-use super::{Cursor, Result, SmaSerde};
+use super::{Cursor, Error, Result, SmaPacketFooter, SmaSerde};
 use byteorder::LittleEndian;
 #[cfg(not(feature = "std"))]
 use core::{
     clone::Clone,
     cmp::{Eq, PartialEq},
     fmt::Debug,
+    iter::Iterator,
+    option::Option::{self, None, Some},
     prelude::rust_2021::derive,
-    result::Result::Ok,
+    result::Result::{Err, Ok},
 };
 
 /// Total inverter energy production at a given timestamp.
@@ -43,9 +45,108 @@ impl SmaInvMeterValue {
     pub fn is_valid(&self) -> bool {
         self.energy_wh != 0xFFFF_FFFF_FFFF_FFFF
     }
+
+    /// Returns an iterator which lazily decodes the records of a
+    /// [`SmaInvGetDayData`](super::SmaInvGetDayData) response from the
+    /// payload region after the fixed fields, e.g. to process a large
+    /// response without allocating all records.
+    pub fn iter_records(buf: &[u8]) -> RecordIter<'_> {
+        RecordIter {
+            buffer: Cursor::new(buf),
+            done: false,
+        }
+    }
+}
+
+/// Iterator over serialized [`SmaInvMeterValue`] records,
+/// see [`SmaInvMeterValue::iter_records`].
+///
+/// Iteration ends at the end of the buffer or at a trailing zero footer.
+/// Any other trailing bytes yield a single [`Error::BufferTooSmall`].
+#[derive(Debug)]
+pub struct RecordIter<'a> {
+    buffer: Cursor<&'a [u8]>,
+    done: bool,
+}
+
+impl Iterator for RecordIter<'_> {
+    type Item = Result<SmaInvMeterValue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let remaining = self.buffer.remaining();
+        if self.done || remaining == 0 {
+            return None;
+        }
+
+        if remaining < SmaInvMeterValue::LENGTH {
+            self.done = true;
+            let pos = self.buffer.position();
+            let mut trailing = [0u8; SmaPacketFooter::LENGTH];
+            if remaining <= trailing.len() {
+                self.buffer.read_bytes(&mut trailing[..remaining]);
+                if trailing.iter().all(|x| *x == 0) {
+                    return None;
+                }
+            }
+
+            return Some(Err(Error::BufferTooSmall {
+                size: self.buffer.len(),
+                expected: pos + SmaInvMeterValue::LENGTH,
+            }));
+        }
+
+        Some(SmaInvMeterValue::deserialize(&mut self.buffer))
+    }
+}
+
+/// Returns the energy in Wh produced over the given records, which must be
+/// sorted by timestamp, e.g. as returned by a day data request.
+/// Invalid records are skipped. A decreasing counter indicates that the
+/// device reset its counter to zero, so the counter value after the reset
+/// is counted as production. Returns `None` if there is no valid record.
+pub fn yield_over(records: &[SmaInvMeterValue]) -> Option<u64> {
+    let mut valid = records.iter().filter(|record| record.is_valid());
+    let mut prev = valid.next()?.energy_wh;
+    let mut total = 0u64;
+    for record in valid {
+        let delta = if record.energy_wh < prev {
+            record.energy_wh
+        } else {
+            record.energy_wh - prev
+        };
+        total = total.saturating_add(delta);
+        prev = record.energy_wh;
+    }
+
+    Some(total)
+}
+
+/// Merges `incoming` records into `existing` by timestamp.
+/// Records with duplicate timestamps are replaced by the incoming value and
+/// the result is sorted by ascending timestamp.
+#[cfg(feature = "std")]
+pub fn merge_day_data(
+    existing: &mut Vec<SmaInvMeterValue>,
+    mut incoming: Vec<SmaInvMeterValue>,
+) {
+    existing.append(&mut incoming);
+    // Stable sort keeps incoming records behind existing ones.
+    existing.sort_by_key(|record| record.timestamp);
+    existing.dedup_by(|newer, older| {
+        if newer.timestamp == older.timestamp {
+            core::mem::swap(newer, older);
+            true
+        } else {
+            false
+        }
+    });
 }
 
 impl SmaSerde for SmaInvMeterValue {
+    fn serialized_len(&self) -> usize {
+        Self::LENGTH
+    }
+
     fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
         buffer.check_remaining(Self::LENGTH)?;
 
@@ -67,3 +168,78 @@ impl SmaSerde for SmaInvMeterValue {
         })
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn record(timestamp: u32, energy_wh: u64) -> SmaInvMeterValue {
+        SmaInvMeterValue {
+            timestamp,
+            energy_wh,
+        }
+    }
+
+    #[test]
+    fn test_yield_over() {
+        assert_eq!(None, yield_over(&[]));
+        assert_eq!(Some(0), yield_over(&[record(300, 1000)]));
+        assert_eq!(
+            Some(250),
+            yield_over(&[
+                record(300, 1000),
+                record(600, 1100),
+                record(900, 1250)
+            ])
+        );
+        assert_eq!(
+            Some(100),
+            yield_over(&[
+                record(300, 1000),
+                record(600, 0xFFFF_FFFF_FFFF_FFFF),
+                record(900, 1100),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_yield_over_counter_reset() {
+        // The counter restarts at zero after the second record.
+        let records = [
+            record(300, 1000),
+            record(600, 1100),
+            record(900, 20),
+            record(1200, 50),
+        ];
+        assert_eq!(Some(150), yield_over(&records));
+    }
+
+    #[test]
+    fn test_merge_overlapping_day_data() {
+        let mut existing = vec![record(300, 10), record(600, 20)];
+        let incoming = vec![record(600, 21), record(900, 30)];
+
+        merge_day_data(&mut existing, incoming);
+        assert_eq!(
+            vec![record(300, 10), record(600, 21), record(900, 30)],
+            existing
+        );
+    }
+
+    #[test]
+    fn test_merge_unordered_day_data() {
+        let mut existing = vec![record(900, 30), record(300, 10)];
+        let incoming = vec![record(1200, 40), record(300, 11), record(600, 20)];
+
+        merge_day_data(&mut existing, incoming);
+        assert_eq!(
+            vec![
+                record(300, 11),
+                record(600, 20),
+                record(900, 30),
+                record(1200, 40)
+            ],
+            existing
+        );
+    }
+}
@@ -19,25 +19,42 @@
 //! Module for handling the SMA speedwire inverter sub protocol.
 
 use super::{
-    Cursor, Error, Result, SmaEndpoint, SmaPacketFooter, SmaPacketHeader,
-    SmaSerde,
+    Cursor, DeserializeOptions, Error, Result, SmaEndpoint, SmaPacketFooter,
+    SmaPacketHeader, SmaSerde, TimeSource,
 };
 
 mod cmd;
 mod counter;
+mod get_all_spot_values;
 mod get_day_data;
+mod get_device_info;
+mod get_events;
+mod get_month_data;
+mod get_temperature;
+mod get_total_energy;
 mod header;
 mod identify;
 mod login;
 mod logout;
 mod meter;
+mod spot;
 
-use cmd::SmaCmdWord;
+pub use cmd::{command_framing, CommandFraming, SmaCmdWord};
+pub(crate) use cmd::{command_name, SUPPORTED_OPCODES};
 pub use counter::SmaInvCounter;
 pub(crate) use header::SmaInvHeader;
 
+pub use get_all_spot_values::SmaInvGetAllSpotValues;
 pub use get_day_data::SmaInvGetDayData;
-pub use identify::SmaInvIdentify;
+pub use get_device_info::SmaInvGetDeviceInfo;
+pub use get_events::{EventRecord, SmaInvGetEvents};
+pub use get_month_data::SmaInvGetMonthData;
+pub use get_temperature::SmaInvGetTemperature;
+pub use get_total_energy::SmaInvGetTotalEnergy;
+pub use identify::{DeviceClass, DeviceIdentity, SmaInvIdentify};
 pub use login::{InvalidPasswordError, SmaInvLogin};
 pub use logout::SmaInvLogout;
-pub use meter::SmaInvMeterValue;
+#[cfg(feature = "std")]
+pub use meter::merge_day_data;
+pub use meter::{yield_over, RecordIter, SmaInvMeterValue};
+pub use spot::decode_spot_i32;
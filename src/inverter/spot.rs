@@ -0,0 +1,49 @@
+/******************************************************************************\
+    sma-proto - A SMA Speedwire protocol library
+    Copyright (C) 2024 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+#[cfg(not(feature = "std"))]
+use core::option::Option::{self, None, Some};
+
+/// Raw spot value which marks an invalid signed value.
+const NAN_S32: u32 = 0x8000_0000;
+/// Raw spot value which marks an invalid unsigned value.
+const NAN_U32: u32 = 0xFFFF_FFFF;
+
+/// Decodes a raw signed 32bit spot value, e.g. a power which becomes
+/// negative while consuming energy. Returns `None` if the device marked
+/// the value as invalid with one of the NaN sentinels `0x80000000` or
+/// `0xFFFFFFFF`.
+pub fn decode_spot_i32(raw: u32) -> Option<i32> {
+    match raw {
+        NAN_S32 | NAN_U32 => None,
+        raw => Some(raw as i32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_spot_i32() {
+        assert_eq!(None, decode_spot_i32(0x8000_0000));
+        assert_eq!(None, decode_spot_i32(0xFFFF_FFFF));
+        assert_eq!(Some(-1500), decode_spot_i32(-1500i32 as u32));
+        assert_eq!(Some(1500), decode_spot_i32(1500));
+        assert_eq!(Some(i32::MIN + 1), decode_spot_i32(0x8000_0001));
+    }
+}
@@ -23,15 +23,28 @@ mod any;
 mod cursor;
 mod error;
 mod packet;
+#[cfg(feature = "std")]
+mod stream;
+mod time;
 
-#[cfg(feature = "client")]
+#[cfg(any(feature = "client", feature = "blocking"))]
 pub mod client;
 pub mod energymeter;
 pub mod inverter;
 
-use packet::{SmaPacketFooter, SmaPacketHeader};
+use packet::SmaPacketHeader;
 
-pub use any::AnySmaMessage;
+pub use any::{
+    opcode_name, supported_opcodes, supported_protocols, AnySmaMessage,
+};
 pub use cursor::Cursor;
 pub use error::{Error, Result};
-pub use packet::{SmaEndpoint, SmaSerde};
+pub use packet::{
+    DeserializeOptions, Serial, SmaEndpoint, SmaPacketFooter, SmaSerde, SusyId,
+    TransportInfo,
+};
+#[cfg(feature = "std")]
+pub use stream::parse_stream;
+#[cfg(feature = "std")]
+pub use time::SystemTimeSource;
+pub use time::TimeSource;
@@ -18,19 +18,39 @@
 
 //! Common SMA packet serialization and deserialization structures and traits.
 
-use super::{Cursor, Error, Result};
+use super::{energymeter::ObisRule, Cursor, Error, Result};
 use byteorder::BigEndian;
 #[cfg(not(feature = "std"))]
 use core::{
     clone::Clone,
     cmp::{Eq, PartialEq},
     fmt::Debug,
+    iter::Iterator,
+    option::Option::{None, Some},
     prelude::rust_2021::derive,
     result::Result::{Err, Ok},
 };
 
+/// Largest seen SMA speedwire packet size before fragmentation.
+pub(crate) const MAX_FRAME_LEN: usize = 1030;
+/// UDP port of the SMA speedwire protocol, used for unicast and
+/// multicast traffic.
+#[cfg(any(feature = "client", feature = "blocking"))]
+pub(crate) const SMA_PORT: u16 = 9522;
+
 /// Interface for (de)serialization of SMA speedwire messages.
 pub trait SmaSerde {
+    /// Returns the serialized length of the given object in bytes.
+    /// The default implementation serializes the object into a scratch
+    /// buffer and returns zero if serialization fails.
+    fn serialized_len(&self) -> usize {
+        let mut buffer = [0u8; MAX_FRAME_LEN];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        match self.serialize(&mut cursor) {
+            Ok(()) => cursor.position(),
+            Err(_) => 0,
+        }
+    }
     /// Serialize given object into buffer.
     fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()>;
     /// Deserialize buffer into object.
@@ -38,29 +58,220 @@ pub trait SmaSerde {
     fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self>
     where
         Self: Sized;
+    /// Deserialize buffer into object using the given options.
+    /// Objects without configurable behavior ignore the options.
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        _options: &DeserializeOptions,
+    ) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Self::deserialize(buffer)
+    }
+    /// Deserializes the buffer, which must contain exactly one packet,
+    /// re-serializes the result and checks that it matches the input
+    /// byte by byte. This detects fields which are silently lost during
+    /// deserialization. Returns [`Error::RoundtripMismatch`] with the
+    /// offset of the first differing byte on mismatch.
+    fn verify_roundtrip(buf: &[u8]) -> Result<()>
+    where
+        Self: Sized,
+    {
+        let message = Self::deserialize(&mut Cursor::new(buf))?;
+
+        let mut buffer = [0u8; MAX_FRAME_LEN];
+        let len = message.serialized_len();
+        if len > buffer.len() {
+            return Err(Error::BufferTooSmall {
+                size: buffer.len(),
+                expected: len,
+            });
+        }
+        message.serialize(&mut Cursor::new(&mut buffer[..len]))?;
+
+        match buf.iter().zip(&buffer[..len]).position(|(a, b)| a != b) {
+            Some(offset) => Err(Error::RoundtripMismatch { offset }),
+            None if buf.len() != len => Err(Error::RoundtripMismatch {
+                offset: buf.len().min(len),
+            }),
+            None => Ok(()),
+        }
+    }
+    /// Serialize given object into a stack allocated array of `N` bytes,
+    /// e.g. `N = SmaInvLogout::LENGTH`. Returns the array and the number
+    /// of used bytes or [`Error::BufferTooSmall`] if the object does not
+    /// fit into the array.
+    fn serialize_into_array<const N: usize>(&self) -> Result<([u8; N], usize)>
+    where
+        Self: Sized,
+    {
+        let mut buffer = [0u8; N];
+        let len = self.serialized_len();
+        if len > N {
+            return Err(Error::BufferTooSmall {
+                size: N,
+                expected: len,
+            });
+        }
+        self.serialize(&mut Cursor::new(&mut buffer[..len]))?;
+
+        Ok((buffer, len))
+    }
+    /// Serialize given object into a newly allocated vector.
+    #[cfg(feature = "std")]
+    fn to_vec(&self) -> Result<Vec<u8>> {
+        let mut buffer = vec![0u8; self.serialized_len()];
+        self.serialize(&mut Cursor::new(&mut buffer[..]))?;
+        Ok(buffer)
+    }
+    /// Serialize given object and write it to the given sink.
+    /// Serialization errors are returned as
+    /// [`std::io::ErrorKind::InvalidInput`].
+    #[cfg(feature = "std")]
+    fn write_to(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
+        let buffer = self.to_vec().map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string())
+        })?;
+        w.write_all(&buffer)
+    }
+}
+
+/// Options to relax the strict default validation during deserialization.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeserializeOptions {
+    /// Accepted protocol versions in the common packet header.
+    pub versions: &'static [u16],
+    /// Accepts frames which end right after the payload without a footer,
+    /// e.g. captures reconstructed from logs.
+    pub lenient_footer: bool,
+    /// Stops decoding the OBIS values of an energymeter message at zero
+    /// padding after the last value instead of failing. Any other
+    /// unsupported OBIS ID is still rejected because the length of unknown
+    /// values can not be determined.
+    pub lenient_obis: bool,
+    /// Additional rules for energymeter OBIS IDs which are not supported
+    /// by the built-in tables. Built-in IDs take precedence.
+    pub obis_rules: &'static [ObisRule],
+}
+
+impl Default for DeserializeOptions {
+    fn default() -> Self {
+        Self {
+            versions: &[SmaPacketHeader::SMA_VERSION],
+            lenient_footer: false,
+            lenient_obis: false,
+            obis_rules: &[],
+        }
+    }
+}
+
+impl DeserializeOptions {
+    /// Returns the minimum length of a message with the given length
+    /// including the footer, which is optional in lenient footer mode.
+    pub(crate) fn min_len(&self, length: usize) -> usize {
+        if self.lenient_footer {
+            length - SmaPacketFooter::LENGTH
+        } else {
+            length
+        }
+    }
+}
+
+/// Raw fields of the common packet header of a frame,
+/// see [`AnySmaMessage::transport_info`](crate::AnySmaMessage::transport_info).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TransportInfo {
+    /// SMA speedwire protocol version.
+    pub version: u16,
+    /// Group ID.
+    pub group: u32,
+    /// Sub-protocol type ID.
+    pub protocol: u16,
+    /// Length of the data payload following the common header.
+    pub data_len: usize,
 }
 
 /// Common SMA speedwire packet header.
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) struct SmaPacketHeader {
     /// Length of the following data payload.
     pub data_len: usize,
+    /// SMA speedwire protocol version.
+    pub version: u16,
     /// Sub-protocol type ID.
     pub protocol: u16,
 }
 
+impl Default for SmaPacketHeader {
+    fn default() -> Self {
+        Self {
+            data_len: 0,
+            version: Self::SMA_VERSION,
+            protocol: 0,
+        }
+    }
+}
+
 impl SmaPacketHeader {
     /// Serialized length of the common packet header.
     pub const LENGTH: usize = 18;
     pub const SMA_FOURCC: u32 = 0x534D4100; // SMA\0
-    const START_TAG_LEN: usize = 4;
+    /// Length in bytes of the start tag data, i.e. the group ID.
+    /// This is independent of the total header length [`Self::LENGTH`].
+    const START_TAG_LEN: u16 = 4;
     const START_TAG: u16 = 0x02A0;
     const DEFAULT_GROUP: u32 = 1;
     /// SMA inverter sub-protocol ID.
     pub const SMA_PROTOCOL_INV: u16 = 0x6065;
     /// SMA energymeter sub-protocol ID.
     pub const SMA_PROTOCOL_EM: u16 = 0x6069;
-    const SMA_VERSION: u16 = 0x10;
+    /// Default SMA speedwire protocol version.
+    pub const SMA_VERSION: u16 = 0x10;
+
+    /// Reads the raw fields of the common packet header of a frame.
+    /// Only the FOURCC, start tag and data length are validated.
+    pub(crate) fn transport_info(buf: &[u8]) -> Result<TransportInfo> {
+        Self::read_raw(&mut Cursor::new(buf))
+    }
+
+    /// Reads the raw fields of the common packet header, see
+    /// [`Self::transport_info`].
+    fn read_raw(buffer: &mut Cursor<&[u8]>) -> Result<TransportInfo> {
+        buffer.check_remaining(Self::LENGTH)?;
+
+        let fourcc = buffer.read_u32::<BigEndian>();
+        if fourcc != Self::SMA_FOURCC {
+            return Err(Error::InvalidFourCC { fourcc });
+        }
+
+        let len = buffer.read_u16::<BigEndian>();
+        if len != Self::START_TAG_LEN {
+            return Err(Error::InvalidStartTagLen { len });
+        }
+
+        let tag = buffer.read_u16::<BigEndian>();
+        if tag != Self::START_TAG {
+            return Err(Error::InvalidStartTag { tag });
+        }
+
+        let group = buffer.read_u32::<BigEndian>();
+
+        let data_len = buffer.read_u16::<BigEndian>() as usize;
+        if data_len < 2 {
+            return Err(Error::InvalidDataLen {
+                len: data_len,
+                expected: 2,
+            });
+        }
+
+        Ok(TransportInfo {
+            version: buffer.read_u16::<BigEndian>(),
+            group,
+            protocol: buffer.read_u16::<BigEndian>(),
+            data_len: data_len - 2,
+        })
+    }
 
     pub fn check_protocol(&self, protocol: u16) -> Result<()> {
         if self.protocol != protocol {
@@ -74,82 +285,99 @@ impl SmaPacketHeader {
 }
 
 impl SmaSerde for SmaPacketHeader {
+    fn serialized_len(&self) -> usize {
+        Self::LENGTH
+    }
+
     fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
         buffer.check_remaining(Self::LENGTH)?;
 
         buffer.write_u32::<BigEndian>(Self::SMA_FOURCC);
-        // Length of the header in 32bit words without the protocol field.
-        buffer.write_u16::<BigEndian>((Self::LENGTH / 4) as u16);
+        // Length of the start tag data in bytes.
+        buffer.write_u16::<BigEndian>(Self::START_TAG_LEN);
         // Constant start tag value.
         buffer.write_u16::<BigEndian>(Self::START_TAG);
         // Default group ID.
         buffer.write_u32::<BigEndian>(Self::DEFAULT_GROUP);
         buffer.write_u16::<BigEndian>((self.data_len + 2) as u16);
-        // SMA speedwire version.
-        buffer.write_u16::<BigEndian>(Self::SMA_VERSION);
+        buffer.write_u16::<BigEndian>(self.version);
         buffer.write_u16::<BigEndian>(self.protocol);
 
         Ok(())
     }
 
     fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
-        buffer.check_remaining(Self::LENGTH)?;
-
-        let fourcc = buffer.read_u32::<BigEndian>();
-        if fourcc != Self::SMA_FOURCC {
-            return Err(Error::InvalidFourCC { fourcc });
-        }
-
-        let len = buffer.read_u16::<BigEndian>();
-        if len != (Self::START_TAG_LEN) as u16 {
-            return Err(Error::InvalidStartTagLen { len });
-        }
-
-        let tag = buffer.read_u16::<BigEndian>();
-        if tag != Self::START_TAG {
-            return Err(Error::InvalidStartTag { tag });
-        }
+        Self::deserialize_with(buffer, &DeserializeOptions::default())
+    }
 
-        let group = buffer.read_u32::<BigEndian>();
-        if group != Self::DEFAULT_GROUP {
-            return Err(Error::InvalidGroup { group });
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        let info = Self::read_raw(buffer)?;
+        if info.group != Self::DEFAULT_GROUP {
+            return Err(Error::InvalidGroup { group: info.group });
         }
-
-        let data_len = (buffer.read_u16::<BigEndian>() - 2) as usize;
-
-        let version = buffer.read_u16::<BigEndian>();
-        if version != Self::SMA_VERSION {
-            return Err(Error::UnsupportedVersion { version });
+        if !options.versions.contains(&info.version) {
+            return Err(Error::UnsupportedVersion {
+                version: info.version,
+            });
         }
 
-        let protocol = buffer.read_u16::<BigEndian>();
-
-        Ok(Self { data_len, protocol })
+        Ok(Self {
+            data_len: info.data_len,
+            version: info.version,
+            protocol: info.protocol,
+        })
     }
 }
 
 /// Footer with optional variable length zero padding at the and of an
 /// SMA packet.
-#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
-pub(crate) struct SmaPacketFooter {}
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SmaPacketFooter {
+    /// Number of zero padding bytes in the footer.
+    /// Some devices send a short 2 byte footer instead of the normal 4 bytes.
+    pub padding_len: usize,
+}
+
+impl Default for SmaPacketFooter {
+    fn default() -> Self {
+        Self {
+            padding_len: Self::LENGTH,
+        }
+    }
+}
 
 impl SmaPacketFooter {
     /// Serialized length of a short SMA speedwire packet footer.
     pub const LENGTH_SHORT: usize = 2;
     /// Serialized length of a normal SMA speedwire packet footer.
     pub const LENGTH: usize = 4;
+
+    /// Returns true if this is a short 2 byte footer.
+    pub fn is_short(&self) -> bool {
+        self.padding_len == Self::LENGTH_SHORT
+    }
 }
 
 impl SmaSerde for SmaPacketFooter {
+    fn serialized_len(&self) -> usize {
+        self.padding_len
+    }
+
     fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
-        buffer.check_remaining(Self::LENGTH)?;
-        buffer.write_u32::<BigEndian>(0);
+        buffer.check_remaining(self.padding_len)?;
+        for _ in 0..self.padding_len {
+            buffer.write_u8(0);
+        }
 
         Ok(())
     }
 
     fn deserialize(buffer: &mut Cursor<&[u8]>) -> Result<Self> {
         buffer.check_remaining(Self::LENGTH_SHORT)?;
+        let start = buffer.position();
 
         while buffer.remaining() >= Self::LENGTH {
             let padding = buffer.read_u32::<BigEndian>();
@@ -170,7 +398,52 @@ impl SmaSerde for SmaPacketFooter {
             return Err(Error::BufferNotConsumed { trailing });
         }
 
-        Ok(Self {})
+        Ok(Self {
+            padding_len: buffer.position() - start,
+        })
+    }
+
+    fn deserialize_with(
+        buffer: &mut Cursor<&[u8]>,
+        options: &DeserializeOptions,
+    ) -> Result<Self> {
+        if options.lenient_footer && buffer.remaining() == 0 {
+            return Ok(Self { padding_len: 0 });
+        }
+
+        Self::deserialize(buffer)
+    }
+}
+
+/// SMA Update System-ID of a device type.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SusyId(pub u16);
+
+impl From<u16> for SusyId {
+    fn from(susy_id: u16) -> Self {
+        Self(susy_id)
+    }
+}
+
+impl From<SusyId> for u16 {
+    fn from(susy_id: SusyId) -> Self {
+        susy_id.0
+    }
+}
+
+/// Serial number of a device.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Serial(pub u32);
+
+impl From<u32> for Serial {
+    fn from(serial: u32) -> Self {
+        Self(serial)
+    }
+}
+
+impl From<Serial> for u32 {
+    fn from(serial: Serial) -> Self {
+        serial.0
     }
 }
 
@@ -186,6 +459,26 @@ pub struct SmaEndpoint {
 impl SmaEndpoint {
     const LENGTH: usize = 6;
 
+    /// Creates an endpoint from a SUSy ID and serial number, e.g. as printed
+    /// on the device label. The distinct types prevent swapping both
+    /// arguments, e.g. `SmaEndpoint::new(SusyId(0x015D), Serial(1901439139))`.
+    pub fn new(susy_id: SusyId, serial: Serial) -> Self {
+        Self {
+            susy_id: susy_id.into(),
+            serial: serial.into(),
+        }
+    }
+
+    /// Returns true if this is the [`Self::broadcast`] endpoint.
+    pub fn is_broadcast(&self) -> bool {
+        *self == Self::broadcast()
+    }
+
+    /// Returns true if this is the [`Self::dummy`] endpoint.
+    pub fn is_dummy(&self) -> bool {
+        *self == Self::dummy()
+    }
+
     /// The libraries dummy SUSy ID and serial SMA endpoint.
     pub fn dummy() -> Self {
         Self {
@@ -203,7 +496,18 @@ impl SmaEndpoint {
     }
 }
 
+#[cfg(feature = "std")]
+impl std::fmt::Display for SmaEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.susy_id, self.serial)
+    }
+}
+
 impl SmaSerde for SmaEndpoint {
+    fn serialized_len(&self) -> usize {
+        Self::LENGTH
+    }
+
     fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
         buffer.check_remaining(Self::LENGTH)?;
         buffer.write_u16::<BigEndian>(self.susy_id);
@@ -226,11 +530,32 @@ impl SmaSerde for SmaEndpoint {
 mod tests {
     use super::*;
 
+    struct FixedPayload;
+
+    impl SmaSerde for FixedPayload {
+        fn serialize(&self, buffer: &mut Cursor<&mut [u8]>) -> Result<()> {
+            buffer.check_remaining(3)?;
+            buffer.write_u8(1);
+            buffer.write_u16::<BigEndian>(2);
+            Ok(())
+        }
+
+        fn deserialize(_buffer: &mut Cursor<&[u8]>) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+
+    #[test]
+    fn test_default_serialized_len() {
+        assert_eq!(3, FixedPayload.serialized_len());
+    }
+
     #[test]
     fn test_sma_packet_header_serialization() {
         let header = SmaPacketHeader {
             data_len: 8,
             protocol: SmaPacketHeader::SMA_PROTOCOL_EM,
+            ..Default::default()
         };
         let mut buffer = [0u8; SmaPacketHeader::LENGTH];
         let mut cursor = Cursor::new(&mut buffer[..]);
@@ -250,6 +575,7 @@ mod tests {
             0x60, 0x69,
         ];
         assert_eq!(SmaPacketHeader::LENGTH, cursor.position());
+        assert_eq!(header.serialized_len(), cursor.position());
         assert_eq!(expected, buffer);
     }
 
@@ -269,6 +595,7 @@ mod tests {
         let expected = SmaPacketHeader {
             data_len: 8,
             protocol: SmaPacketHeader::SMA_PROTOCOL_EM,
+            ..Default::default()
         };
 
         let mut cursor = Cursor::new(&serialized[..]);
@@ -281,6 +608,60 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sma_packet_header_start_tag_len() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00,
+            0x00, 0x05,
+            0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01,
+            0x00, 0x0A,
+            0x00, 0x10,
+            0x60, 0x69,
+        ];
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaPacketHeader::deserialize(&mut cursor) {
+            Err(Error::InvalidStartTagLen { len: 5 }) => (),
+            x => panic!("Deserialized invalid start tag length as {x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_sma_packet_header_version_deserialization() {
+        #[rustfmt::skip]
+        let serialized = [
+            0x53, 0x4D, 0x41, 0x00,
+            0x00, 0x04,
+            0x02, 0xA0,
+            0x00, 0x00, 0x00, 0x01,
+            0x00, 0x0A,
+            0x00, 0x0F,
+            0x60, 0x69,
+        ];
+
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaPacketHeader::deserialize(&mut cursor) {
+            Err(Error::UnsupportedVersion { version: 0x0F }) => (),
+            x => panic!("Deserialized unsupported version as {x:?}"),
+        }
+
+        let options = DeserializeOptions {
+            versions: &[SmaPacketHeader::SMA_VERSION, 0x0F],
+            ..Default::default()
+        };
+        let mut cursor = Cursor::new(&serialized[..]);
+        match SmaPacketHeader::deserialize_with(&mut cursor, &options) {
+            Err(e) => panic!("SmaPacketHeader deserialization failed: {e:?}"),
+            Ok(header) => {
+                assert_eq!(0x0F, header.version);
+                assert_eq!(8, header.data_len);
+                assert_eq!(SmaPacketHeader::LENGTH, cursor.position());
+            }
+        }
+    }
+
     #[test]
     fn test_sma_packet_footer_serialization() {
         let token = SmaPacketFooter::default();
@@ -292,6 +673,7 @@ mod tests {
         }
 
         assert_eq!(4, cursor.position());
+        assert_eq!(token.serialized_len(), cursor.position());
         assert_eq!([0u8; 4], buffer);
     }
 
@@ -300,24 +682,33 @@ mod tests {
         let buffer = [0u8; 4];
         let mut cursor = Cursor::new(&buffer[..]);
 
-        if let Err(e) = SmaPacketFooter::deserialize(&mut cursor) {
-            panic!("SmaPacketFooter deserialization failed: {e:?}");
+        match SmaPacketFooter::deserialize(&mut cursor) {
+            Err(e) => panic!("SmaPacketFooter deserialization failed: {e:?}"),
+            Ok(footer) => {
+                assert_eq!(4, footer.padding_len);
+                assert!(!footer.is_short());
+            }
         }
         assert_eq!(4, cursor.position());
 
         let buffer = [0u8; 2];
         let mut cursor = Cursor::new(&buffer[..]);
 
-        if let Err(e) = SmaPacketFooter::deserialize(&mut cursor) {
-            panic!("SmaPacketFooter deserialization failed: {e:?}");
+        match SmaPacketFooter::deserialize(&mut cursor) {
+            Err(e) => panic!("SmaPacketFooter deserialization failed: {e:?}"),
+            Ok(footer) => {
+                assert_eq!(2, footer.padding_len);
+                assert!(footer.is_short());
+            }
         }
         assert_eq!(2, cursor.position());
 
         let buffer = [0u8; 12];
         let mut cursor = Cursor::new(&buffer[..]);
 
-        if let Err(e) = SmaPacketFooter::deserialize(&mut cursor) {
-            panic!("SmaPacketFooter deserialization failed: {e:?}");
+        match SmaPacketFooter::deserialize(&mut cursor) {
+            Err(e) => panic!("SmaPacketFooter deserialization failed: {e:?}"),
+            Ok(footer) => assert_eq!(12, footer.padding_len),
         }
         assert_eq!(12, cursor.position());
     }
@@ -341,6 +732,7 @@ mod tests {
             0xDE, 0xAD, 0xBE, 0xEF,
         ];
         assert_eq!(SmaEndpoint::LENGTH, cursor.position());
+        assert_eq!(endpoint.serialized_len(), cursor.position());
         assert_eq!(expected, buffer);
     }
 
@@ -366,4 +758,42 @@ mod tests {
             }
         };
     }
+
+    #[test]
+    fn test_sma_endpoint_sentinels() {
+        let endpoint = SmaEndpoint::new(SusyId(0x5678), Serial(0xABCDABCE));
+        assert_eq!(0x5678, endpoint.susy_id);
+        assert_eq!(0xABCDABCE, endpoint.serial);
+        assert!(!endpoint.is_broadcast());
+        assert!(!endpoint.is_dummy());
+
+        assert!(
+            SmaEndpoint::new(SusyId(0xFFFF), Serial(0xFFFFFFFF)).is_broadcast()
+        );
+        assert!(!SmaEndpoint::new(SusyId(0xFFFF), Serial(0xABCDABCE))
+            .is_broadcast());
+        assert!(!SmaEndpoint::broadcast().is_dummy());
+
+        assert!(SmaEndpoint::new(SusyId(0xDEAD), Serial(0xDEADBEEF)).is_dummy());
+        assert!(
+            !SmaEndpoint::new(SusyId(0xDEAD), Serial(0xABCDABCE)).is_dummy()
+        );
+        assert!(!SmaEndpoint::dummy().is_broadcast());
+    }
+
+    #[test]
+    fn test_sma_endpoint_newtypes() {
+        let susy_id = SusyId::from(0x015D);
+        let serial: Serial = 1901439139.into();
+        let endpoint = SmaEndpoint::new(susy_id, serial);
+        assert_eq!(
+            SmaEndpoint {
+                susy_id: 0x015D,
+                serial: 1901439139,
+            },
+            endpoint
+        );
+        assert_eq!(0x015D, u16::from(susy_id));
+        assert_eq!(1901439139, u32::from(serial));
+    }
 }
@@ -0,0 +1,231 @@
+/******************************************************************************\
+    sma-proto - A SMA Speedwire protocol library
+    Copyright (C) 2024 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+//! Parsing of back-to-back frames from a byte stream, e.g. a capture file.
+
+use super::{
+    packet::SmaPacketHeader, AnySmaMessage, Error, Result, SmaPacketFooter,
+};
+use std::io::{ErrorKind, Read};
+
+/// Parses concatenated frames from a reader, e.g. a file of raw frames
+/// dumped back-to-back by a capture tool. The length of each frame is
+/// taken from its common header and exactly that many bytes are read
+/// before the frame is decoded, so frames with short footers are not
+/// supported. Junk bytes which do not start with the SMA FOURCC are
+/// skipped up to the next `SMA\0` sequence. The iterator ends at the end
+/// of the stream or after the first I/O error, a truncated last frame is
+/// reported as [`Error::BufferTooSmall`].
+pub fn parse_stream(
+    reader: impl Read,
+) -> impl Iterator<Item = Result<AnySmaMessage>> {
+    FrameStream {
+        reader,
+        buffer: Vec::new(),
+        done: false,
+    }
+}
+
+/// Iterator state of [`parse_stream`].
+struct FrameStream<R> {
+    reader: R,
+    /// Bytes read from the stream which were not consumed yet.
+    buffer: Vec<u8>,
+    /// Set after the end of the stream or an I/O error.
+    done: bool,
+}
+
+impl<R: Read> FrameStream<R> {
+    /// Reads from the stream until the buffer contains `len` bytes.
+    /// Returns `Ok(false)` if the stream ended before.
+    fn fill(&mut self, len: usize) -> Result<bool> {
+        let mut pos = self.buffer.len();
+        if pos >= len {
+            return Ok(true);
+        }
+
+        self.buffer.resize(len, 0);
+        while pos < len {
+            match self.reader.read(&mut self.buffer[pos..]) {
+                Ok(0) => break,
+                Ok(count) => pos += count,
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => {
+                    self.buffer.truncate(pos);
+                    return Err(Error::Io { kind: e.kind() });
+                }
+            }
+        }
+        self.buffer.truncate(pos);
+
+        Ok(pos == len)
+    }
+
+    /// Drops leading bytes up to the next possible start of a frame.
+    /// A trailing partial FOURCC is kept until more data is available.
+    fn resync(&mut self) {
+        let fourcc = SmaPacketHeader::SMA_FOURCC.to_be_bytes();
+        let start = (1..self.buffer.len())
+            .find(|&i| {
+                let tail = &self.buffer[i..];
+                let len = tail.len().min(fourcc.len());
+                tail[..len] == fourcc[..len]
+            })
+            .unwrap_or(self.buffer.len());
+        self.buffer.drain(..start);
+    }
+
+    fn next_frame(&mut self) -> Option<Result<AnySmaMessage>> {
+        loop {
+            match self.fill(SmaPacketHeader::LENGTH) {
+                Ok(true) => (),
+                Ok(false) if self.buffer.is_empty() => return None,
+                Ok(false) => {
+                    self.resync();
+                    if self.buffer.is_empty() {
+                        return None;
+                    }
+                    let size = self.buffer.len();
+                    self.buffer.clear();
+                    return Some(Err(Error::BufferTooSmall {
+                        size,
+                        expected: SmaPacketHeader::LENGTH,
+                    }));
+                }
+                Err(e) => return Some(Err(e)),
+            }
+
+            let info = match AnySmaMessage::transport_info(&self.buffer) {
+                Ok(info) => info,
+                Err(Error::InvalidFourCC { .. }) => {
+                    self.resync();
+                    continue;
+                }
+                Err(e) => {
+                    self.resync();
+                    return Some(Err(e));
+                }
+            };
+
+            let len = SmaPacketHeader::LENGTH
+                + info.data_len
+                + SmaPacketFooter::LENGTH;
+            match self.fill(len) {
+                Ok(true) => (),
+                Ok(false) => {
+                    let size = self.buffer.len();
+                    self.buffer.clear();
+                    return Some(Err(Error::BufferTooSmall {
+                        size,
+                        expected: len,
+                    }));
+                }
+                Err(e) => return Some(Err(e)),
+            }
+
+            let frame: Vec<u8> = self.buffer.drain(..len).collect();
+            return Some(AnySmaMessage::from_bytes(&frame));
+        }
+    }
+}
+
+impl<R: Read> Iterator for FrameStream<R> {
+    type Item = Result<AnySmaMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let item = self.next_frame();
+        if matches!(item, None | Some(Err(Error::Io { .. }))) {
+            self.done = true;
+        }
+
+        item
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inverter::{SmaInvCounter, SmaInvLogout},
+        SmaEndpoint, SmaSerde,
+    };
+
+    fn logout(packet_id: u16) -> SmaInvLogout {
+        SmaInvLogout {
+            dst: SmaEndpoint::broadcast(),
+            src: SmaEndpoint::dummy(),
+            counters: SmaInvCounter {
+                packet_id,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn serialize(message: &SmaInvLogout) -> Vec<u8> {
+        match message.to_vec() {
+            Ok(x) => x,
+            Err(e) => panic!("SmaInvLogout serialization failed: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_stream_skips_junk() {
+        let first = logout(1);
+        let second = logout(2);
+
+        let mut stream = Vec::new();
+        stream.extend_from_slice(&[0x00, 0x53, 0x4D]);
+        stream.extend(serialize(&first));
+        stream.extend_from_slice(&[0xDE, 0x53, 0x4D, 0x41, 0xAD]);
+        stream.extend(serialize(&second));
+
+        let messages: Vec<_> = parse_stream(&stream[..]).collect();
+        assert_eq!(2, messages.len());
+        match &messages[0] {
+            Ok(AnySmaMessage::InvLogout(message)) => {
+                assert_eq!(first, *message)
+            }
+            x => panic!("Unexpected first message {x:?}"),
+        }
+        match &messages[1] {
+            Ok(AnySmaMessage::InvLogout(message)) => {
+                assert_eq!(second, *message)
+            }
+            x => panic!("Unexpected second message {x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_stream_truncated_frame() {
+        let buffer = serialize(&logout(1));
+        let len = buffer.len();
+
+        let messages: Vec<_> = parse_stream(&buffer[..len - 3]).collect();
+        match &messages[..] {
+            [Err(Error::BufferTooSmall { size, expected })] => {
+                assert_eq!(len - 3, *size);
+                assert_eq!(len, *expected);
+            }
+            x => panic!("Unexpected result {x:?}"),
+        }
+    }
+}
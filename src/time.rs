@@ -0,0 +1,48 @@
+/******************************************************************************\
+    sma-proto - A SMA Speedwire protocol library
+    Copyright (C) 2024 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+
+//! Time abstraction for filling message timestamps without std.
+
+#[cfg(not(feature = "std"))]
+use core::result::Result;
+
+/// Source of the current wall clock time.
+pub trait TimeSource {
+    /// Error returned if the current time is not available.
+    type Error;
+
+    /// Returns the current time as seconds since the Unix epoch.
+    fn now_unix_secs(&self) -> Result<u32, Self::Error>;
+}
+
+/// [`TimeSource`] backed by the operating system clock.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SystemTimeSource;
+
+#[cfg(feature = "std")]
+impl TimeSource for SystemTimeSource {
+    type Error = std::time::SystemTimeError;
+
+    /// Returns the system time or an error if the clock is before the epoch.
+    fn now_unix_secs(&self) -> Result<u32, Self::Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?;
+        Ok(now.as_secs() as u32)
+    }
+}
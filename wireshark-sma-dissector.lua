@@ -52,6 +52,8 @@ inv_login_timeout =
     ProtoField.uint32("sma.inv_login_timeout", "Timeout", base.DEC)
 inv_login_timestamp =
     ProtoField.uint32("sma.inv_login_timestamp", "Timestamp", base.DEC)
+inv_login_status =
+    ProtoField.uint32("sma.inv_login_status", "Status", base.HEX)
 inv_login_password =
     ProtoField.bytes("sma.inv_login_password", "Encoded Password", base.NONE)
 
@@ -96,6 +98,7 @@ sma_protocol.fields = {
     inv_login_user_group,
     inv_login_timeout,
     inv_login_timestamp,
+    inv_login_status,
     inv_login_password,
     inv_daydata_start_time,
     inv_daydata_end_time,
@@ -305,7 +308,7 @@ function sma_protocol.dissector(buffer, pinfo, tree)
             logintree:add_le(inv_login_user_group, buffer(46, 4))
             logintree:add_le(inv_login_timeout, buffer(50, 4))
             logintree:add_le(inv_login_timestamp, buffer(54, 4))
-            logintree:add(inv_padding, buffer(58, 4))
+            logintree:add_le(inv_login_status, buffer(58, 4))
             if dlen - 46 >= 12 then
                 logintree:add(inv_login_password, buffer(62, 12))
             end